@@ -0,0 +1,256 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use log::info;
+use serde_json::{json, Value};
+
+use crate::{compress::read_json, markdown::date, people::csv_field, ValueHelper};
+
+// the exceptions schoology marks grades with, missing ones count as zero
+const EXCUSED: i64 = 1;
+const INCOMPLETE: i64 = 2;
+const MISSING: i64 = 3;
+
+struct Grade {
+    assignment_id: String,
+    category_id: Option<String>,
+    period_title: String,
+    earned: f64,
+    possible: f64,
+    timestamp: i64,
+}
+
+fn number(value: &Value, key: &str) -> Option<f64> {
+    value.get_string_lossy(key)?.trim().parse().ok()
+}
+
+// unweighted 4 point scale
+fn grade_points(percentage: f64) -> f64 {
+    match percentage {
+        x if x >= 90.0 => 4.0,
+        x if x >= 80.0 => 3.0,
+        x if x >= 70.0 => 2.0,
+        x if x >= 60.0 => 1.0,
+        _ => 0.0,
+    }
+}
+
+fn round(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
+
+// weighted by category when the course has category weights, by points otherwise
+fn percentage(grades: &[&Grade], weights: &HashMap<String, f64>) -> Option<f64> {
+    let mut totals = HashMap::<Option<&str>, (f64, f64)>::new();
+    for grade in grades {
+        let total = totals.entry(grade.category_id.as_deref()).or_default();
+        total.0 += grade.earned;
+        total.1 += grade.possible;
+    }
+    let (mut score, mut weight_sum) = (0.0, 0.0);
+    for (category, (earned, possible)) in totals {
+        if possible <= 0.0 {
+            continue;
+        }
+        let weight = match weights.is_empty() {
+            true => possible,
+            false => category
+                .and_then(|x| weights.get(x))
+                .copied()
+                .unwrap_or_default(),
+        };
+        score += weight * earned / possible;
+        weight_sum += weight;
+    }
+    (weight_sum > 0.0).then(|| round(score / weight_sum * 100.0))
+}
+
+fn course_grades(section: &Value) -> Vec<Grade> {
+    let mut grades = Vec::new();
+    for period in section.get_array("period").unwrap_or_default() {
+        let period_title = period
+            .get_string("period_title")
+            .or_else(|| period.get_string_lossy("period_id"))
+            .unwrap_or_default();
+        for grade in period.get_array("assignment").unwrap_or_default() {
+            let (Some(assignment_id), Some(possible)) = (
+                grade.get_string_lossy("assignment_id"),
+                number(&grade, "max_points"),
+            ) else {
+                continue;
+            };
+            let earned = match grade.get_int("exception") {
+                Some(EXCUSED | INCOMPLETE) => continue,
+                Some(MISSING) => 0.0,
+                _ => match number(&grade, "grade") {
+                    Some(x) => x,
+                    None => continue,
+                },
+            };
+            grades.push(Grade {
+                assignment_id,
+                category_id: grade.get_string_lossy("category_id"),
+                period_title: period_title.clone(),
+                earned,
+                possible,
+                timestamp: grade.get_int("timestamp").unwrap_or_default(),
+            });
+        }
+    }
+    grades.sort_by_key(|x| x.timestamp);
+    grades
+}
+
+fn course_report(course: &Value, grades_info: &Value, csv: &mut String) -> Option<Value> {
+    let course_id = course.get_string_lossy("id")?;
+    let title = course.get_string("course_title").unwrap_or_default();
+    let section = grades_info
+        .get_array("section")
+        .unwrap_or_default()
+        .into_iter()
+        .find(|x| {
+            x.get_string_lossy("section_id")
+                .is_none_or(|x| x == course_id)
+        })?;
+    let weights = section
+        .get_array("grading_category")
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|x| Some((x.get_string_lossy("id")?, number(&x, "weight")?)))
+        .filter(|(_, weight)| *weight > 0.0)
+        .collect::<HashMap<_, _>>();
+    let grades = course_grades(&section);
+    if grades.is_empty() {
+        return None;
+    }
+
+    // the grade after every graded assignment, in the order they were graded
+    let mut trend = Vec::new();
+    for (idx, grade) in grades.iter().enumerate() {
+        let graded = grades[..=idx].iter().collect::<Vec<_>>();
+        let Some(running) = percentage(&graded, &weights) else {
+            continue;
+        };
+        let fields = [
+            course_id.clone(),
+            title.clone(),
+            grade.period_title.clone(),
+            date(Some(grade.timestamp))
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            grade.assignment_id.clone(),
+            grade.earned.to_string(),
+            grade.possible.to_string(),
+            running.to_string(),
+        ];
+        *csv += &fields.map(|x| csv_field(&x)).join(",");
+        csv.push('\n');
+        trend.push(json!({
+            "graded": date(Some(grade.timestamp)),
+            "assignment_id": grade.assignment_id,
+            "points_earned": grade.earned,
+            "points_possible": grade.possible,
+            "percentage": running,
+        }));
+    }
+
+    let final_grades = section
+        .get_array("final_grade")
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|x| Some((x.get_string_lossy("period_id")?, x.get("grade")?.clone())))
+        .collect::<HashMap<_, _>>();
+    let periods = section
+        .get_array("period")
+        .unwrap_or_default()
+        .into_iter()
+        .map(|period| {
+            let period_title = period
+                .get_string("period_title")
+                .or_else(|| period.get_string_lossy("period_id"))
+                .unwrap_or_default();
+            let graded = grades
+                .iter()
+                .filter(|x| x.period_title == period_title)
+                .collect::<Vec<_>>();
+            json!({
+                "period_id": period.get("period_id"),
+                "period_title": period_title,
+                "assignments": graded.len(),
+                "percentage": percentage(&graded, &weights),
+                "final_grade": period
+                    .get_string_lossy("period_id")
+                    .and_then(|x| final_grades.get(&x)),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let overall = percentage(&grades.iter().collect::<Vec<_>>(), &weights)?;
+    Some(json!({
+        "course_id": course_id,
+        "course_title": title,
+        "section_title": course.get("section_title"),
+        "weighted_by_category": !weights.is_empty(),
+        "assignments": grades.len(),
+        "percentage": overall,
+        "grade_points": grade_points(overall),
+        "periods": periods,
+        "trend": trend,
+    }))
+}
+
+fn write_report(export_dir: &Path, write_csv: bool) -> anyhow::Result<()> {
+    let courses = read_json(&export_dir.join("courses").join("info.json"))
+        .and_then(|x| x.get_array("section"))
+        .unwrap_or_default();
+    let mut csv = String::from(
+        "course_id,course,grading_period,graded,assignment_id,points_earned,points_possible,percentage\n",
+    );
+    let reports = courses
+        .iter()
+        .filter_map(|course| {
+            let grades_info = read_json(
+                &export_dir
+                    .join("courses")
+                    .join(course.get_string_lossy("id")?)
+                    .join("grades.json"),
+            )?;
+            course_report(course, &grades_info, &mut csv)
+        })
+        .collect::<Vec<_>>();
+
+    // every course counts the same, however many points it hands out
+    let gpa = (!reports.is_empty()).then(|| {
+        round(
+            reports
+                .iter()
+                .filter_map(|x| x["grade_points"].as_f64())
+                .sum::<f64>()
+                / reports.len() as f64,
+        )
+    });
+    info!("writing grade report of {} courses", reports.len());
+    let report = json!({
+        "generated": humantime::format_rfc3339_seconds(std::time::SystemTime::now()).to_string(),
+        "courses": reports,
+        "gpa": gpa,
+    });
+    let report_path = export_dir.join("report.json");
+    std::fs::write(&report_path, serde_json::to_string_pretty(&report)?)
+        .with_context(|| format!("failed to write {:?}", report_path))?;
+    if write_csv {
+        let csv_path = export_dir.join("grade_trends.csv");
+        std::fs::write(&csv_path, csv)
+            .with_context(|| format!("failed to write {:?}", csv_path))?;
+    }
+    Ok(())
+}
+
+// grade trends of every course of a finished export, from the grades saved with each course
+pub async fn grade_report(export_dir: PathBuf, write_csv: bool) -> anyhow::Result<()> {
+    tokio::task::spawn_blocking(move || write_report(&export_dir, write_csv)).await?
+}
@@ -0,0 +1,459 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use log::info;
+use serde_json::Value;
+
+const NAME_KEYS: &[&str] = &[
+    "name_display",
+    "name_first",
+    "name_first_preferred",
+    "name_middle",
+    "name_last",
+    "name_title",
+    "username",
+];
+const EMAIL_KEYS: &[&str] = &["primary_email", "email"];
+const PICTURE_KEYS: &[&str] = &["picture_url"];
+// keys whose values are always user ids, "id" only is one on objects describing a user
+const USER_ID_KEYS: &[&str] = &["uid", "author_id", "created_by", "user_id"];
+// lists of user ids, comma separated or as arrays
+const USER_LIST_KEYS: &[&str] = &["child_uids", "recipient_ids"];
+// directories of the export that are named after the uid of a user
+const USER_DIRS: &[&str] = &["users", "children"];
+const TEXT_EXTENSIONS: &[&str] = &["html", "md", "csv", "txt"];
+// shorter names would replace parts of ordinary words
+const MIN_NAME_LEN: usize = 3;
+
+fn id_string(value: &Value) -> Option<String> {
+    value
+        .as_i64()
+        .map(|x| x.to_string())
+        .or(value.as_str().map(|x| x.to_string()))
+        .filter(|x| !x.is_empty() && x != "0")
+}
+
+fn user_key(object: &serde_json::Map<String, Value>) -> Option<String> {
+    let has_name = NAME_KEYS.iter().any(|x| object.contains_key(*x));
+    let id = ["uid", "author_id"]
+        .iter()
+        .chain(has_name.then_some(&"id"))
+        .find_map(|key| id_string(object.get(*key)?));
+    id.or(object
+        .get("name_display")
+        .and_then(|x| x.as_str())
+        .map(|x| x.to_string()))
+}
+
+fn is_uid(text: &str) -> bool {
+    !text.is_empty() && text.bytes().all(|x| x.is_ascii_digit())
+}
+
+// users are numbered in the order they are found, the numbers can't be traced back to their uids
+#[derive(Default)]
+struct Pseudonyms {
+    numbers: HashMap<String, usize>,
+    // names and emails found anywhere in the export, replaced in free text too
+    replacements: HashMap<String, String>,
+}
+
+impl Pseudonyms {
+    fn number(&mut self, key: &str) -> usize {
+        let next = self.numbers.len() + 1;
+        *self.numbers.entry(key.to_string()).or_insert(next)
+    }
+
+    fn pseudonym(&self, key: &str) -> String {
+        match self.numbers.get(key) {
+            Some(number) => format!("User {number}"),
+            None => "User".to_string(),
+        }
+    }
+
+    // the number of a user takes the place of the uid, in the type the uid had
+    fn user_id(&self, value: &Value) -> Option<Value> {
+        let number = *self.numbers.get(&id_string(value)?)?;
+        Some(match value {
+            Value::String(_) => number.to_string().into(),
+            _ => number.into(),
+        })
+    }
+
+    fn collect(&mut self, value: &Value) {
+        match value {
+            Value::Object(object) => {
+                let has_identity = NAME_KEYS
+                    .iter()
+                    .chain(EMAIL_KEYS)
+                    .any(|x| object.contains_key(*x));
+                if let Some(key) = user_key(object).filter(|_| has_identity) {
+                    self.number(&key);
+                    let pseudonym = self.pseudonym(&key);
+                    let field = |key: &str| {
+                        object
+                            .get(key)
+                            .and_then(|x| x.as_str())
+                            .map(|x| x.trim().to_string())
+                            .filter(|x| x.len() >= MIN_NAME_LEN)
+                    };
+                    for key in NAME_KEYS {
+                        if let Some(name) = field(key) {
+                            self.replacements.insert(name, pseudonym.clone());
+                        }
+                    }
+                    if let (Some(first), Some(last)) = (field("name_first"), field("name_last")) {
+                        self.replacements
+                            .insert(format!("{first} {last}"), pseudonym.clone());
+                    }
+                    for key in EMAIL_KEYS {
+                        if let Some(email) = field(key) {
+                            self.replacements.insert(email, email_of(&pseudonym));
+                        }
+                    }
+                }
+                for key in USER_ID_KEYS {
+                    if let Some(uid) = object.get(*key).and_then(id_string) {
+                        self.number(&uid);
+                    }
+                }
+                for key in USER_LIST_KEYS {
+                    match object.get(*key) {
+                        Some(Value::String(uids)) => {
+                            for uid in uids.split(',').map(|x| x.trim()).filter(|x| is_uid(x)) {
+                                self.number(uid);
+                            }
+                        }
+                        Some(Value::Array(uids)) => {
+                            for uid in uids.iter().filter_map(id_string) {
+                                self.number(&uid);
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                for value in object.values() {
+                    self.collect(value);
+                }
+            }
+            Value::Array(array) => {
+                for value in array {
+                    self.collect(value);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // users/<uid> and children/<uid> in the paths and links of the export
+    fn replace_paths(&self, text: &str) -> String {
+        let mut replaced = String::with_capacity(text.len());
+        let mut rest = text;
+        'outer: while !rest.is_empty() {
+            for dir in USER_DIRS {
+                let prefix = format!("{dir}/");
+                if !rest.starts_with(&prefix) {
+                    continue;
+                }
+                let digits = rest[prefix.len()..]
+                    .find(|x: char| !x.is_ascii_digit())
+                    .unwrap_or(rest.len() - prefix.len());
+                let uid = &rest[prefix.len()..prefix.len() + digits];
+                let ends_segment = !rest[prefix.len() + digits..]
+                    .starts_with(|x: char| x.is_alphanumeric() || x == '_');
+                if let Some(number) = self.numbers.get(uid).filter(|_| ends_segment) {
+                    replaced += &format!("{prefix}{number}");
+                    rest = &rest[prefix.len() + digits..];
+                    continue 'outer;
+                }
+            }
+            let next = rest.chars().next().map_or(1, |x| x.len_utf8());
+            replaced += &rest[..next];
+            rest = &rest[next..];
+        }
+        replaced
+    }
+
+    fn replace_text(&self, text: &str, names: &[(&String, &String)]) -> String {
+        let mut text = self.replace_paths(text);
+        for (name, pseudonym) in names {
+            if text.contains(name.as_str()) {
+                text = text.replace(name.as_str(), pseudonym);
+            }
+        }
+        text
+    }
+
+    // the columns named like user id keys hold uids, e.g. in members.csv
+    fn replace_csv(&self, csv: &str, names: &[(&String, &String)]) -> String {
+        let mut lines = csv.split_inclusive('\n');
+        let Some(header) = lines.next() else {
+            return String::new();
+        };
+        let uid_columns = header
+            .trim_end()
+            .split(',')
+            .map(|x| USER_ID_KEYS.contains(&x))
+            .collect::<Vec<_>>();
+        let mut replaced = header.to_string();
+        for line in lines {
+            let line = line
+                .split(',')
+                .enumerate()
+                .map(|(i, field)| {
+                    let uid = field.trim_end();
+                    match self.numbers.get(uid) {
+                        Some(number) if uid_columns.get(i) == Some(&true) => {
+                            format!("{number}{}", &field[uid.len()..])
+                        }
+                        _ => field.to_string(),
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(",");
+            replaced += &line;
+        }
+        self.replace_text(&replaced, names)
+    }
+
+    fn rewrite(&self, value: &mut Value, names: &[(&String, &String)]) {
+        match value {
+            Value::Object(object) => {
+                if let Some(key) = user_key(object) {
+                    let pseudonym = self.pseudonym(&key);
+                    for key in NAME_KEYS {
+                        if let Some(name) = object.get_mut(*key) {
+                            *name = match *key {
+                                "name_display" | "name_first" | "username" => {
+                                    pseudonym.clone().into()
+                                }
+                                _ => String::new().into(),
+                            };
+                        }
+                    }
+                    for key in EMAIL_KEYS {
+                        if let Some(email) = object.get_mut(*key) {
+                            *email = email_of(&pseudonym).into();
+                        }
+                    }
+                    let has_name = NAME_KEYS.iter().any(|x| object.contains_key(*x));
+                    if let Some(id) = object.get_mut("id").filter(|_| has_name) {
+                        if let Some(number) = self.user_id(id) {
+                            *id = number;
+                        }
+                    }
+                }
+                for key in USER_ID_KEYS {
+                    if let Some(uid) = object.get_mut(*key) {
+                        if let Some(number) = self.user_id(uid) {
+                            *uid = number;
+                        }
+                    }
+                }
+                for key in USER_LIST_KEYS {
+                    match object.get_mut(*key) {
+                        Some(Value::String(uids)) => {
+                            *uids = uids
+                                .split(',')
+                                .map(|x| match self.numbers.get(x.trim()) {
+                                    Some(number) => number.to_string(),
+                                    None => x.to_string(),
+                                })
+                                .collect::<Vec<_>>()
+                                .join(",");
+                        }
+                        Some(Value::Array(uids)) => {
+                            for uid in uids {
+                                if let Some(number) = self.user_id(uid) {
+                                    *uid = number;
+                                }
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                for key in PICTURE_KEYS {
+                    if let Some(picture) = object.get_mut(*key) {
+                        *picture = Value::Null;
+                    }
+                }
+                for value in object.values_mut() {
+                    self.rewrite(value, names);
+                }
+            }
+            Value::Array(array) => {
+                for value in array {
+                    self.rewrite(value, names);
+                }
+            }
+            Value::String(text) => *text = self.replace_text(text, names),
+            _ => {}
+        }
+    }
+}
+
+// users/index.json is keyed by uid
+fn rename_keys(document: &mut Value, numbers: &HashMap<String, usize>) {
+    if let Value::Object(object) = document {
+        *object = std::mem::take(object)
+            .into_iter()
+            .map(|(key, value)| match numbers.get(&key) {
+                Some(number) => (number.to_string(), value),
+                None => (key, value),
+            })
+            .collect();
+    }
+}
+
+fn email_of(pseudonym: &str) -> String {
+    format!(
+        "{}@example.invalid",
+        pseudonym.to_lowercase().replace(' ', ".")
+    )
+}
+
+fn export_files(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    let mut entries = std::fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+    // sorted, so the users are numbered the same way every time
+    entries.sort_by_key(|x| x.path());
+    for entry in entries {
+        if entry.file_type()?.is_dir() {
+            export_files(&entry.path(), files)?;
+        } else {
+            files.push(entry.path());
+        }
+    }
+    Ok(())
+}
+
+// the directories of users with a matching name, deepest first so renaming one doesn't move the others
+fn user_dirs(
+    dir: &Path,
+    is_name: &dyn Fn(&str) -> bool,
+    dirs: &mut Vec<PathBuf>,
+) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        user_dirs(&entry.path(), is_name, dirs)?;
+        let is_user_dir = dir
+            .file_name()
+            .is_some_and(|x| USER_DIRS.iter().any(|y| x == *y))
+            && is_name(&entry.file_name().to_string_lossy());
+        if is_user_dir {
+            dirs.push(entry.path());
+        }
+    }
+    Ok(())
+}
+
+// through temporary names, the number of one user can be the uid of another
+fn rename_user_dirs(export_dir: &Path, numbers: &HashMap<String, usize>) -> anyhow::Result<()> {
+    const TEMP_PREFIX: &str = "anonymized_";
+    for (is_name, name) in [
+        (
+            &is_uid as &dyn Fn(&str) -> bool,
+            &(|x: &str| format!("{TEMP_PREFIX}{}", numbers[x])) as &dyn Fn(&str) -> String,
+        ),
+        (&|x: &str| x.starts_with(TEMP_PREFIX), &|x: &str| {
+            x[TEMP_PREFIX.len()..].to_string()
+        }),
+    ] {
+        let mut dirs = Vec::new();
+        user_dirs(export_dir, is_name, &mut dirs).context("failed to list export")?;
+        for dir in dirs {
+            let renamed =
+                dir.with_file_name(name(&dir.file_name().unwrap_or_default().to_string_lossy()));
+            std::fs::rename(&dir, &renamed)
+                .with_context(|| format!("failed to rename {:?}", dir))?;
+        }
+    }
+    Ok(())
+}
+
+fn anonymize_files(export_dir: &Path) -> anyhow::Result<HashMap<String, String>> {
+    let mut files = Vec::new();
+    export_files(export_dir, &mut files).context("failed to list export")?;
+    let has_extension = |path: &Path, extensions: &[&str]| {
+        path.extension()
+            .is_some_and(|x| extensions.iter().any(|y| x == *y))
+    };
+
+    let mut pseudonyms = Pseudonyms::default();
+    let mut documents = Vec::new();
+    for path in files.iter().filter(|x| has_extension(x, &["json"])) {
+        let contents =
+            std::fs::read_to_string(path).with_context(|| format!("failed to read {:?}", path))?;
+        if let Ok(document) = serde_json::from_str::<Value>(&contents) {
+            pseudonyms.collect(&document);
+            documents.push(path);
+        }
+    }
+    let mut dirs = Vec::new();
+    user_dirs(export_dir, &is_uid, &mut dirs).context("failed to list export")?;
+    for dir in &dirs {
+        pseudonyms.number(&dir.file_name().unwrap_or_default().to_string_lossy());
+    }
+    // longer names first, so a full name isn't replaced one half at a time
+    let mut names = pseudonyms.replacements.iter().collect::<Vec<_>>();
+    names.sort_by_key(|(name, _)| std::cmp::Reverse(name.len()));
+    info!(
+        "anonymizing {} users with {} names and emails in {} files",
+        pseudonyms.numbers.len(),
+        names.len(),
+        files.len()
+    );
+
+    // read again instead of kept around, exports can be large
+    for path in documents {
+        let mut document = serde_json::from_str::<Value>(
+            &std::fs::read_to_string(path).with_context(|| format!("failed to read {:?}", path))?,
+        )?;
+        pseudonyms.rewrite(&mut document, &names);
+        if path.ends_with(Path::new("users").join("index.json")) {
+            rename_keys(&mut document, &pseudonyms.numbers);
+        }
+        std::fs::write(path, serde_json::to_string_pretty(&document)?)
+            .with_context(|| format!("failed to write {:?}", path))?;
+    }
+    for path in files.iter().filter(|x| has_extension(x, TEXT_EXTENSIONS)) {
+        let Ok(contents) = std::fs::read_to_string(path) else {
+            continue;
+        };
+        let contents = match has_extension(path, &["csv"]) {
+            true => pseudonyms.replace_csv(&contents, &names),
+            false => pseudonyms.replace_text(&contents, &names),
+        };
+        std::fs::write(path, contents).with_context(|| format!("failed to write {:?}", path))?;
+    }
+    // users/self only holds the uid of the exported account
+    let self_path = export_dir.join("users").join("self");
+    if let Ok(uid) = std::fs::read_to_string(&self_path) {
+        if let Some(number) = pseudonyms.numbers.get(uid.trim()) {
+            std::fs::write(&self_path, number.to_string())
+                .with_context(|| format!("failed to write {:?}", self_path))?;
+        }
+    }
+    for path in files
+        .iter()
+        .filter(|x| x.file_name().is_some_and(|x| x == "user_image.png"))
+    {
+        std::fs::remove_file(path).with_context(|| format!("failed to remove {:?}", path))?;
+    }
+    rename_user_dirs(export_dir, &pseudonyms.numbers)?;
+    Ok(pseudonyms
+        .numbers
+        .into_iter()
+        .map(|(uid, number)| (uid, number.to_string()))
+        .collect())
+}
+
+// replaces the names, emails, pictures and uids of every user in a finished export,
+// returns the number each uid was replaced with
+pub async fn anonymize(export_dir: PathBuf) -> anyhow::Result<HashMap<String, String>> {
+    tokio::task::spawn_blocking(move || anonymize_files(&export_dir)).await?
+}
@@ -1,19 +1,28 @@
-use std::time::SystemTime;
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
 
-use anyhow::Context;
-use reqwest::{header::HeaderValue, Body, Method, Request, Url};
+use anyhow::{anyhow, Context};
+use log::{debug, warn};
+use reqwest::{
+    header::{HeaderMap, HeaderValue},
+    Method, Request, StatusCode, Url,
+};
 use reqwest_middleware::ClientWithMiddleware;
 use serde_json::{json, Value};
 use uuid::Uuid;
 
-use crate::{TokenInfo, ValueHelper};
+use crate::{error::ExportError, TokenInfo, ValueHelper, WebSession};
 
-fn generate_oauth_header(token_info: &TokenInfo) -> anyhow::Result<String> {
+pub fn generate_oauth_header(token_info: &TokenInfo) -> anyhow::Result<String> {
     let TokenInfo {
         client_token,
         client_secret,
         user_token,
         user_secret,
+        ..
     } = token_info;
     let user_token = user_token.as_ref().map(|x| x.as_str());
     let user_secret = user_secret.as_ref().map(|x| x.as_str());
@@ -25,16 +34,89 @@ fn generate_oauth_header(token_info: &TokenInfo) -> anyhow::Result<String> {
     Ok(format!("OAuth realm=\"Schoology API\",oauth_consumer_key=\"{}\",oauth_token=\"{}\",oauth_nonce=\"{}\",oauth_timestamp=\"{}\",oauth_signature_method=\"PLAINTEXT\",oauth_version=\"1.0\",oauth_signature=\"{}%26{}\"", client_token, user_token.unwrap_or(""), nonce, timestamp, client_secret, user_secret.unwrap_or("")))
 }
 
+const NOT_READY_RETRIES: u32 = 5;
+
+// a response read to the end, an empty body can only be told apart from a chunked one this way
+struct ApiResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Vec<u8>,
+}
+
+// some endpoints answer with 202 or an empty body while the content is still being prepared
+async fn execute_ready(
+    client: &ClientWithMiddleware,
+    request: Request,
+) -> anyhow::Result<ApiResponse> {
+    let url = request.url().to_string();
+    for retry in 0..=NOT_READY_RETRIES {
+        let attempt = request
+            .try_clone()
+            .ok_or_else(|| anyhow!("{:?} can't be retried", url))?;
+        let response = client.execute(attempt).await?;
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = response.bytes().await?.to_vec();
+        let not_ready = status == StatusCode::ACCEPTED
+            || (status == StatusCode::OK && body.iter().all(u8::is_ascii_whitespace));
+        if !not_ready {
+            return Ok(ApiResponse {
+                status,
+                headers,
+                body,
+            });
+        }
+        if retry == NOT_READY_RETRIES {
+            break;
+        }
+        let delay = Duration::from_secs(1 << retry);
+        debug!("{:?} is not ready yet, retrying in {:?}", url, delay);
+        tokio::time::sleep(delay).await;
+    }
+    Err(ExportError::NotReady {
+        url,
+        retries: NOT_READY_RETRIES,
+    }
+    .into())
+}
+
+// rejected tokens and exhausted rate limits fail every later request too, so they are never ignored
+fn check_status(url: &str, response: &ApiResponse) -> Result<(), ExportError> {
+    match response.status {
+        StatusCode::UNAUTHORIZED => Err(ExportError::Auth {
+            url: url.to_string(),
+            status: response.status,
+        }),
+        StatusCode::TOO_MANY_REQUESTS => Err(ExportError::RateLimited {
+            url: url.to_string(),
+            retry_after: response
+                .headers
+                .get("Retry-After")
+                .and_then(|x| x.to_str().ok())
+                .and_then(|x| x.parse().ok()),
+        }),
+        _ => Ok(()),
+    }
+}
+
+fn read_json(url: &str, response: ApiResponse) -> anyhow::Result<Value> {
+    check_status(url, &response)?;
+    Ok(
+        serde_json::from_slice(&response.body).map_err(|source| ExportError::Schema {
+            url: url.to_string(),
+            source,
+        })?,
+    )
+}
+
 pub async fn get(
     client: &ClientWithMiddleware,
     token_info: &TokenInfo,
     url: &str,
 ) -> anyhow::Result<Value> {
-    Ok(client
-        .execute(Request::get(url)?.into_schoology(token_info)?)
-        .await?
-        .json()
-        .await?)
+    let request = Request::get(&token_info.api_base, url)?.into_schoology(token_info)?;
+    let url = request.url().to_string();
+    read_json(&url, execute_ready(client, request).await?)
 }
 
 pub async fn get_raw(
@@ -42,15 +124,230 @@ pub async fn get_raw(
     token_info: &TokenInfo,
     url: &str,
 ) -> anyhow::Result<Value> {
-    Ok(client
-        .execute(Request::get_raw(url)?.into_schoology(token_info)?)
-        .await?
-        .json()
-        .await?)
+    let request = Request::get_raw(url)?.into_schoology(token_info)?;
+    read_json(url, execute_ready(client, request).await?)
+}
+
+pub async fn get_all_pages(
+    client: &ClientWithMiddleware,
+    token_info: &TokenInfo,
+    url: &str,
+    key: &str,
+) -> anyhow::Result<Vec<Value>> {
+    let mut items = Vec::new();
+    let mut page = get(client, token_info, url).await?;
+    loop {
+        items.extend(page.get_array(key).unwrap_or_default());
+        match page.get("links").and_then(|x| x.get_string("next")) {
+            Some(next_link) => page = get_raw(client, token_info, &next_link).await?,
+            None => break,
+        }
+    }
+    Ok(items)
+}
+
+// for endpoints that are disabled or hidden on some accounts
+async fn execute_optional(
+    client: &ClientWithMiddleware,
+    request: Request,
+) -> anyhow::Result<Option<Value>> {
+    let url = request.url().to_string();
+    let response = execute_ready(client, request).await?;
+    if response.status == StatusCode::TOO_MANY_REQUESTS {
+        check_status(&url, &response)?;
+    }
+    if response.status.is_client_error() {
+        debug!("{:?} is not available: {}", url, response.status);
+        return Ok(None);
+    }
+    if !response.status.is_success() {
+        return Err(ExportError::Status {
+            url,
+            status: response.status,
+        }
+        .into());
+    }
+    Ok(Some(read_json(&url, response)?))
+}
+
+pub async fn get_optional(
+    client: &ClientWithMiddleware,
+    token_info: &TokenInfo,
+    url: &str,
+) -> anyhow::Result<Option<Value>> {
+    execute_optional(
+        client,
+        Request::get(&token_info.api_base, url)?.into_schoology(token_info)?,
+    )
+    .await
+}
+
+pub async fn get_raw_optional(
+    client: &ClientWithMiddleware,
+    token_info: &TokenInfo,
+    url: &str,
+) -> anyhow::Result<Option<Value>> {
+    execute_optional(client, Request::get_raw(url)?.into_schoology(token_info)?).await
+}
+
+const MULTIGET_LIMIT: usize = 50;
+
+// bundles GET requests into /multiget calls, a failed request is returned as None
+pub async fn multiget(
+    client: &ClientWithMiddleware,
+    token_info: &TokenInfo,
+    urls: &[String],
+) -> anyhow::Result<Vec<Option<Value>>> {
+    let api_base = &token_info.api_base;
+    let api_path = Url::parse(api_base)?.path().to_string();
+    let mut results = Vec::with_capacity(urls.len());
+    for chunk in urls.chunks(MULTIGET_LIMIT) {
+        let body = json!({
+            "request": chunk
+                .iter()
+                .map(|x| format!("{api_path}{}", x.strip_prefix(api_base.as_str()).unwrap_or(x)))
+                .collect::<Vec<_>>(),
+        });
+        let mut request = Request::get(api_base, "multiget")?.into_schoology(token_info)?;
+        *request.method_mut() = Method::POST;
+        request
+            .headers_mut()
+            .insert("Content-Type", HeaderValue::from_static("application/json"));
+        *request.body_mut() = Some(serde_json::to_vec(&body)?.into());
+
+        let url = request.url().to_string();
+        let response = execute_ready(client, request).await?;
+        if !response.status.is_success() {
+            check_status(&url, &response)?;
+            return Err(ExportError::Status {
+                url,
+                status: response.status,
+            }
+            .into());
+        }
+        let responses = read_json(&url, response)?
+            .get_array("response")
+            .ok_or(ExportError::missing_field("multiget", "response"))?;
+        if responses.len() != chunk.len() {
+            return Err(anyhow!(
+                "multiget returned {} responses for {} requests",
+                responses.len(),
+                chunk.len()
+            ));
+        }
+        results.extend(responses.into_iter().map(|x| {
+            x.get_string_lossy("response_code")
+                .is_some_and(|x| x.starts_with('2'))
+                .then(|| x.get("body").cloned())
+                .flatten()
+        }));
+    }
+    Ok(results)
+}
+
+// responses fetched ahead of time through multiget, keyed by absolute url
+#[derive(Default)]
+pub struct Prefetched(Mutex<HashMap<String, Value>>);
+
+impl Prefetched {
+    pub async fn prefetch(
+        &self,
+        client: &ClientWithMiddleware,
+        token_info: &TokenInfo,
+        urls: impl IntoIterator<Item = String>,
+    ) {
+        let mut urls = urls
+            .into_iter()
+            // item locations are absolute, whatever the scheme of the api base
+            .map(|x| match Url::parse(&x) {
+                Ok(_) => x,
+                Err(_) => format!("{}{x}", token_info.api_base),
+            })
+            .collect::<Vec<_>>();
+        urls.sort();
+        urls.dedup();
+        {
+            let prefetched = self.0.lock().unwrap();
+            urls.retain(|x| !prefetched.contains_key(x));
+        }
+        // a single request is cheaper without the multiget overhead
+        if urls.len() < 2 {
+            return;
+        }
+
+        match multiget(client, token_info, &urls).await {
+            Ok(responses) => {
+                debug!("prefetched {} requests", urls.len());
+                let mut prefetched = self.0.lock().unwrap();
+                for (url, response) in urls.into_iter().zip(responses) {
+                    if let Some(response) = response {
+                        prefetched.insert(url, response);
+                    }
+                }
+            }
+            // the requests are retried one by one when they are needed
+            Err(err) => warn!("failed to prefetch {} requests: {:#}", urls.len(), err),
+        }
+    }
+
+    // like get_raw, but keeps the response around for a later get_raw
+    pub async fn peek(
+        &self,
+        client: &ClientWithMiddleware,
+        token_info: &TokenInfo,
+        url: &str,
+    ) -> anyhow::Result<Value> {
+        let prefetched = self.0.lock().unwrap().get(url).cloned();
+        if let Some(response) = prefetched {
+            return Ok(response);
+        }
+        let response = self.get_raw(client, token_info, url).await?;
+        self.0
+            .lock()
+            .unwrap()
+            .insert(url.to_string(), response.clone());
+        Ok(response)
+    }
+
+    pub async fn get(
+        &self,
+        client: &ClientWithMiddleware,
+        token_info: &TokenInfo,
+        url: &str,
+    ) -> anyhow::Result<Value> {
+        self.get_raw(client, token_info, &format!("{}{url}", token_info.api_base))
+            .await
+    }
+
+    pub async fn get_raw(
+        &self,
+        client: &ClientWithMiddleware,
+        token_info: &TokenInfo,
+        url: &str,
+    ) -> anyhow::Result<Value> {
+        let prefetched = self.0.lock().unwrap().remove(url);
+        if let Some(response) = prefetched {
+            return Ok(response);
+        }
+
+        let response =
+            execute_ready(client, Request::get_raw(url)?.into_schoology(token_info)?).await?;
+        if matches!(
+            response.status,
+            StatusCode::FORBIDDEN | StatusCode::NOT_FOUND
+        ) {
+            return Err(ExportError::Denied {
+                url: url.to_string(),
+                status: response.status,
+            }
+            .into());
+        }
+        read_json(url, response)
+    }
 }
 
 pub trait SchoologyRequestHelper {
-    fn get(url: &str) -> anyhow::Result<Self>
+    fn get(api_base: &str, url: &str) -> anyhow::Result<Self>
     where
         Self: Sized;
     fn get_raw(url: &str) -> anyhow::Result<Self>
@@ -59,11 +356,14 @@ pub trait SchoologyRequestHelper {
     fn into_schoology(self, token_info: &TokenInfo) -> anyhow::Result<Self>
     where
         Self: Sized;
+    fn into_web_session(self, web_session: &WebSession) -> anyhow::Result<Self>
+    where
+        Self: Sized;
 }
 
 impl SchoologyRequestHelper for Request {
-    fn get(url: &str) -> anyhow::Result<Self> {
-        Self::get_raw(&format!("https://api.schoology.com/v1/{url}"))
+    fn get(api_base: &str, url: &str) -> anyhow::Result<Self> {
+        Self::get_raw(&format!("{api_base}{url}"))
     }
 
     fn get_raw(url: &str) -> anyhow::Result<Self> {
@@ -78,6 +378,16 @@ impl SchoologyRequestHelper for Request {
         );
         self.headers_mut()
             .insert("Accept", HeaderValue::from_static("application/json"));
+        if let Some(run_as) = token_info.run_as {
+            self.headers_mut()
+                .insert("X-Schoology-Run-As", HeaderValue::from(run_as));
+        }
+        Ok(self)
+    }
+
+    fn into_web_session(mut self, web_session: &WebSession) -> anyhow::Result<Self> {
+        self.headers_mut()
+            .insert("Cookie", HeaderValue::from_str(&web_session.cookie)?);
         Ok(self)
     }
 }
@@ -0,0 +1,137 @@
+use std::{
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use async_trait::async_trait;
+use clap::ValueEnum;
+use flate2::{read::GzDecoder, write::GzEncoder};
+use serde_json::Value;
+
+use crate::storage::{Storage, StorageWriter};
+
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq)]
+pub enum JsonCompression {
+    Gzip,
+    Zstd,
+}
+
+const EXTENSIONS: &[(&str, JsonCompression)] = &[
+    ("gz", JsonCompression::Gzip),
+    ("zst", JsonCompression::Zstd),
+];
+
+// read by verify, fetch-attachments and the next sync, so they stay plain
+const PLAIN_FILES: &[&str] = &["manifest.json", "summary.json"];
+
+impl JsonCompression {
+    fn extension(self) -> &'static str {
+        match self {
+            JsonCompression::Gzip => "gz",
+            JsonCompression::Zstd => "zst",
+        }
+    }
+
+    fn compress(self, contents: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            JsonCompression::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(contents)?;
+                encoder.finish()
+            }
+            JsonCompression::Zstd => zstd::encode_all(contents, 0),
+        }
+    }
+
+    fn decompress(self, contents: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            JsonCompression::Gzip => {
+                let mut decompressed = Vec::new();
+                GzDecoder::new(contents).read_to_end(&mut decompressed)?;
+                Ok(decompressed)
+            }
+            JsonCompression::Zstd => zstd::decode_all(contents),
+        }
+    }
+}
+
+fn with_extension(path: &Path, extension: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(extension);
+    path.with_file_name(name)
+}
+
+// the json written by the export is compressed on the way to the storage, downloads are left as they are
+pub struct CompressedStorage {
+    inner: Arc<dyn Storage>,
+    compression: JsonCompression,
+}
+
+impl CompressedStorage {
+    pub fn new(inner: Arc<dyn Storage>, compression: JsonCompression) -> Self {
+        Self { inner, compression }
+    }
+}
+
+#[async_trait]
+impl Storage for CompressedStorage {
+    async fn create_dir_all(&self, path: &Path) -> anyhow::Result<()> {
+        self.inner.create_dir_all(path).await
+    }
+
+    async fn write(&self, path: &Path, contents: Vec<u8>) -> anyhow::Result<()> {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if !name.ends_with(".json") || PLAIN_FILES.contains(&name.as_ref()) {
+            return self.inner.write(path, contents).await;
+        }
+        let compression = self.compression;
+        let compressed =
+            tokio::task::spawn_blocking(move || compression.compress(&contents)).await??;
+        self.inner
+            .write(&with_extension(path, compression.extension()), compressed)
+            .await
+    }
+
+    async fn create(&self, path: &Path) -> anyhow::Result<StorageWriter> {
+        self.inner.create(path).await
+    }
+
+    fn size(&self, path: &Path) -> u64 {
+        self.inner.size(path)
+    }
+}
+
+// reads a file of an export whether or not it was written with --compress-json
+pub fn read_to_string(path: &Path) -> io::Result<String> {
+    match std::fs::read_to_string(path) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            for (extension, compression) in EXTENSIONS {
+                let Ok(contents) = std::fs::read(with_extension(path, extension)) else {
+                    continue;
+                };
+                return String::from_utf8(compression.decompress(&contents)?)
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err));
+            }
+            Err(err)
+        }
+        x => x,
+    }
+}
+
+pub fn read_json(path: &Path) -> Option<Value> {
+    serde_json::from_str(&read_to_string(path).ok()?).ok()
+}
+
+// the path a compressed json file of the export stands for, others are returned as they are
+pub fn uncompressed_path(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    EXTENSIONS
+        .iter()
+        .find_map(|(extension, _)| {
+            name.strip_suffix(&format!(".{extension}"))
+                .filter(|x| x.ends_with(".json"))
+        })
+        .map_or(path.to_path_buf(), |x| path.with_file_name(x))
+}
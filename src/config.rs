@@ -0,0 +1,250 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use serde::Deserialize;
+
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub domain: String,
+    // for deployments that don't use the public api host
+    pub api_base: Option<String>,
+    // can also come from the environment or the keychain
+    #[serde(default)]
+    pub app_token: String,
+    #[serde(default)]
+    pub app_secret: String,
+    pub user_token: Option<String>,
+    pub user_secret: Option<String>,
+    pub session_cookie: Option<String>,
+    // two-legged admin keys, exports every user of the school
+    #[serde(default)]
+    pub admin: bool,
+    pub output: Option<PathBuf>,
+    #[serde(default = "default_parallelism")]
+    pub parallelism: usize,
+    #[serde(default = "default_attachment_parallelism")]
+    pub attachment_parallelism: usize,
+    #[serde(default = "default_user_parallelism")]
+    pub user_parallelism: usize,
+    #[serde(default = "default_page_parallelism")]
+    pub page_parallelism: usize,
+    #[serde(default)]
+    pub profile: Profile,
+    #[serde(default)]
+    pub privacy: Privacy,
+    #[serde(default)]
+    pub content: Content,
+    #[serde(default)]
+    pub filters: Filters,
+    #[serde(default)]
+    pub retry: RetryConfig,
+    // more accounts of the same domain, each exported to its own directory
+    #[serde(default)]
+    pub accounts: Vec<Account>,
+    #[serde(default)]
+    pub parallel_accounts: bool,
+    // replaces the user agent sent with every request
+    pub user_agent: Option<String>,
+    // added to the default user agent so districts know who to reach
+    pub contact: Option<String>,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Account {
+    pub name: String,
+    pub user_token: Option<String>,
+    pub user_secret: Option<String>,
+    pub session_cookie: Option<String>,
+}
+
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Profile {
+    #[default]
+    Full,
+    // for parents exporting a young child's account
+    ChildSafe,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum OtherUsers {
+    All,
+    Teachers,
+    None,
+}
+
+#[derive(Deserialize, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Privacy {
+    pub other_users: Option<OtherUsers>,
+    pub redact_classmates: Option<bool>,
+}
+
+#[derive(Deserialize, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Content {
+    #[serde(default)]
+    pub other_user_blogs: bool,
+    // web links can die after graduation, so their pages are saved too
+    #[serde(default)]
+    pub archive_links: bool,
+    // usage of the sections the account teaches, for record-keeping
+    #[serde(default)]
+    pub analytics: bool,
+}
+
+#[derive(Deserialize, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Filters {
+    #[serde(default)]
+    pub courses: Vec<String>,
+    #[serde(default)]
+    pub exclude_courses: Vec<String>,
+}
+
+impl Filters {
+    pub fn includes_course(&self, course_id: &str) -> bool {
+        (self.courses.is_empty() || self.courses.iter().any(|x| x == course_id))
+            && !self.exclude_courses.iter().any(|x| x == course_id)
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RetryConfig {
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+    // seconds until a request is given up, including the download of the body
+    #[serde(default = "default_request_timeout")]
+    pub request_timeout: u64,
+    #[serde(default = "default_connect_timeout")]
+    pub connect_timeout: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: default_max_retries(),
+            request_timeout: default_request_timeout(),
+            connect_timeout: default_connect_timeout(),
+        }
+    }
+}
+
+fn default_parallelism() -> usize {
+    1
+}
+
+fn default_attachment_parallelism() -> usize {
+    4
+}
+
+fn default_user_parallelism() -> usize {
+    4
+}
+
+fn default_page_parallelism() -> usize {
+    4
+}
+
+fn default_max_retries() -> u32 {
+    10
+}
+
+fn default_request_timeout() -> u64 {
+    30 * 60
+}
+
+fn default_connect_timeout() -> u64 {
+    30
+}
+
+impl Config {
+    pub fn api_base(&self) -> String {
+        match &self.api_base {
+            Some(api_base) if api_base.ends_with('/') => api_base.clone(),
+            Some(api_base) => format!("{api_base}/"),
+            None => "https://api.schoology.com/v1/".to_string(),
+        }
+    }
+
+    pub fn user_agent(&self) -> String {
+        if let Some(user_agent) = &self.user_agent {
+            return user_agent.clone();
+        }
+        let mut comment = "+https://github.com/r58Playz/export-schoology".to_string();
+        if let Some(contact) = &self.contact {
+            comment.push_str(&format!("; {contact}"));
+        }
+        format!(
+            "export-schoology/{} ({})",
+            env!("CARGO_PKG_VERSION"),
+            comment
+        )
+    }
+
+    pub fn other_users(&self) -> OtherUsers {
+        self.privacy.other_users.unwrap_or(match self.profile {
+            Profile::Full => OtherUsers::All,
+            Profile::ChildSafe => OtherUsers::Teachers,
+        })
+    }
+
+    pub fn redact_classmates(&self) -> bool {
+        self.privacy
+            .redact_classmates
+            .unwrap_or(self.profile == Profile::ChildSafe)
+    }
+
+    pub async fn load(path: &Path) -> anyhow::Result<Self> {
+        let contents = tokio::fs::read_to_string(path)
+            .await
+            .context("failed to read config file")?;
+
+        if path.extension().is_some_and(|x| x == "toml") {
+            toml::from_str(&contents).context("failed to parse config file")
+        } else {
+            Self::from_legacy(&contents)
+        }
+    }
+
+    // the original newline separated creds file
+    fn from_legacy(contents: &str) -> anyhow::Result<Self> {
+        let mut creds = contents.split('\n');
+
+        let domain = creds.next().context("no schoology domain")?;
+        let app_token = creds.next().context("no app token")?;
+        let app_secret = creds.next().context("no app secret")?;
+        let user_token = creds.next().filter(|x| !x.is_empty());
+        let user_secret = creds.next().filter(|x| !x.is_empty());
+
+        Ok(Self {
+            domain: domain.to_string(),
+            // only configurable through config.toml
+            api_base: None,
+            app_token: app_token.to_string(),
+            app_secret: app_secret.to_string(),
+            user_token: user_token.map(|x| x.to_string()),
+            user_secret: user_secret.map(|x| x.to_string()),
+            session_cookie: None,
+            admin: false,
+            output: None,
+            parallelism: default_parallelism(),
+            attachment_parallelism: default_attachment_parallelism(),
+            user_parallelism: default_user_parallelism(),
+            page_parallelism: default_page_parallelism(),
+            profile: Profile::default(),
+            privacy: Privacy::default(),
+            content: Content::default(),
+            filters: Filters::default(),
+            retry: RetryConfig::default(),
+            accounts: Vec::new(),
+            parallel_accounts: false,
+            user_agent: None,
+            contact: None,
+        })
+    }
+}
@@ -0,0 +1,138 @@
+use std::{
+    collections::HashSet,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use futures::channel::mpsc::UnboundedSender;
+use serde_json::Value;
+
+#[cfg(feature = "pdf")]
+use crate::pdf::PdfRenderer;
+use crate::{
+    api_helpers::Prefetched,
+    config::{Config, OtherUsers},
+    manifest::Manifest,
+    markdown::Format,
+    people::People,
+    sanitize::Layout,
+    storage::Storage,
+    summary::Summary,
+    throttle::RateLimiter,
+    timeline::Timeline,
+    WebSession,
+};
+
+pub struct ExportContext {
+    pub config: Config,
+    pub uid: i64,
+    // users with admin enrollments in any exported section
+    pub teachers: HashSet<i64>,
+    pub limiter: Option<RateLimiter>,
+    pub layout: Layout,
+    pub format: Format,
+    // attachments are added to the manifest without downloading them
+    pub no_attachments: bool,
+    pub people: People,
+    pub timeline: Timeline,
+    pub manifest: Manifest,
+    pub prefetched: Prefetched,
+    // users that are exported or queued for export
+    pub exported_users: Mutex<HashSet<i64>>,
+    // folder items by type and id, to find the materials the folder walk missed
+    pub exported_items: Mutex<HashSet<(String, i64)>>,
+    // users waiting for the background export
+    pub user_queue: UnboundedSender<i64>,
+    pub summary: Arc<Summary>,
+    pub storage: Arc<dyn Storage>,
+    // the browser session of the account, for what the api doesn't serve
+    pub web_session: Option<WebSession>,
+    #[cfg(feature = "pdf")]
+    pub pdf: Option<PdfRenderer>,
+}
+
+impl ExportContext {
+    // does nothing unless built with the pdf feature and a renderer is set, failures aren't fatal
+    #[cfg_attr(not(feature = "pdf"), allow(unused_variables))]
+    pub async fn render_pdf(&self, path: &Path, title: &str, body: &str) {
+        #[cfg(feature = "pdf")]
+        if let Some(pdf) = &self.pdf {
+            if let Err(err) = pdf.render(self.storage.as_ref(), path, title, body).await {
+                log::warn!("failed to render {:?}: {:#}", path, err);
+                self.summary
+                    .failure(format!("failed to render {:?}: {:#}", path, err));
+            }
+        }
+    }
+
+    // returns false if the user was already claimed
+    pub fn claim_user(&self, uid: i64) -> bool {
+        self.exported_users.lock().unwrap().insert(uid)
+    }
+
+    pub fn queue_user(&self, uid: i64) -> anyhow::Result<()> {
+        if self.should_export_user(uid) && self.claim_user(uid) {
+            self.user_queue.unbounded_send(uid)?;
+        }
+        Ok(())
+    }
+
+    pub fn is_user_claimed(&self, uid: i64) -> bool {
+        self.exported_users.lock().unwrap().contains(&uid)
+    }
+
+    pub fn is_item_exported(&self, item_type: &str, item_id: i64) -> bool {
+        self.exported_items
+            .lock()
+            .unwrap()
+            .contains(&(item_type.to_string(), item_id))
+    }
+
+    pub fn should_export_user(&self, uid: i64) -> bool {
+        uid == self.uid
+            || match self.config.other_users() {
+                OtherUsers::All => true,
+                OtherUsers::Teachers => self.teachers.contains(&uid),
+                OtherUsers::None => false,
+            }
+    }
+
+    pub fn is_classmate(&self, uid: i64) -> bool {
+        uid != self.uid && !self.teachers.contains(&uid)
+    }
+
+    pub fn redact(&self, value: &mut Value) {
+        if !self.config.redact_classmates() {
+            return;
+        }
+        match value {
+            Value::Object(object) => {
+                let classmate = ["uid", "author_id"].iter().any(|key| {
+                    object
+                        .get(*key)
+                        .and_then(|x| x.as_i64().or(x.as_str().and_then(|x| x.parse().ok())))
+                        .is_some_and(|x| self.is_classmate(x))
+                });
+                if classmate {
+                    for key in ["uid", "author_id"] {
+                        if object.contains_key(key) {
+                            object.insert(key.to_string(), Value::Null);
+                        }
+                    }
+                    for key in ["name_display", "name_first", "name_last", "picture_url"] {
+                        object.remove(key);
+                    }
+                }
+                for value in object.values_mut() {
+                    self.redact(value);
+                }
+            }
+            Value::Array(array) => {
+                for value in array {
+                    self.redact(value);
+                }
+            }
+            _ => {}
+        }
+    }
+}
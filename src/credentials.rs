@@ -0,0 +1,84 @@
+use anyhow::{anyhow, Context};
+use keyring::Entry;
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+
+const KEYCHAIN_SERVICE: &str = "export-schoology";
+
+// stored as a single json secret per domain
+#[derive(Serialize, Deserialize, Default)]
+pub struct Credentials {
+    pub app_token: String,
+    pub app_secret: String,
+    pub user_token: Option<String>,
+    pub user_secret: Option<String>,
+}
+
+fn keychain_entry(domain: &str) -> anyhow::Result<Entry> {
+    Entry::new(KEYCHAIN_SERVICE, domain).context("failed to open keychain")
+}
+
+fn load_keychain(domain: &str) -> anyhow::Result<Option<Credentials>> {
+    match keychain_entry(domain)?.get_password() {
+        Ok(secret) => Ok(Some(
+            serde_json::from_str(&secret).context("failed to parse keychain credentials")?,
+        )),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(err) => Err(err).context("failed to read keychain"),
+    }
+}
+
+pub fn save_keychain(domain: &str, credentials: &Credentials) -> anyhow::Result<()> {
+    keychain_entry(domain)?
+        .set_password(&serde_json::to_string(credentials)?)
+        .context("failed to write keychain")
+}
+
+fn fill(field: &mut Option<String>, value: Option<String>) {
+    if field.is_none() {
+        *field = value.filter(|x| !x.is_empty());
+    }
+}
+
+// the config file wins, then the environment, then the keychain
+pub fn fill_credentials(config: &mut Config) -> anyhow::Result<()> {
+    let mut app_token = Some(std::mem::take(&mut config.app_token)).filter(|x| !x.is_empty());
+    let mut app_secret = Some(std::mem::take(&mut config.app_secret)).filter(|x| !x.is_empty());
+    fill(&mut app_token, std::env::var("SCHOOLOGY_APP_TOKEN").ok());
+    fill(&mut app_secret, std::env::var("SCHOOLOGY_APP_SECRET").ok());
+    fill(
+        &mut config.user_token,
+        std::env::var("SCHOOLOGY_USER_TOKEN").ok(),
+    );
+    fill(
+        &mut config.user_secret,
+        std::env::var("SCHOOLOGY_USER_SECRET").ok(),
+    );
+
+    let missing = app_token.is_none()
+        || app_secret.is_none()
+        || (!config.admin && (config.user_token.is_none() || config.user_secret.is_none()));
+    if missing {
+        // a keychain that can't be reached shouldn't stop an export that asks for authorization anyway
+        match load_keychain(&config.domain) {
+            Ok(Some(credentials)) => {
+                fill(&mut app_token, Some(credentials.app_token));
+                fill(&mut app_secret, Some(credentials.app_secret));
+                fill(&mut config.user_token, credentials.user_token);
+                fill(&mut config.user_secret, credentials.user_secret);
+            }
+            Ok(None) => {}
+            Err(err) => debug!("no keychain credentials: {:#}", err),
+        }
+    }
+
+    config.app_token = app_token.ok_or(anyhow!(
+        "no app token in the config, SCHOOLOGY_APP_TOKEN or the keychain"
+    ))?;
+    config.app_secret = app_secret.ok_or(anyhow!(
+        "no app secret in the config, SCHOOLOGY_APP_SECRET or the keychain"
+    ))?;
+    Ok(())
+}
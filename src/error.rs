@@ -0,0 +1,84 @@
+use std::{io, path::PathBuf};
+
+use reqwest::StatusCode;
+use thiserror::Error;
+
+// the failures worth telling apart, everything else stays a plain anyhow error. the `.context`
+// wrapped around these only names the step of the export that failed, the typed cause below it is
+// still found with `downcast_ref`
+#[derive(Debug, Error)]
+pub enum ExportError {
+    #[error("{url:?} returned {status}, the tokens were rejected; run the login command again")]
+    Auth { url: String, status: StatusCode },
+    #[error("{url:?} is still rate limited after retrying")]
+    RateLimited {
+        url: String,
+        retry_after: Option<u64>,
+    },
+    // content that can't be accessed anymore, e.g. folders in archived courses
+    #[error("{url:?} returned {status}")]
+    Denied { url: String, status: StatusCode },
+    #[error("{url:?} returned {status}")]
+    Status { url: String, status: StatusCode },
+    #[error("{url:?} was still not ready after {retries} retries")]
+    NotReady { url: String, retries: u32 },
+    #[error("{url:?} returned an unexpected response")]
+    Schema {
+        url: String,
+        #[source]
+        source: serde_json::Error,
+    },
+    #[error("{entity} {id} has an unknown type {kind:?}")]
+    UnexpectedType {
+        entity: &'static str,
+        id: String,
+        kind: String,
+    },
+    #[error("{entity}{} has no {field}", id.as_ref().map(|x| format!(" {x}")).unwrap_or_default())]
+    MissingField {
+        entity: &'static str,
+        id: Option<String>,
+        field: &'static str,
+    },
+    #[error("failed to {action} {path:?}")]
+    Filesystem {
+        action: &'static str,
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+}
+
+impl ExportError {
+    pub fn missing_field(entity: &'static str, field: &'static str) -> Self {
+        Self::MissingField {
+            entity,
+            id: None,
+            field,
+        }
+    }
+
+    pub fn missing_field_of(entity: &'static str, id: impl ToString, field: &'static str) -> Self {
+        Self::MissingField {
+            entity,
+            id: Some(id.to_string()),
+            field,
+        }
+    }
+
+    pub fn filesystem(
+        action: &'static str,
+        path: impl Into<PathBuf>,
+    ) -> impl FnOnce(io::Error) -> Self {
+        let path = path.into();
+        move |source| Self::Filesystem {
+            action,
+            path,
+            source,
+        }
+    }
+}
+
+pub fn is_denied(err: &anyhow::Error) -> bool {
+    matches!(err.downcast_ref(), Some(ExportError::Denied { .. }))
+}
@@ -1,362 +1,3699 @@
 #![allow(clippy::too_many_arguments)]
-use std::path::PathBuf;
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
 
 use anyhow::{anyhow, Context};
 use async_recursion::async_recursion;
+use futures::{stream, StreamExt};
 use log::{debug, error, info, warn};
-use reqwest::Request;
+use reqwest::{header::CONTENT_TYPE, Request, Response, Url};
 use reqwest_middleware::ClientWithMiddleware;
-use serde_json::Value;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt;
 
-use crate::{api_helpers::SchoologyRequestHelper, TokenInfo, ValueHelper};
+use crate::{
+    api_helpers::{
+        get, get_all_pages, get_optional, get_raw, get_raw_optional, SchoologyRequestHelper,
+    },
+    context::ExportContext,
+    error::{is_denied, ExportError},
+    filetype::corrected_name,
+    links::export_links,
+    manifest::ManifestEntry,
+    markdown::{date, html_to_markdown, markdown_document, Format},
+    progress,
+    reports::{
+        analytics_csv, grade_comments, grade_history, grade_setup, grade_statistics, grades_csv,
+    },
+    richtext::{decode_entities, embedded_urls},
+    sanitize::{sanitize_filename, Layout, UniqueNames},
+    storage::Storage,
+    throttle::RateLimiter,
+    TokenInfo, ValueHelper, WebSession,
+};
+
+// default pictures and banners are theme assets, e.g. /sites/all/themes/schoology_theme/images/course-default.svg
+fn is_placeholder_image(url: &Url) -> bool {
+    let path = url.path().to_ascii_lowercase();
+    path.contains("/sites/all/themes/")
+        || path
+            .rsplit('/')
+            .next()
+            .is_some_and(|x| x.contains("default") || x.contains("placeholder"))
+}
+
+// pictures, banners and badges, which can be relative urls or a default image
+pub async fn export_image(
+    path: &Path,
+    client: &ClientWithMiddleware,
+    token_info: Option<&TokenInfo>,
+    ctx: &ExportContext,
+    url: &str,
+) -> anyhow::Result<()> {
+    let url = match url.trim() {
+        "" => return Ok(()),
+        x if x.starts_with("//") => format!("https:{x}"),
+        x => x.to_string(),
+    };
+    let url = match Url::parse(&url) {
+        Ok(x) => x,
+        Err(_) => Url::parse(&format!("https://{}/", ctx.config.domain))?.join(&url)?,
+    };
+    if is_placeholder_image(&url) {
+        debug!("skipping default image {:?}", url.as_str());
+        return Ok(());
+    }
+
+    let mut request = Request::get_raw(url.as_str())?;
+    if let Some(token_info) = token_info {
+        request = request.into_schoology(token_info)?;
+    }
+    let response = match client.execute(request).await {
+        Ok(x) if x.status().is_success() => x,
+        Ok(x) => {
+            warn!("failed to request image {:?}: {}", url.as_str(), x.status());
+            ctx.summary.failure(format!(
+                "failed to request image {:?}: {}",
+                url.as_str(),
+                x.status()
+            ));
+            return Ok(());
+        }
+        Err(err) => {
+            warn!("failed to request image {:?}: {:#}", url.as_str(), err);
+            ctx.summary.failure(format!(
+                "failed to request image {:?}: {:#}",
+                url.as_str(),
+                err
+            ));
+            return Ok(());
+        }
+    };
+    // a login or error page instead of the image
+    let is_html = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|x| x.to_str().ok())
+        .is_some_and(|x| x.to_ascii_lowercase().starts_with("text/html"));
+    let image = response.bytes().await?;
+    let start = String::from_utf8_lossy(&image[..image.len().min(64)])
+        .trim_start()
+        .to_ascii_lowercase();
+    if is_html || start.starts_with("<!doctype html") || start.starts_with("<html") {
+        warn!("{:?} returned a web page instead of an image", url.as_str());
+        ctx.summary.failure(format!(
+            "{:?} returned a web page instead of an image",
+            url.as_str()
+        ));
+        return Ok(());
+    }
+    ctx.storage.write(path, image.into()).await
+}
 
 pub async fn export_school(
     export_path: PathBuf,
     client: &ClientWithMiddleware,
     token_info: &TokenInfo,
-    school_id: i64,
-) -> anyhow::Result<()> {
-    info!("exporting school/building {}", school_id);
-    let info = client
-        .execute(Request::get(&format!("schools/{school_id}"))?.into_schoology(token_info)?)
-        .await?
-        .json::<Value>()
-        .await?;
+    ctx: &ExportContext,
+    school_id: i64,
+) -> anyhow::Result<()> {
+    info!("exporting school/building {}", school_id);
+    let info = get(client, token_info, &format!("schools/{school_id}")).await?;
+
+    ctx.storage
+        .write(
+            &export_path.join("info.json"),
+            serde_json::to_string_pretty(&info)?.into(),
+        )
+        .await?;
+
+    export_image(
+        &export_path.join("picture.png"),
+        client,
+        None,
+        ctx,
+        &info
+            .get_string("picture_url")
+            .ok_or_else(|| ExportError::missing_field_of("school", school_id, "picture_url"))?,
+    )
+    .await
+    .context("failed to save school/building picture")?;
+
+    Ok(())
+}
+
+// resources shared by the district with everyone in a school or building
+pub async fn export_school_resources(
+    export_path: PathBuf,
+    client: &ClientWithMiddleware,
+    token_info: &TokenInfo,
+    ctx: &ExportContext,
+    school_id: i64,
+) -> anyhow::Result<()> {
+    let Some(mut resources_info) = get_optional(
+        client,
+        token_info,
+        &format!("schools/{school_id}/resources?with_attachments=TRUE&richtext=1"),
+    )
+    .await
+    .context("failed to request school resources")?
+    else {
+        return Ok(());
+    };
+
+    let mut resources_cnt = 0;
+    loop {
+        let resources = resources_info.get_array("resource").unwrap_or_default();
+        if resources.is_empty() {
+            break;
+        }
+        if resources_cnt == 0 {
+            info!("exporting resources of school/building {}", school_id);
+            ctx.storage.create_dir_all(&export_path).await?;
+        }
+
+        let resources_path = export_path.join(format!("resources_{resources_cnt}.json"));
+        for resource in resources {
+            let resource_started = Instant::now();
+            let resource_id = resource.get_int("id").ok_or_else(|| {
+                ExportError::missing_field_of("resource of school", school_id, "id")
+            })?;
+            export_attachments(
+                &|file_name| export_path.join(format!("resource_{resource_id}_{file_name}")),
+                client,
+                token_info,
+                ctx,
+                &resource,
+            )
+            .await?;
+            ctx.summary.entity(
+                "resource",
+                resource_id,
+                &resources_path,
+                None,
+                resource_started,
+            );
+        }
+
+        ctx.storage
+            .write(
+                &resources_path,
+                serde_json::to_string_pretty(&resources_info)?.into(),
+            )
+            .await?;
+
+        resources_cnt += 1;
+        match resources_info
+            .get("links")
+            .and_then(|x| x.get_string("next"))
+        {
+            Some(next_link) => {
+                resources_info = get_raw(client, token_info, &next_link)
+                    .await
+                    .context("failed to request school resources")?
+            }
+            None => break,
+        }
+    }
+
+    Ok(())
+}
+
+// the calendar feed only has the summary of an event, the attachments are on the event itself
+pub async fn export_events(
+    export_path: PathBuf,
+    client: &ClientWithMiddleware,
+    token_info: &TokenInfo,
+    ctx: &ExportContext,
+    realm: &str,
+) -> anyhow::Result<()> {
+    // events are only listed within a date range, this covers everything up to a year ahead
+    let end_date = humantime::format_rfc3339_seconds(
+        SystemTime::now() + Duration::from_secs(365 * 24 * 60 * 60),
+    )
+    .to_string();
+    let Some(mut events_info) = get_optional(
+        client,
+        token_info,
+        &format!(
+            "{realm}/events?start_date=2000-01-01&end_date={}&limit=200",
+            &end_date[..10]
+        ),
+    )
+    .await
+    .context("failed to request events")?
+    else {
+        return Ok(());
+    };
+    let mut events = events_info.get_array("event").unwrap_or_default();
+    while let Some(next_link) = events_info.get("links").and_then(|x| x.get_string("next")) {
+        events_info = get_raw(client, token_info, &next_link)
+            .await
+            .context("failed to request events")?;
+        events.extend(events_info.get_array("event").unwrap_or_default());
+    }
+    if events.is_empty() {
+        return Ok(());
+    }
+
+    info!("exporting {} events of {}", events.len(), realm);
+    ctx.storage.create_dir_all(&export_path).await?;
+    for event in &events {
+        let event_started = Instant::now();
+        let event_id = event
+            .get_int("id")
+            .ok_or_else(|| ExportError::missing_field_of("event of", realm, "id"))?;
+        // assignments and discussions show up in the calendar too, they are exported with their course
+        let is_event = event.get_string("type").is_none_or(|x| x == "event");
+        // course events are also listed in the calendar of the user
+        if !is_event
+            || !ctx
+                .exported_items
+                .lock()
+                .unwrap()
+                .insert(("event".to_string(), event_id))
+        {
+            continue;
+        }
+
+        let event_url = format!("{}{realm}/events/{event_id}", token_info.api_base);
+        let mut event_info = get_raw_optional(
+            client,
+            token_info,
+            &format!("{event_url}?with_attachments=TRUE&richtext=1"),
+        )
+        .await
+        .context("failed to request event")?
+        .unwrap_or(event.clone());
+
+        let event_directory = export_path.join(event_id.to_string());
+        ctx.storage.create_dir_all(&event_directory).await?;
+        if let Some(description) = event_info.get_string("description") {
+            event_info["description"] =
+                export_embedded_files(&event_directory, "event_files", client, ctx, &description)
+                    .await?
+                    .into();
+        }
+        ctx.storage
+            .write(
+                &event_directory.join("info.json"),
+                serde_json::to_string_pretty(&event_info)?.into(),
+            )
+            .await?;
+        ctx.timeline
+            .record("event", event_id, &event_info, &event_directory);
+        export_attachments(
+            &|file_name| event_directory.join(format!("attachment_{file_name}")),
+            client,
+            token_info,
+            ctx,
+            &event_info,
+        )
+        .await?;
+        export_item_comments(&event_directory, client, token_info, ctx, &event_url)
+            .await
+            .context("failed to export event comments")?;
+        ctx.summary
+            .entity("event", event_id, &event_directory, None, event_started);
+    }
+
+    ctx.storage
+        .write(
+            &export_path.join("events.json"),
+            serde_json::to_string_pretty(&json!({ "event": events }))?.into(),
+        )
+        .await?;
+    Ok(())
+}
+
+#[async_recursion]
+async fn export_collection_folder(
+    export_path: PathBuf,
+    client: &ClientWithMiddleware,
+    token_info: &TokenInfo,
+    ctx: &ExportContext,
+    library: &str,
+    folder_id: i64,
+) -> anyhow::Result<()> {
+    ctx.storage.create_dir_all(&export_path).await?;
+    let resources = get_all_pages(
+        client,
+        token_info,
+        &format!("{library}/resources?f={folder_id}&limit=200"),
+        "resource",
+    )
+    .await
+    .context("failed to request library resources")?;
+
+    let mut names = UniqueNames::default();
+    let mut index = Vec::new();
+    for resource in resources {
+        let resource_started = Instant::now();
+        let resource_id = resource
+            .get_int("id")
+            .ok_or_else(|| ExportError::missing_field_of("resource of folder", folder_id, "id"))?;
+        let resource_title = resource.get_string("title").unwrap_or_default();
+        let resource_type = resource.get_string("type").unwrap_or_default();
+        info!("exporting resource {:?}", resource_title);
+
+        let resource_name = match ctx.layout {
+            Layout::Title => names.claim(&format!("{resource_id}_{resource_title}")),
+            Layout::Id => resource_id.to_string(),
+        };
+        let resource_directory = export_path.join(&resource_name);
+        index.push(json!({
+            "position": index.len(),
+            "id": resource_id,
+            "type": resource_type,
+            "title": resource_title,
+            "path": resource_name,
+        }));
+
+        if resource_type == "folder" {
+            export_collection_folder(
+                resource_directory.clone(),
+                client,
+                token_info,
+                ctx,
+                library,
+                resource_id,
+            )
+            .await?;
+        } else {
+            ctx.storage.create_dir_all(&resource_directory).await?;
+            let resource_info = get_optional(
+                client,
+                token_info,
+                &format!("{library}/resources/{resource_id}?with_attachments=TRUE&richtext=1"),
+            )
+            .await
+            .context("failed to request resource")?
+            .unwrap_or(resource);
+            ctx.storage
+                .write(
+                    &resource_directory.join("info.json"),
+                    serde_json::to_string_pretty(&resource_info)?.into(),
+                )
+                .await?;
+            if let Some(body) = resource_info.get_string("body").filter(|x| !x.is_empty()) {
+                let body =
+                    export_embedded_files(&resource_directory, "body_files", client, ctx, &body)
+                        .await?;
+                ctx.storage
+                    .write(&resource_directory.join("body.html"), body.into())
+                    .await?;
+            }
+            export_attachments(
+                &|file_name| resource_directory.join(format!("attachment_{file_name}")),
+                client,
+                token_info,
+                ctx,
+                &resource_info,
+            )
+            .await?;
+        }
+        if ctx.layout == Layout::Id {
+            ctx.storage
+                .write(&resource_directory.join("title.txt"), resource_title.into())
+                .await?;
+        }
+        ctx.summary.entity(
+            "resource",
+            resource_id,
+            &resource_directory,
+            None,
+            resource_started,
+        );
+    }
+    ctx.storage
+        .write(
+            &export_path.join("folder_index.json"),
+            serde_json::to_string_pretty(&index)?.into(),
+        )
+        .await?;
+    Ok(())
+}
+
+// the personal resources library, every collection is walked like a course folder
+pub async fn export_collections(
+    export_path: PathBuf,
+    client: &ClientWithMiddleware,
+    token_info: &TokenInfo,
+    ctx: &ExportContext,
+) -> anyhow::Result<()> {
+    let Some(collections) = get_optional(client, token_info, "collections")
+        .await
+        .context("failed to request resource collections")?
+    else {
+        return Ok(());
+    };
+    let collections = collections.get_array("collection").unwrap_or_default();
+    if collections.is_empty() {
+        return Ok(());
+    }
+
+    ctx.storage.create_dir_all(&export_path).await?;
+    ctx.storage
+        .write(
+            &export_path.join("collections.json"),
+            serde_json::to_string_pretty(&collections)?.into(),
+        )
+        .await?;
+    for collection in collections {
+        let collection_id = collection
+            .get_string_lossy("id")
+            .ok_or(ExportError::missing_field("collection", "id"))?;
+        info!(
+            "exporting resource collection {:?}",
+            collection.get_string("title").unwrap_or_default()
+        );
+        export_collection_folder(
+            export_path.join(&collection_id),
+            client,
+            token_info,
+            ctx,
+            &format!("collections/{collection_id}"),
+            0,
+        )
+        .await
+        .context("failed to export resource collection")?;
+    }
+    Ok(())
+}
+
+// the shared library of a group, kept apart from its updates
+pub async fn export_group_resources(
+    export_path: PathBuf,
+    client: &ClientWithMiddleware,
+    token_info: &TokenInfo,
+    ctx: &ExportContext,
+    group_id: &str,
+) -> anyhow::Result<()> {
+    let Some(resources) = get_optional(
+        client,
+        token_info,
+        &format!("groups/{group_id}/resources?f=0&limit=200"),
+    )
+    .await
+    .context("failed to request group resources")?
+    else {
+        return Ok(());
+    };
+    if resources
+        .get_array("resource")
+        .unwrap_or_default()
+        .is_empty()
+    {
+        return Ok(());
+    }
+    info!("exporting resources of group {}", group_id);
+    export_collection_folder(
+        export_path,
+        client,
+        token_info,
+        ctx,
+        &format!("groups/{group_id}"),
+        0,
+    )
+    .await
+}
+
+pub async fn export_user(
+    export_path: PathBuf,
+    client: &ClientWithMiddleware,
+    token_info: &TokenInfo,
+    ctx: &ExportContext,
+    user_id: i64,
+) -> anyhow::Result<Value> {
+    info!("exporting user {}", user_id);
+    ctx.storage.create_dir_all(&export_path).await?;
+
+    let user_info = ctx
+        .prefetched
+        .get(client, token_info, &format!("users/{}", user_id))
+        .await
+        .context("failed to request user info")?;
+
+    ctx.storage
+        .write(
+            &export_path.join("user_info.json"),
+            serde_json::to_string_pretty(&user_info)?.into(),
+        )
+        .await?;
+
+    export_image(
+        &export_path.join("user_image.png"),
+        client,
+        None,
+        ctx,
+        &user_info
+            .get_string("picture_url")
+            .ok_or_else(|| ExportError::missing_field_of("user", user_id, "picture_url"))?,
+    )
+    .await
+    .context("failed to save user picture")?;
+
+    // bio, interests and activities are only part of the extended profile
+    if let Some(profile) = get_optional(
+        client,
+        token_info,
+        &format!("users/{user_id}?extended=TRUE"),
+    )
+    .await
+    .context("failed to request user profile")?
+    {
+        ctx.storage
+            .write(
+                &export_path.join("profile.json"),
+                serde_json::to_string_pretty(&profile)?.into(),
+            )
+            .await?;
+    }
+
+    if let Some(mut updates_info) = get_optional(
+        client,
+        token_info,
+        &format!("users/{user_id}/updates?limit=50&with_attachments=TRUE&richtext=1"),
+    )
+    .await
+    .context("failed to request user updates")?
+    {
+        let mut updates = updates_info.get_array("update").unwrap_or_default();
+        while let Some(next_link) = updates_info.get("links").and_then(|x| x.get_string("next")) {
+            updates_info = get_raw(client, token_info, &next_link)
+                .await
+                .context("failed to request user updates")?;
+            updates.extend(updates_info.get_array("update").unwrap_or_default());
+        }
+        if !updates.is_empty() {
+            let updates_path = export_path.join("updates.json");
+            for update in &updates {
+                if let Some(update_user_id) = update.get_int("uid") {
+                    ctx.people.record(
+                        update_user_id,
+                        &updates_path,
+                        "update",
+                        update.get_int("created"),
+                    );
+                }
+            }
+            let mut updates = json!({ "update": updates });
+            ctx.redact(&mut updates);
+            ctx.storage
+                .write(
+                    &updates_path,
+                    serde_json::to_string_pretty(&updates)?.into(),
+                )
+                .await?;
+        }
+    }
+
+    Ok(user_info)
+}
+
+// returns the number of bytes written and their sha256
+pub async fn download(
+    storage: &dyn Storage,
+    path: &Path,
+    response: Response,
+    limiter: Option<&RateLimiter>,
+) -> anyhow::Result<(u64, String)> {
+    download_rest(storage, path, &[], response, limiter).await
+}
+
+// for a response whose first chunk was already read
+async fn download_rest(
+    storage: &dyn Storage,
+    path: &Path,
+    head: &[u8],
+    mut response: Response,
+    limiter: Option<&RateLimiter>,
+) -> anyhow::Result<(u64, String)> {
+    let mut file = storage.create(path).await?;
+    let mut hasher = Sha256::new();
+    if let Some(limiter) = limiter {
+        limiter.acquire(head.len()).await;
+    }
+    file.write_all(head).await?;
+    hasher.update(head);
+    progress::download(head.len());
+    let mut bytes = head.len() as u64;
+    while let Some(chunk) = response.chunk().await? {
+        if let Some(limiter) = limiter {
+            limiter.acquire(chunk.len()).await;
+        }
+        file.write_all(&chunk).await?;
+        hasher.update(&chunk);
+        bytes += chunk.len() as u64;
+        progress::download(chunk.len());
+    }
+    file.shutdown().await?;
+    Ok((bytes, format!("{:x}", hasher.finalize())))
+}
+
+pub async fn export_blog(
+    export_path: PathBuf,
+    client: &ClientWithMiddleware,
+    token_info: &TokenInfo,
+    ctx: &ExportContext,
+    user_id: i64,
+) -> anyhow::Result<()> {
+    let Some(mut blog_info) = get_optional(
+        client,
+        token_info,
+        &format!("users/{user_id}/blogs?with_attachments=TRUE&richtext=1"),
+    )
+    .await
+    .context("failed to request blog")?
+    else {
+        return Ok(());
+    };
+
+    let mut posts_cnt = 0;
+    loop {
+        let posts = blog_info.get_array("blog").unwrap_or_default();
+        if posts.is_empty() {
+            break;
+        }
+        if posts_cnt == 0 {
+            info!("exporting blog of user {}", user_id);
+            ctx.storage.create_dir_all(&export_path).await?;
+        }
+
+        for post in posts {
+            let post_id = post
+                .get_int("id")
+                .ok_or_else(|| ExportError::missing_field_of("blog post of user", user_id, "id"))?;
+
+            // comments can be disabled or hidden on a post
+            let mut comments = get_optional(
+                client,
+                token_info,
+                &format!(
+                    "users/{user_id}/blogs/{post_id}/comments?with_attachments=TRUE&richtext=1"
+                ),
+            )
+            .await
+            .context("failed to request blog post comments")?;
+            let comments_path = export_path.join(format!("post_{post_id}_comments.json"));
+            if let Some(comments) = &mut comments {
+                for comment in comments.get_array("comment").unwrap_or_default() {
+                    if let Some(comment_user_id) = comment.get_int("uid") {
+                        ctx.people.record(
+                            comment_user_id,
+                            &comments_path,
+                            "blog comment",
+                            comment.get_int("created"),
+                        );
+                    }
+                }
+                ctx.redact(comments);
+                ctx.storage
+                    .write(
+                        &comments_path,
+                        serde_json::to_string_pretty(comments)?.into(),
+                    )
+                    .await?;
+            }
+
+            export_attachments(
+                &|file_name| export_path.join(format!("post_{post_id}_{file_name}")),
+                client,
+                token_info,
+                ctx,
+                &post,
+            )
+            .await?;
+            let comments = comments.and_then(|x| x.get_array("comment"));
+            for comment in comments.unwrap_or_default() {
+                let comment_id = comment.get_int("id").ok_or_else(|| {
+                    ExportError::missing_field_of("comment of blog post", post_id, "id")
+                })?;
+                export_attachments(
+                    &|file_name| {
+                        export_path.join(format!("post_{post_id}_comment_{comment_id}_{file_name}"))
+                    },
+                    client,
+                    token_info,
+                    ctx,
+                    &comment,
+                )
+                .await?;
+            }
+        }
+
+        ctx.storage
+            .write(
+                &export_path.join(format!("posts_{posts_cnt}.json")),
+                serde_json::to_string_pretty(&blog_info)?.into(),
+            )
+            .await?;
+
+        posts_cnt += 1;
+        match blog_info.get("links").and_then(|x| x.get_string("next")) {
+            Some(next_link) => {
+                blog_info = get_raw(client, token_info, &next_link)
+                    .await
+                    .context("failed to request blog")?
+            }
+            None => break,
+        }
+    }
+
+    Ok(())
+}
+
+async fn export_attachment(
+    attachment_path: PathBuf,
+    client: &ClientWithMiddleware,
+    token_info: &TokenInfo,
+    ctx: &ExportContext,
+    attachment: &Value,
+) -> anyhow::Result<()> {
+    let file_id = attachment
+        .get_int("id")
+        .ok_or(ExportError::missing_field("file attachment", "id"))?;
+    let download_url = attachment.get_string("download_path").ok_or_else(|| {
+        ExportError::missing_field_of("file attachment", file_id, "download_path")
+    })?;
+    let file_name = attachment
+        .get_string("filename")
+        .ok_or_else(|| ExportError::missing_field_of("file attachment", file_id, "filename"))?;
+    let filesize = attachment
+        .get_string_lossy("filesize")
+        .and_then(|x| x.parse::<u64>().ok());
+    if ctx
+        .manifest
+        .reuse(&attachment_path, &download_url, filesize)
+        .await
+    {
+        debug!("attachment {:?} is unchanged, skipping", file_name);
+        return Ok(());
+    }
+    if ctx.no_attachments {
+        ctx.manifest.add(ManifestEntry {
+            path: attachment_path,
+            url: download_url,
+            filesize,
+            bytes: 0,
+            sha256: String::new(),
+            pending: true,
+            original_name: None,
+        });
+        return Ok(());
+    }
+    info!("exporting attachment {:?}", file_name);
+    let attachment_started = Instant::now();
+
+    let mut attachment_path = attachment_path;
+    let mut original_name = None;
+    let mut attempts = 0;
+    let (bytes, sha256) = loop {
+        let response = client
+            .execute(Request::get_raw(&download_url)?.into_schoology(token_info)?)
+            .await
+            .context("failed to request file attachment")?;
+        // an error or login page isn't saved as the attachment, it's left pending for fetch-attachments
+        let failed = match response.error_for_status_ref() {
+            Err(err) => Some(err.to_string()),
+            Ok(response) if response.url().path().starts_with("/login") => {
+                Some("redirected to the login page".to_string())
+            }
+            Ok(_) => None,
+        };
+        if let Some(failed) = failed {
+            warn!("failed to download attachment {:?}: {}", file_name, failed);
+            ctx.summary
+                .failure(format!("attachment {file_id} failed to download: {failed}"));
+            ctx.manifest.add(ManifestEntry {
+                path: attachment_path,
+                url: download_url,
+                filesize,
+                bytes: 0,
+                sha256: String::new(),
+                pending: true,
+                original_name: None,
+            });
+            return Ok(());
+        }
+        let mut response = response;
+        let head = response.chunk().await?.unwrap_or_default();
+        // names without an extension or with the wrong one can't be opened
+        let saved_name = attachment_path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        if let Some(corrected) = corrected_name(&saved_name, response.headers(), &head) {
+            info!("saving attachment {:?} as {:?}", file_name, corrected);
+            attachment_path.set_file_name(corrected);
+            original_name = Some(file_name.clone());
+        }
+        let (bytes, sha256) = download_rest(
+            ctx.storage.as_ref(),
+            &attachment_path,
+            &head,
+            response,
+            ctx.limiter.as_ref(),
+        )
+        .await
+        .context("failed to save file attachment")?;
+        attempts += 1;
+
+        match filesize {
+            Some(filesize) if filesize != bytes => {
+                warn!(
+                    "attachment {:?} is {} bytes instead of {} bytes",
+                    file_name, bytes, filesize
+                );
+                if attempts >= 2 {
+                    ctx.summary.failure(format!(
+                        "attachment {file_id} is {bytes} bytes instead of {filesize} bytes"
+                    ));
+                    break (bytes, sha256);
+                }
+            }
+            _ => break (bytes, sha256),
+        }
+    };
+    ctx.manifest.add(ManifestEntry {
+        path: attachment_path.clone(),
+        url: download_url,
+        filesize,
+        bytes,
+        sha256,
+        pending: false,
+        original_name,
+    });
+    ctx.summary.entity(
+        "attachment",
+        file_id,
+        &attachment_path,
+        Some(bytes),
+        attachment_started,
+    );
+    Ok(())
+}
+
+// the document viewer keeps converted and annotated renditions of an upload under their own download paths
+async fn export_attachment_renditions(
+    export_path: &Path,
+    client: &ClientWithMiddleware,
+    token_info: &TokenInfo,
+    ctx: &ExportContext,
+    info: &Value,
+) -> anyhow::Result<()> {
+    let file_attachments = info
+        .get("attachments")
+        .and_then(|x| x.get("files"))
+        .and_then(|x| x.get_array("file"))
+        .unwrap_or_default();
+    let mut names = UniqueNames::default();
+    for attachment in file_attachments {
+        let Value::Object(fields) = &attachment else {
+            continue;
+        };
+        let file_id = attachment
+            .get_int("id")
+            .ok_or(ExportError::missing_field("file attachment", "id"))?;
+        let file_name = attachment
+            .get_string("filename")
+            .ok_or_else(|| ExportError::missing_field_of("file attachment", file_id, "filename"))?;
+        for (key, download_path) in fields {
+            let Some(rendition) = key.strip_suffix("_download_path").filter(|x| !x.is_empty())
+            else {
+                continue;
+            };
+            let Some(download_path) = download_path.as_str().filter(|x| !x.is_empty()) else {
+                continue;
+            };
+            let rendition_name = attachment
+                .get_string(&format!("{rendition}_filename"))
+                .unwrap_or(file_name.clone());
+            let rendition_attachment = json!({
+                "id": file_id,
+                "filename": rendition_name,
+                "download_path": download_path,
+                "filesize": attachment.get(format!("{rendition}_filesize")),
+            });
+            export_attachment(
+                export_path.join(names.claim(&format!("{rendition}_{file_id}_{rendition_name}"))),
+                client,
+                token_info,
+                ctx,
+                &rendition_attachment,
+            )
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+pub async fn export_attachments(
+    export_path_mapper: &(dyn Fn(String) -> PathBuf + Sync + Send),
+    client: &ClientWithMiddleware,
+    token_info: &TokenInfo,
+    ctx: &ExportContext,
+    info: &Value,
+) -> anyhow::Result<()> {
+    let Some(file_attachments) = info
+        .get("attachments")
+        .and_then(|x| x.get("files"))
+        .and_then(|x| x.get_array("file"))
+    else {
+        return Ok(());
+    };
+
+    // names are claimed up front so the downloads can run in any order
+    let mut names = UniqueNames::default();
+    let mut attachments = Vec::new();
+    for attachment in file_attachments {
+        let file_id = attachment
+            .get_int("id")
+            .ok_or(ExportError::missing_field("file attachment", "id"))?;
+        let file_name = attachment
+            .get_string("filename")
+            .ok_or_else(|| ExportError::missing_field_of("file attachment", file_id, "filename"))?;
+        let attachment_path = export_path_mapper(names.claim(&format!("{file_id}_{file_name}")));
+        attachments.push((attachment_path, attachment));
+    }
+
+    let errors = stream::iter(attachments)
+        .map(|(attachment_path, attachment)| async move {
+            export_attachment(attachment_path, client, token_info, ctx, &attachment).await
+        })
+        .buffer_unordered(ctx.config.attachment_parallelism.max(1))
+        .filter_map(|x| async move { x.err() })
+        .collect::<Vec<_>>()
+        .await;
+    match errors.len() {
+        0 => Ok(()),
+        1 => Err(errors.into_iter().next().unwrap()),
+        n => Err(anyhow!(
+            "failed to export {} attachments: {}",
+            n,
+            errors
+                .iter()
+                .map(|x| format!("{:#}", x))
+                .collect::<Vec<_>>()
+                .join("; ")
+        )),
+    }
+}
+
+pub async fn export_embedded_files(
+    html_dir: &Path,
+    files_dir: &str,
+    client: &ClientWithMiddleware,
+    ctx: &ExportContext,
+    html: &str,
+) -> anyhow::Result<String> {
+    let urls = embedded_urls(html);
+    if urls.is_empty() {
+        return Ok(html.to_string());
+    }
+
+    let export_path = html_dir.join(files_dir);
+    ctx.storage.create_dir_all(&export_path).await?;
+
+    let mut html = html.to_string();
+    let mut names = UniqueNames::default();
+    for url in urls {
+        let download_url = decode_entities(&url);
+        let download_url = if download_url.starts_with("//") {
+            format!("https:{download_url}")
+        } else {
+            download_url
+        };
+        if !download_url.starts_with("http://") && !download_url.starts_with("https://") {
+            debug!("skipping embedded file {:?}", download_url);
+            continue;
+        }
+
+        info!("exporting embedded file {:?}", download_url);
+        let file_started = Instant::now();
+        let file = match client.get(&download_url).send().await {
+            Ok(x) if x.status().is_success() => x.bytes().await?,
+            Ok(x) => {
+                warn!(
+                    "failed to request embedded file {:?}: {}",
+                    download_url,
+                    x.status()
+                );
+                ctx.summary.failure(format!(
+                    "failed to request embedded file {:?}: {}",
+                    download_url,
+                    x.status()
+                ));
+                continue;
+            }
+            Err(err) => {
+                warn!(
+                    "failed to request embedded file {:?}: {}",
+                    download_url, err
+                );
+                ctx.summary.failure(format!(
+                    "failed to request embedded file {:?}: {}",
+                    download_url, err
+                ));
+                continue;
+            }
+        };
+
+        let file_name = names.claim(
+            download_url
+                .split(['?', '#'])
+                .next()
+                .and_then(|x| x.rsplit('/').next())
+                .filter(|x| !x.is_empty())
+                .unwrap_or("file"),
+        );
+        let file_bytes = file.len() as u64;
+        ctx.storage
+            .write(&export_path.join(&file_name), file.into())
+            .await
+            .context("failed to save embedded file")?;
+        ctx.summary.entity(
+            "embedded file",
+            &download_url,
+            &export_path.join(&file_name),
+            Some(file_bytes),
+            file_started,
+        );
+
+        html = html.replace(&url, &format!("{files_dir}/{file_name}"));
+    }
+
+    Ok(html)
+}
+
+pub async fn export_update_files(
+    export_path: &Path,
+    client: &ClientWithMiddleware,
+    ctx: &ExportContext,
+    update_info: &mut Value,
+) -> anyhow::Result<()> {
+    let Some(updates) = update_info.get_mut("update").and_then(|x| x.as_array_mut()) else {
+        return Ok(());
+    };
+    for update in updates {
+        if let (Some(update_id), Some(body)) = (update.get_int("id"), update.get_string("body")) {
+            update["body"] = export_embedded_files(
+                export_path,
+                &format!("update_{update_id}_files"),
+                client,
+                ctx,
+                &body,
+            )
+            .await?
+            .into();
+        }
+    }
+    Ok(())
+}
+
+// expects the updates with their files already rewritten and redacted
+pub async fn export_updates_markdown(
+    export_path: &Path,
+    ctx: &ExportContext,
+    update_info: &Value,
+) -> anyhow::Result<()> {
+    if ctx.format != Format::Markdown {
+        return Ok(());
+    }
+    for update in update_info.get_array("update").unwrap_or_default() {
+        let update_id = update
+            .get_int("id")
+            .ok_or(ExportError::missing_field("update", "id"))?;
+        let mut body = html_to_markdown(&update.get_string("body").unwrap_or_default());
+        for comment in update.get_array("comments").unwrap_or_default() {
+            body += &format!(
+                "\n\n---\n\n**{}** ({}):\n\n{}",
+                comment
+                    .get_int("uid")
+                    .map(|x| x.to_string())
+                    .unwrap_or_default(),
+                date(comment.get_int("created"))
+                    .as_str()
+                    .unwrap_or_default(),
+                html_to_markdown(&comment.get_string("comment").unwrap_or_default())
+            );
+        }
+        let document = markdown_document(
+            &[
+                ("id", update_id.into()),
+                ("author", update.get("uid").cloned().unwrap_or_default()),
+                ("date", date(update.get_int("created"))),
+                ("course", update.get_string_lossy("section_id").into()),
+                ("group", update.get_string_lossy("group_id").into()),
+            ],
+            &body,
+        );
+        ctx.storage
+            .write(
+                &export_path.join(format!("update_{update_id}.md")),
+                document.into(),
+            )
+            .await?;
+    }
+    Ok(())
+}
+
+// expects the updates with their files already rewritten and redacted
+pub async fn export_updates_pdf(
+    export_path: &Path,
+    ctx: &ExportContext,
+    update_info: &Value,
+) -> anyhow::Result<()> {
+    for update in update_info.get_array("update").unwrap_or_default() {
+        let update_id = update
+            .get_int("id")
+            .ok_or(ExportError::missing_field("update", "id"))?;
+        let mut body = update.get_string("body").unwrap_or_default();
+        for comment in update.get_array("comments").unwrap_or_default() {
+            body += &format!(
+                "<hr><p><b>{}</b> ({}):</p>{}",
+                comment
+                    .get_int("uid")
+                    .map(|x| x.to_string())
+                    .unwrap_or_default(),
+                date(comment.get_int("created"))
+                    .as_str()
+                    .unwrap_or_default(),
+                comment.get_string("comment").unwrap_or_default()
+            );
+        }
+        ctx.render_pdf(
+            &export_path.join(format!("update_{update_id}.pdf")),
+            &format!("Update {update_id}"),
+            &body,
+        )
+        .await;
+    }
+    Ok(())
+}
+
+pub async fn export_message_markdown(
+    message_path: &Path,
+    ctx: &ExportContext,
+    message_info: &Value,
+) -> anyhow::Result<()> {
+    if ctx.format != Format::Markdown {
+        return Ok(());
+    }
+    let thread = message_info.get_array("message").unwrap_or_default();
+    let Some(first) = thread.first() else {
+        return Ok(());
+    };
+    let body = thread
+        .iter()
+        .map(|x| {
+            format!(
+                "**{}** ({}):\n\n{}",
+                x.get_int("author_id")
+                    .map(|x| x.to_string())
+                    .unwrap_or_default(),
+                date(x.get_int("last_updated")).as_str().unwrap_or_default(),
+                html_to_markdown(&x.get_string("message").unwrap_or_default())
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n---\n\n");
+    let document = markdown_document(
+        &[
+            ("id", first.get("id").cloned().unwrap_or_default()),
+            ("subject", first.get_string("subject").into()),
+            (
+                "author",
+                first.get("author_id").cloned().unwrap_or_default(),
+            ),
+            ("date", date(first.get_int("last_updated"))),
+        ],
+        &body,
+    );
+    ctx.storage
+        .write(&message_path.with_extension("md"), document.into())
+        .await
+}
+
+// the options and results of polls are only available from the poll sub-resource
+pub async fn export_update_poll(
+    export_path: &Path,
+    client: &ClientWithMiddleware,
+    token_info: &TokenInfo,
+    ctx: &ExportContext,
+    update: &Value,
+) -> anyhow::Result<()> {
+    if update.get_string("type").as_deref() != Some("poll") && update.get("poll").is_none() {
+        return Ok(());
+    }
+    let update_id = update
+        .get_int("id")
+        .ok_or(ExportError::missing_field("update", "id"))?;
+    let Some(update_url) = update.get("links").and_then(|x| x.get_string("self")) else {
+        warn!("poll {} has no url", update_id);
+        ctx.summary
+            .failure(format!("poll {update_id} has no url, skipping its results"));
+        return Ok(());
+    };
+    info!("exporting poll {}", update_id);
+    let poll_started = Instant::now();
+
+    let Some(mut poll) = get_raw_optional(client, token_info, &format!("{update_url}/poll"))
+        .await
+        .context("failed to request poll")?
+    else {
+        return Ok(());
+    };
+    let poll_path = export_path.join(format!("update_{update_id}_poll.json"));
+    ctx.redact(&mut poll);
+    ctx.storage
+        .write(&poll_path, serde_json::to_string_pretty(&poll)?.into())
+        .await?;
+    ctx.summary
+        .entity("poll", update_id, &poll_path, None, poll_started);
+
+    Ok(())
+}
+
+// the users who liked an update or comment, the update only has the count
+async fn request_likes(
+    client: &ClientWithMiddleware,
+    token_info: &TokenInfo,
+    ctx: &ExportContext,
+    likes_path: &Path,
+    kind: &'static str,
+    url: &str,
+    liked: &Value,
+) -> anyhow::Result<Value> {
+    let users = match liked.get_int("likes").unwrap_or_default() {
+        0 => Vec::new(),
+        _ => get_optional(client, token_info, url)
+            .await
+            .context("failed to request likes")?
+            .and_then(|x| x.get_array("users").or(x.get_array("user")))
+            .unwrap_or_default(),
+    };
+    for user in &users {
+        if let Some(uid) = user.get_int("uid") {
+            ctx.queue_user(uid)?;
+            ctx.people.record(uid, likes_path, kind, None);
+        }
+    }
+    Ok(json!({
+        "id": liked.get("id"),
+        "likes": liked.get("likes"),
+        "user_like_action": liked.get("user_like_action"),
+        "users": users,
+    }))
+}
+
+pub async fn export_update_likes(
+    export_path: &Path,
+    client: &ClientWithMiddleware,
+    token_info: &TokenInfo,
+    ctx: &ExportContext,
+    update: &Value,
+) -> anyhow::Result<()> {
+    let update_id = update
+        .get_int("id")
+        .ok_or(ExportError::missing_field("update", "id"))?;
+    let comments = update.get_array("comments").unwrap_or_default();
+    let has_likes = std::iter::once(update)
+        .chain(&comments)
+        .any(|x| x.get_int("likes").unwrap_or_default() > 0);
+    if !has_likes {
+        return Ok(());
+    }
+    let likes_started = Instant::now();
+
+    let likes_path = export_path.join(format!("update_{update_id}_likes.json"));
+    let like_url = format!("like/{update_id}");
+    let mut likes = request_likes(
+        client,
+        token_info,
+        ctx,
+        &likes_path,
+        "update like",
+        &like_url,
+        update,
+    )
+    .await?;
+    let mut comment_likes = Vec::new();
+    for comment in &comments {
+        let Some(comment_id) = comment.get_int("id") else {
+            continue;
+        };
+        comment_likes.push(
+            request_likes(
+                client,
+                token_info,
+                ctx,
+                &likes_path,
+                "comment like",
+                &format!("{like_url}/comment/{comment_id}"),
+                comment,
+            )
+            .await?,
+        );
+    }
+    likes["comments"] = comment_likes.into();
+
+    ctx.redact(&mut likes);
+    ctx.storage
+        .write(&likes_path, serde_json::to_string_pretty(&likes)?.into())
+        .await?;
+    ctx.summary
+        .entity("likes", update_id, &likes_path, None, likes_started);
+
+    Ok(())
+}
+
+// updates the account posted anywhere, with every comment of their thread and the comments' attachments
+pub async fn export_posted_updates(
+    export_path: &Path,
+    client: &ClientWithMiddleware,
+    token_info: &TokenInfo,
+    ctx: &ExportContext,
+    posted: Vec<(Value, PathBuf)>,
+) -> anyhow::Result<()> {
+    if posted.is_empty() {
+        return Ok(());
+    }
+    let posted_path = export_path.join("posted.json");
+    let mut entries = Vec::new();
+    for (update, updates_path) in posted {
+        let posted_started = Instant::now();
+        let update_id = update
+            .get_int("id")
+            .ok_or(ExportError::missing_field("update", "id"))?;
+        let realm = update
+            .get_string("realm")
+            .and_then(|realm| {
+                let realm_id = update.get_string_lossy(&format!("{realm}_id"))?;
+                Some(format!("{realm}s/{realm_id}"))
+            })
+            .unwrap_or_else(|| format!("users/{}", ctx.uid));
+
+        // the feeds only embed the latest comments
+        let comments = get_optional(
+            client,
+            token_info,
+            &format!("{realm}/updates/{update_id}/comments?with_attachments=TRUE&richtext=1"),
+        )
+        .await
+        .context("failed to request update comments")?
+        .and_then(|x| x.get_array("comment"))
+        .unwrap_or_else(|| update.get_array("comments").unwrap_or_default());
+        for comment in &comments {
+            let Some(comment_id) = comment.get_int("id") else {
+                continue;
+            };
+            if let Some(comment_user_id) = comment.get_int("uid") {
+                ctx.queue_user(comment_user_id)?;
+                ctx.people.record(
+                    comment_user_id,
+                    &posted_path,
+                    "update comment",
+                    comment.get_int("created"),
+                );
+            }
+            export_attachments(
+                &|file_name| {
+                    export_path.join(format!(
+                        "update_{update_id}_comment_{comment_id}_{file_name}"
+                    ))
+                },
+                client,
+                token_info,
+                ctx,
+                comment,
+            )
+            .await?;
+        }
+
+        let mut entry = json!({
+            "id": update_id,
+            "realm": realm,
+            "created": update.get("created"),
+            "body": update.get("body"),
+            "updates": updates_path.strip_prefix(export_path).unwrap_or(&updates_path),
+            "comments": comments,
+        });
+        ctx.redact(&mut entry);
+        entries.push(entry);
+        ctx.summary.entity(
+            "posted update",
+            update_id,
+            &posted_path,
+            None,
+            posted_started,
+        );
+    }
+    ctx.storage
+        .write(&posted_path, serde_json::to_string_pretty(&entries)?.into())
+        .await
+        .context("failed to write posted updates")
+}
+
+pub async fn export_syllabus(
+    export_path: PathBuf,
+    client: &ClientWithMiddleware,
+    token_info: &TokenInfo,
+    ctx: &ExportContext,
+    section_info: &Value,
+) -> anyhow::Result<()> {
+    // students are often denied the parent course, the section's own description is used then
+    let course_info = match section_info.get_string_lossy("course_id") {
+        Some(course_id) => get_optional(
+            client,
+            token_info,
+            &format!("courses/{course_id}?with_attachments=TRUE&richtext=1"),
+        )
+        .await
+        .context("failed to request course options")?,
+        None => None,
+    };
+    if let Some(course_info) = &course_info {
+        ctx.storage
+            .write(
+                &export_path.join("course.json"),
+                serde_json::to_string_pretty(course_info)?.into(),
+            )
+            .await?;
+    }
+
+    let syllabus = [Some(section_info), course_info.as_ref()]
+        .into_iter()
+        .flatten()
+        .filter_map(|x| x.get_string("description"))
+        .find(|x| !x.trim().is_empty());
+    if let Some(syllabus) = syllabus {
+        info!("exporting syllabus");
+        let syllabus =
+            export_embedded_files(&export_path, "syllabus_files", client, ctx, &syllabus).await?;
+        ctx.storage
+            .write(&export_path.join("syllabus.html"), syllabus.into())
+            .await?;
+    }
+
+    for info in [Some(section_info), course_info.as_ref()]
+        .into_iter()
+        .flatten()
+    {
+        export_attachments(
+            &|file_name| export_path.join(format!("syllabus_attachment_{file_name}")),
+            client,
+            token_info,
+            ctx,
+            info,
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+pub async fn export_badge_images(
+    export_path: PathBuf,
+    client: &ClientWithMiddleware,
+    ctx: &ExportContext,
+    badges: &[Value],
+) -> anyhow::Result<()> {
+    ctx.storage.create_dir_all(&export_path).await?;
+
+    let mut names = UniqueNames::default();
+    for badge in badges {
+        let Some(image_url) = badge
+            .get_string("image_url")
+            .or_else(|| badge.get_string("picture_url"))
+        else {
+            continue;
+        };
+        let badge_id = badge.get_string_lossy("id").unwrap_or_default();
+        let badge_title = badge.get_string("title").unwrap_or_default();
+        info!("exporting badge image {:?}", badge_title);
+
+        export_image(
+            &export_path.join(names.claim(&format!("{badge_id}_{badge_title}.png"))),
+            client,
+            None,
+            ctx,
+            &image_url,
+        )
+        .await
+        .context("failed to save badge image")?;
+    }
+
+    Ok(())
+}
+
+pub async fn export_course_badges(
+    export_path: PathBuf,
+    client: &ClientWithMiddleware,
+    token_info: &TokenInfo,
+    ctx: &ExportContext,
+    course_id: &str,
+) -> anyhow::Result<()> {
+    let Some(badges) = get_optional(client, token_info, &format!("sections/{course_id}/badges"))
+        .await
+        .context("failed to request course badges")?
+    else {
+        return Ok(());
+    };
+    info!("exporting badges for course {}", course_id);
+
+    ctx.storage.create_dir_all(&export_path).await?;
+    ctx.storage
+        .write(
+            &export_path.join("badges.json"),
+            serde_json::to_string_pretty(&badges)?.into(),
+        )
+        .await?;
+    export_badge_images(
+        export_path.join("images"),
+        client,
+        ctx,
+        &badges.get_array("badge").unwrap_or_default(),
+    )
+    .await?;
+
+    if let Some(awards) = get_optional(
+        client,
+        token_info,
+        &format!("sections/{course_id}/badges/awards"),
+    )
+    .await
+    .context("failed to request course badge awards")?
+    {
+        ctx.storage
+            .write(
+                &export_path.join("awards.json"),
+                serde_json::to_string_pretty(&awards)?.into(),
+            )
+            .await?;
+    }
+
+    Ok(())
+}
+
+// a parent account lists its children in child_uids, their realms are readable by the parent
+pub async fn export_children(
+    export_path: PathBuf,
+    client: &ClientWithMiddleware,
+    token_info: &TokenInfo,
+    ctx: &ExportContext,
+    user_info: &Value,
+) -> anyhow::Result<()> {
+    let child_uids = user_info
+        .get_string_lossy("child_uids")
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|x| x.trim().parse::<i64>().ok())
+        .collect::<Vec<_>>();
+    if child_uids.is_empty() {
+        return Ok(());
+    }
+    info!("exporting {} children", child_uids.len());
+
+    for child_uid in child_uids {
+        let child_started = Instant::now();
+        let child_dir = export_path.join(child_uid.to_string());
+        ctx.storage.create_dir_all(&child_dir).await?;
+        // the profile is exported to users/ like everyone else
+        ctx.queue_user(child_uid)?;
+        ctx.people.record(child_uid, &child_dir, "child", None);
+
+        for (name, url) in [
+            ("sections", format!("users/{child_uid}/sections")),
+            ("grades", format!("users/{child_uid}/grades")),
+            (
+                "updates",
+                format!("users/{child_uid}/updates?limit=200&with_attachments=TRUE&richtext=1"),
+            ),
+        ] {
+            let Some(mut info) = get_optional(client, token_info, &url)
+                .await
+                .with_context(|| format!("failed to request child {name}"))?
+            else {
+                debug!("{} of child {} aren't available", name, child_uid);
+                continue;
+            };
+            ctx.redact(&mut info);
+            ctx.storage
+                .write(
+                    &child_dir.join(format!("{name}.json")),
+                    serde_json::to_string_pretty(&info)?.into(),
+                )
+                .await?;
+        }
+        ctx.summary
+            .entity("child", child_uid, &child_dir, None, child_started);
+    }
+    Ok(())
+}
+
+// badges awarded to the user across all sections
+pub async fn export_user_badges(
+    export_path: PathBuf,
+    client: &ClientWithMiddleware,
+    token_info: &TokenInfo,
+    ctx: &ExportContext,
+    user_id: i64,
+) -> anyhow::Result<()> {
+    let Some(awards) = get_optional(client, token_info, &format!("users/{user_id}/badges"))
+        .await
+        .context("failed to request user badges")?
+    else {
+        return Ok(());
+    };
+    let awards_list = awards.get_array("badge").unwrap_or_default();
+    if awards_list.is_empty() {
+        return Ok(());
+    }
+    info!("exporting {} awarded badges", awards_list.len());
+
+    ctx.storage.create_dir_all(&export_path).await?;
+    let awards_path = export_path.join("awarded.json");
+    ctx.storage
+        .write(&awards_path, serde_json::to_string_pretty(&awards)?.into())
+        .await?;
+    // awards either embed the badge they are for or are the badge itself
+    export_badge_images(
+        export_path.join("images"),
+        client,
+        ctx,
+        &awards_list
+            .iter()
+            .map(|x| x.get("badge").unwrap_or(x).clone())
+            .collect::<Vec<_>>(),
+    )
+    .await?;
+
+    for award in &awards_list {
+        ctx.summary.entity(
+            "badge",
+            award.get_string_lossy("id").unwrap_or_default(),
+            &awards_path,
+            None,
+            Instant::now(),
+        );
+    }
+
+    Ok(())
+}
+
+// what a notification is about, from its type or else its text
+fn notification_kind(notification: &Value) -> &'static str {
+    let text = notification
+        .get_string("type")
+        .or_else(|| notification.get_string("notification_type"))
+        .or_else(|| notification.get_string("message"))
+        .or_else(|| notification.get_string("title"))
+        .unwrap_or_default()
+        .to_lowercase();
+    if text.contains("grade") {
+        "grade"
+    } else if text.contains("comment") {
+        "comment"
+    } else if text.contains("due") || text.contains("overdue") {
+        "due date"
+    } else {
+        "other"
+    }
+}
+
+// not part of the documented api, so only exported where the endpoints answer
+pub async fn export_notifications(
+    export_path: PathBuf,
+    client: &ClientWithMiddleware,
+    token_info: &TokenInfo,
+    ctx: &ExportContext,
+    user_id: i64,
+) -> anyhow::Result<()> {
+    let mut notifications = Vec::new();
+    for (endpoint, key) in [("notifications", "notification"), ("alerts", "alert")] {
+        let Some(response) =
+            get_optional(client, token_info, &format!("users/{user_id}/{endpoint}"))
+                .await
+                .with_context(|| format!("failed to request {endpoint}"))?
+        else {
+            continue;
+        };
+        let items = response.get_array(key).unwrap_or_default();
+        if items.is_empty() {
+            continue;
+        }
+        info!("exporting {} {}", items.len(), endpoint);
+        ctx.storage.create_dir_all(&export_path).await?;
+        ctx.storage
+            .write(
+                &export_path.join(format!("{endpoint}.json")),
+                serde_json::to_string_pretty(&response)?.into(),
+            )
+            .await?;
+        for item in items {
+            let timestamp = item
+                .get_int("created")
+                .or_else(|| item.get_int("timestamp"))
+                .or_else(|| item.get_int("last_updated"));
+            notifications.push(json!({
+                "id": item.get("id"),
+                "source": key,
+                "kind": notification_kind(&item),
+                "date": date(timestamp),
+                "timestamp": timestamp,
+                "message": item.get_string("message").or_else(|| item.get_string("title")),
+                "notification": item,
+            }));
+        }
+    }
+    if notifications.is_empty() {
+        return Ok(());
+    }
+
+    notifications.sort_by_key(|x| x["timestamp"].as_i64());
+    let notifications_path = export_path.join("history.json");
+    for notification in &notifications {
+        let item = &notification["notification"];
+        if let Some(uid) = item.get_int("uid").or_else(|| item.get_int("actor_uid")) {
+            ctx.people.record(
+                uid,
+                &notifications_path,
+                "notification",
+                notification["timestamp"].as_i64(),
+            );
+        }
+        ctx.summary.entity(
+            "notification",
+            item.get_string_lossy("id").unwrap_or_default(),
+            &notifications_path,
+            None,
+            Instant::now(),
+        );
+    }
+    ctx.storage
+        .write(
+            &notifications_path,
+            serde_json::to_string_pretty(&notifications)?.into(),
+        )
+        .await
+}
+
+// fields of a section or group that say who can join it and when it is available
+const ACCESS_KEYS: &[&str] = &[
+    "active",
+    "privacy_level",
+    "invite_type",
+    "options",
+    "course_code",
+    "section_code",
+    "section_school_code",
+    "synced",
+];
+
+// access codes and join settings are only shown to the admins of a section or group
+pub async fn export_access(
+    export_path: &Path,
+    client: &ClientWithMiddleware,
+    token_info: &TokenInfo,
+    ctx: &ExportContext,
+    realm: &str,
+    info: &Value,
+) -> anyhow::Result<()> {
+    if info.get_string_lossy("admin").as_deref() != Some("1") {
+        return Ok(());
+    }
+    let access_code = get_optional(client, token_info, &format!("{realm}/access_code"))
+        .await
+        .context("failed to request access code")?;
+    let mut grading_periods = Vec::new();
+    for period_id in info
+        .get_array("grading_periods")
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|x| match x {
+            Value::Number(x) => Some(x.to_string()),
+            Value::String(x) => Some(x.clone()),
+            _ => x.get_string_lossy("id"),
+        })
+    {
+        if let Some(period) =
+            get_optional(client, token_info, &format!("gradingperiods/{period_id}"))
+                .await
+                .context("failed to request grading period")?
+        {
+            grading_periods.push(period);
+        }
+    }
+
+    let mut access = json!({
+        "access_code": access_code,
+        "grading_periods": grading_periods,
+    });
+    for key in ACCESS_KEYS {
+        if let Some(value) = info.get(*key) {
+            access[*key] = value.clone();
+        }
+    }
+    ctx.storage
+        .write(
+            &export_path.join("access.json"),
+            serde_json::to_string_pretty(&access)?.into(),
+        )
+        .await
+}
+
+// access and usage of the students, only shown to the teachers of a section
+async fn export_course_analytics(
+    course_dir: &Path,
+    client: &ClientWithMiddleware,
+    token_info: &TokenInfo,
+    ctx: &ExportContext,
+    course_id: &str,
+    course_info: &Value,
+) -> anyhow::Result<()> {
+    if course_info.get_string_lossy("admin").as_deref() != Some("1") {
+        return Ok(());
+    }
+    let Some(analytics) = get_optional(
+        client,
+        token_info,
+        &format!("analytics/sections/{course_id}"),
+    )
+    .await
+    .context("failed to request course analytics")?
+    else {
+        return Ok(());
+    };
+    info!("exporting analytics of course {}", course_id);
+    let analytics_path = course_dir.join("analytics.json");
+    let users = analytics.get_array("user").unwrap_or_default();
+    for uid in users.iter().filter_map(|x| x.get_int("uid")) {
+        ctx.people.record(uid, &analytics_path, "analytics", None);
+    }
+    ctx.storage
+        .write(
+            &analytics_path,
+            serde_json::to_string_pretty(&analytics)?.into(),
+        )
+        .await?;
+    ctx.storage
+        .write(
+            &course_dir.join("analytics.csv"),
+            analytics_csv(&users).into(),
+        )
+        .await
+}
+
+pub async fn export_course(
+    export_path: PathBuf,
+    client: &ClientWithMiddleware,
+    token_info: &TokenInfo,
+    ctx: &ExportContext,
+    course: &Value,
+) -> anyhow::Result<BTreeSet<String>> {
+    let course_started = Instant::now();
+    let course_id = course
+        .get_string("id")
+        .ok_or(ExportError::missing_field("course", "id"))?;
+    let course_dir = export_path.join(&course_id);
+    ctx.storage.create_dir_all(&course_dir).await?;
+
+    info!("exporting course {}", course_id);
+    progress::course_started(
+        &course_id,
+        &course.get_string("course_title").unwrap_or_default(),
+        &course_dir,
+    );
+
+    let course_info_url = course
+        .get("links")
+        .and_then(|x| x.get_string("self"))
+        .ok_or_else(|| ExportError::missing_field_of("course", &course_id, "links.self"))?;
+    let course_info = get_raw(
+        client,
+        token_info,
+        &format!("{course_info_url}?with_attachments=TRUE&richtext=1"),
+    )
+    .await
+    .context("failed to request course info")?;
+    ctx.storage
+        .write(
+            &course_dir.join("info.json"),
+            serde_json::to_string_pretty(&course_info)?.into(),
+        )
+        .await?;
+
+    export_syllabus(course_dir.clone(), client, token_info, ctx, &course_info)
+        .await
+        .context("failed to export course syllabus")?;
+    export_access(
+        &course_dir,
+        client,
+        token_info,
+        ctx,
+        &format!("sections/{course_id}"),
+        &course_info,
+    )
+    .await
+    .context("failed to export course access settings")?;
+    if ctx.config.content.analytics {
+        export_course_analytics(
+            &course_dir,
+            client,
+            token_info,
+            ctx,
+            &course_id,
+            &course_info,
+        )
+        .await
+        .context("failed to export course analytics")?;
+    }
+
+    let course_banner_url = course_info
+        .get_string("profile_url")
+        .ok_or_else(|| ExportError::missing_field_of("course", &course_id, "profile_url"))?;
+    export_image(
+        &course_dir.join("banner.png"),
+        client,
+        Some(token_info),
+        ctx,
+        &course_banner_url,
+    )
+    .await
+    .context("failed to save course banner")?;
+
+    let course_grades_info = ctx
+        .prefetched
+        .get(
+            client,
+            token_info,
+            &format!("users/{}/grades/?section_id={course_id}", ctx.uid),
+        )
+        .await
+        .context("failed to request course grades")?;
+    ctx.storage
+        .write(
+            &course_dir.join("grades.json"),
+            serde_json::to_string_pretty(&course_grades_info)?.into(),
+        )
+        .await?;
+
+    let assignments = get_all_pages(
+        client,
+        token_info,
+        &format!("sections/{course_id}/assignments?limit=200"),
+        "assignment",
+    )
+    .await
+    .context("failed to request course assignments")?;
+    ctx.storage
+        .write(
+            &course_dir.join("grades.csv"),
+            grades_csv(&course_grades_info, &assignments).into(),
+        )
+        .await?;
+    let comments = grade_comments(&course_grades_info);
+    if !comments.is_empty() {
+        ctx.storage
+            .write(
+                &course_dir.join("grade_comments.json"),
+                serde_json::to_string_pretty(&json!({ "comment": comments }))?.into(),
+            )
+            .await?;
+    }
+
+    let grading_scales = get_optional(
+        client,
+        token_info,
+        &format!("sections/{course_id}/grading_scales"),
+    )
+    .await
+    .context("failed to request course grading scales")?;
+    if let Some(grading_scales) = &grading_scales {
+        ctx.storage
+            .write(
+                &course_dir.join("grading_scale.json"),
+                serde_json::to_string_pretty(grading_scales)?.into(),
+            )
+            .await?;
+    }
+    if let Some(grading_categories) = get_optional(
+        client,
+        token_info,
+        &format!("sections/{course_id}/grading_categories"),
+    )
+    .await
+    .context("failed to request course grading categories")?
+    {
+        ctx.storage
+            .write(
+                &course_dir.join("grading_categories.json"),
+                serde_json::to_string_pretty(&grading_categories)?.into(),
+            )
+            .await?;
+        ctx.storage
+            .write(
+                &course_dir.join("grade_setup.json"),
+                serde_json::to_string_pretty(&grade_setup(
+                    &course_info,
+                    &grading_categories,
+                    grading_scales.as_ref(),
+                ))?
+                .into(),
+            )
+            .await?;
+    }
+
+    export_course_badges(
+        course_dir.join("badges"),
+        client,
+        token_info,
+        ctx,
+        &course_id,
+    )
+    .await
+    .context("failed to export course badges")?;
+
+    let mut grading_periods = BTreeSet::new();
+    for period in course_grades_info
+        .get_array("section")
+        .unwrap_or_default()
+        .iter()
+        .flat_map(|x| x.get_array("period").unwrap_or_default())
+    {
+        if let Some(period_id) = period.get_string_lossy("period_id") {
+            grading_periods.insert(period_id);
+        }
+    }
+
+    // only available when the course has student completion turned on
+    let mut completion = HashMap::new();
+    if let Some(completion_info) = get_optional(
+        client,
+        token_info,
+        &format!("sections/{course_id}/completion/user/{}", ctx.uid),
+    )
+    .await
+    .context("failed to request course completion")?
+    {
+        ctx.storage
+            .write(
+                &course_dir.join("completion.json"),
+                serde_json::to_string_pretty(&completion_info)?.into(),
+            )
+            .await?;
+        for item in completion_info.get_array("item").unwrap_or_default() {
+            if let Some(item_id) = item.get_int("id") {
+                completion.insert(item_id, item);
+            }
+        }
+    }
+
+    let course_files_root = course_dir.join("files");
+
+    // some districts turn off the folder api, the materials below still cover those courses
+    match ctx
+        .prefetched
+        .get(client, token_info, &format!("courses/{course_id}/folder/0"))
+        .await
+    {
+        Ok(course_files_info) => {
+            export_directory(
+                course_files_root,
+                client,
+                token_info,
+                ctx,
+                &completion,
+                &course_files_info,
+            )
+            .await
+            .context("failed to export course files")?;
+        }
+        Err(err) if is_denied(&err) => {
+            warn!(
+                "folders of course {} are not available: {:#}",
+                course_id, err
+            );
+        }
+        Err(err) => return Err(err.context("failed to request course files")),
+    }
+
+    // materials that the folder walk didn't reach, e.g. hidden folders
+    if let Some(materials) = get_optional(
+        client,
+        token_info,
+        &format!("sections/{course_id}/materials?limit=200"),
+    )
+    .await
+    .context("failed to request course materials")?
+    {
+        let missing = materials
+            .get_array("material")
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|x| {
+                let item_type = x.get_string("type").unwrap_or_default();
+                x.get_int("id")
+                    .is_some_and(|x| !ctx.is_item_exported(&item_type, x))
+            })
+            .map(|mut x| {
+                if x.get_string("location").is_none() {
+                    x["location"] = format!(
+                        "{}sections/{course_id}/{}s/{}",
+                        token_info.api_base,
+                        x.get_string("type").unwrap_or_default(),
+                        x.get_int("id").unwrap_or_default()
+                    )
+                    .into();
+                }
+                x
+            })
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            info!(
+                "exporting {} materials of course {} missing from the folders",
+                missing.len(),
+                course_id
+            );
+            export_directory(
+                course_dir.join("materials"),
+                client,
+                token_info,
+                ctx,
+                &completion,
+                &json!({ "folder-item": missing }),
+            )
+            .await
+            .context("failed to export course materials")?;
+        }
+    }
+
+    ctx.summary
+        .entity("course", &course_id, &course_dir, None, course_started);
+
+    Ok(grading_periods)
+}
+
+// the website still shows some items the api denies, the page is saved as is
+async fn export_web_page(
+    item_directory: &Path,
+    client: &ClientWithMiddleware,
+    ctx: &ExportContext,
+    web_session: &WebSession,
+    item_type: &str,
+    item_id: i64,
+    item_url: &str,
+) -> anyhow::Result<bool> {
+    let path = match item_type {
+        "assignment" | "assessment_v2" => format!("assignment/{item_id}"),
+        "page" => format!("page/{item_id}"),
+        "discussion" => format!("discussion/{item_id}"),
+        "folder" => {
+            let Some(course_id) = item_url
+                .split("/courses/")
+                .nth(1)
+                .and_then(|x| x.split('/').next())
+            else {
+                return Ok(false);
+            };
+            format!("course/{course_id}/materials?f={item_id}")
+        }
+        _ => return Ok(false),
+    };
+    let response = client
+        .execute(
+            Request::get_raw(&format!("https://{}/{path}", web_session.domain))?
+                .into_web_session(web_session)?,
+        )
+        .await
+        .context("failed to request web page")?;
+    if !response.status().is_success() {
+        return Err(ExportError::Status {
+            url: response.url().to_string(),
+            status: response.status(),
+        }
+        .into());
+    }
+
+    let is_html = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|x| x.to_str().ok())
+        .is_some_and(|x| x.starts_with("text/html"));
+    // an expired session is redirected to the login form
+    if !is_html || response.url().path().starts_with("/login") {
+        return Err(anyhow!("got {} instead of the page", response.url()));
+    }
+    let html = response.text().await.context("failed to read web page")?;
+    ctx.storage
+        .write(&item_directory.join("web.html"), html.into())
+        .await?;
+    info!("saved the web page of {} {} instead", item_type, item_id);
+    Ok(true)
+}
+
+// the printable grade report is only served by the web ui, not the api
+pub async fn export_grade_reports(
+    export_path: PathBuf,
+    client: &ClientWithMiddleware,
+    ctx: &ExportContext,
+    web_session: &WebSession,
+    grading_periods: &BTreeSet<String>,
+) -> anyhow::Result<()> {
+    ctx.storage.create_dir_all(&export_path).await?;
+
+    for period_id in grading_periods {
+        info!("exporting grade report for grading period {}", period_id);
+        let report_started = Instant::now();
+        let report = client
+            .execute(
+                Request::get_raw(&format!(
+                    "https://{}/grades/grades/print?period_id={period_id}&format=pdf",
+                    web_session.domain
+                ))?
+                .into_web_session(web_session)?,
+            )
+            .await
+            .context("failed to request grade report")?;
+
+        let is_pdf = report
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|x| x.to_str().ok())
+            .is_some_and(|x| x.starts_with("application/pdf"));
+        if !is_pdf {
+            warn!(
+                "grade report for grading period {} is not a pdf, is the session cookie valid?",
+                period_id
+            );
+            ctx.summary.failure(format!(
+                "grade report for grading period {period_id} is not a pdf"
+            ));
+            continue;
+        }
+
+        let report_path = export_path.join(sanitize_filename(&format!("{period_id}.pdf")));
+        let (bytes, _) = download(
+            ctx.storage.as_ref(),
+            &report_path,
+            report,
+            ctx.limiter.as_ref(),
+        )
+        .await
+        .context("failed to save grade report")?;
+        ctx.summary.entity(
+            "grade report",
+            period_id,
+            &report_path,
+            Some(bytes),
+            report_started,
+        );
+    }
+
+    Ok(())
+}
+
+// the final grades of every course for each grading period, as on a report card
+pub async fn export_report_cards(
+    export_path: PathBuf,
+    client: &ClientWithMiddleware,
+    token_info: &TokenInfo,
+    ctx: &ExportContext,
+    user_id: i64,
+    grading_periods: &BTreeSet<String>,
+) -> anyhow::Result<()> {
+    for period_id in grading_periods {
+        let report_started = Instant::now();
+        let Some(mut grades) = get_optional(
+            client,
+            token_info,
+            &format!("users/{user_id}/grades?grading_period_ids={period_id}"),
+        )
+        .await
+        .context("failed to request report card")?
+        // periods without grades of the user answer with an empty object
+        .filter(|x| x.as_object().is_some_and(|x| !x.is_empty())) else {
+            continue;
+        };
+        let period_dir = export_path.join(sanitize_filename(period_id));
+        ctx.storage.create_dir_all(&period_dir).await?;
+        if let Some(period) =
+            get_optional(client, token_info, &format!("gradingperiods/{period_id}"))
+                .await
+                .context("failed to request grading period")?
+        {
+            ctx.storage
+                .write(
+                    &period_dir.join("period.json"),
+                    serde_json::to_string_pretty(&period)?.into(),
+                )
+                .await?;
+        }
+        ctx.redact(&mut grades);
+        let grades_path = period_dir.join("grades.json");
+        ctx.storage
+            .write(&grades_path, serde_json::to_string_pretty(&grades)?.into())
+            .await?;
+        ctx.summary
+            .entity("report card", period_id, &grades_path, None, report_started);
+    }
+
+    Ok(())
+}
+
+fn is_flag_set(value: &Value, key: &str) -> bool {
+    match value.get(key) {
+        Some(Value::Bool(x)) => *x,
+        _ => value.get_string_lossy(key).as_deref() == Some("1"),
+    }
+}
+
+// the due date has no time zone, it's in the time zone of the user
+fn submission_status(
+    assignment_info: &Value,
+    revisions: &[Value],
+    drafts: &[Value],
+    tz_offset: f64,
+) -> Value {
+    let due = assignment_info
+        .get_string("due")
+        .and_then(|x| humantime::parse_rfc3339_weak(&x).ok())
+        .and_then(|x| x.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .map(|x| x.as_secs() as i64 - (tz_offset * 3600.0) as i64);
+    let revisions = revisions
+        .iter()
+        .map(|x| {
+            let created = x.get_int("created");
+            json!({
+                "revision_id": x.get_int("revision_id"),
+                "created": created,
+                "seconds_before_due": due.zip(created).map(|(due, created)| due - created),
+                "late": is_flag_set(x, "late"),
+                "draft": is_flag_set(x, "draft"),
+            })
+        })
+        .collect::<Vec<_>>();
+    let latest = revisions.iter().max_by_key(|x| x["created"].as_i64());
+    let created = revisions.iter().filter_map(|x| x["created"].as_i64());
+    let drafts = drafts
+        .iter()
+        .map(|x| {
+            json!({
+                "id": draft_id(x),
+                "saved": x.get_int("last_saved").or(x.get_int("created")),
+            })
+        })
+        .collect::<Vec<_>>();
+    // a draft saved after the last revision was never submitted
+    let unsubmitted_draft = drafts
+        .iter()
+        .filter_map(|x| x["saved"].as_i64())
+        .max()
+        .is_some_and(|saved| latest.is_none_or(|x| x["created"].as_i64() < Some(saved)));
+
+    json!({
+        "due": assignment_info.get_string("due").filter(|x| !x.is_empty()),
+        "allow_dropbox": is_flag_set(assignment_info, "allow_dropbox"),
+        "dropbox_locked": is_flag_set(assignment_info, "dropbox_locked"),
+        "submitted": latest.is_some_and(|x| x["draft"] != true),
+        "late": latest.is_some_and(|x| x["late"] == true),
+        "draft": latest.is_some_and(|x| x["draft"] == true) || unsubmitted_draft,
+        "first_submitted": created.clone().min(),
+        "last_submitted": created.max(),
+        "revisions": revisions,
+        "drafts": drafts,
+    })
+}
+
+fn draft_id(draft: &Value) -> Option<String> {
+    draft
+        .get_string_lossy("id")
+        .or_else(|| draft.get_string_lossy("draft_id"))
+        .or_else(|| draft.get_string_lossy("revision_id"))
+}
+
+// the requests made for a folder item, so they can be bundled ahead of time
+fn item_urls(item: &Value) -> Vec<String> {
+    let item_url = item.get_string("location").unwrap_or_default();
+    match item.get_string("type").as_deref() {
+        Some("folder") => vec![item_url],
+        Some("page" | "document" | "discussion" | "external_tool") => {
+            vec![format!("{item_url}?with_attachments=TRUE&richtext=1")]
+        }
+        Some("assignment" | "assessment_v2") => vec![
+            format!("{item_url}?with_attachments=TRUE&richtext=1"),
+            item_url.replace("assignments/", "grades?assignment_id="),
+        ],
+        _ => vec![],
+    }
+}
+
+fn attachments_size(info: &Value) -> u64 {
+    info.get("attachments")
+        .and_then(|x| x.get("files"))
+        .and_then(|x| x.get_array("file"))
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|x| x.get_string_lossy("filesize")?.parse::<u64>().ok())
+        .sum()
+}
+
+// sums the attachment sizes of the course files without downloading them, the fetched
+// infos stay prefetched for the export itself
+#[async_recursion]
+async fn estimate_directory_size(
+    client: &ClientWithMiddleware,
+    token_info: &TokenInfo,
+    ctx: &ExportContext,
+    directory_info: &Value,
+) -> anyhow::Result<u64> {
+    let items = directory_info.get_array("folder-item").unwrap_or_default();
+    ctx.prefetched
+        .prefetch(client, token_info, items.iter().flat_map(item_urls))
+        .await;
 
-    tokio::fs::write(
-        export_path.join("info.json"),
-        serde_json::to_string_pretty(&info)?,
-    )
-    .await?;
+    let mut size = 0;
+    for item in items {
+        let item_url = item.get_string("location").unwrap_or_default();
+        if item.get_string("type").as_deref() == Some("folder") {
+            if let Ok(folder_info) = ctx.prefetched.peek(client, token_info, &item_url).await {
+                size += estimate_directory_size(client, token_info, ctx, &folder_info).await?;
+            }
+        } else if let Some(info_url) = item_urls(&item).into_iter().next() {
+            if let Ok(item_info) = ctx.prefetched.peek(client, token_info, &info_url).await {
+                size += attachments_size(&item_info);
+            }
+        }
+    }
+    Ok(size)
+}
 
-    tokio::fs::write(
-        export_path.join("picture.png"),
-        client
-            .get(
-                info.get_string("picture_url")
-                    .context("failed to get school/building picture url")?,
-            )
-            .send()
-            .await
-            .context("failed to request school/building picture url")?
-            .bytes()
-            .await?,
+// archived sections can drop out of the section list while their grades are still there
+pub async fn list_courses(
+    client: &ClientWithMiddleware,
+    token_info: &TokenInfo,
+    uid: i64,
+) -> anyhow::Result<Vec<Value>> {
+    let mut courses = get_all_pages(
+        client,
+        token_info,
+        &format!("users/{uid}/sections?include_past=1&limit=200"),
+        "section",
     )
     .await
-    .context("failed to save school/building picture")?;
+    .context("failed to request courses")?;
+    let mut course_ids = courses
+        .iter()
+        .filter_map(|x| x.get_string_lossy("id"))
+        .collect::<BTreeSet<_>>();
 
-    Ok(())
+    let graded_ids = get_optional(client, token_info, &format!("users/{uid}/grades"))
+        .await
+        .context("failed to request grades")?
+        .and_then(|x| x.get_array("section"))
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|x| x.get_string_lossy("section_id"))
+        .collect::<BTreeSet<_>>();
+    for course_id in graded_ids {
+        if course_ids.contains(&course_id) {
+            continue;
+        }
+        match get_optional(client, token_info, &format!("sections/{course_id}"))
+            .await
+            .context("failed to request course")?
+        {
+            Some(course) => {
+                info!("found course {} that isn't in the course list", course_id);
+                course_ids.insert(course_id);
+                courses.push(course);
+            }
+            None => warn!(
+                "course {} has grades but can't be accessed through the api",
+                course_id
+            ),
+        }
+    }
+    Ok(courses)
 }
 
-pub async fn export_user(
-    export_path: PathBuf,
+pub async fn estimate_course_size(
     client: &ClientWithMiddleware,
     token_info: &TokenInfo,
-    user_id: i64,
-) -> anyhow::Result<Value> {
-    info!("exporting user {}", user_id);
-    tokio::fs::create_dir(&export_path)
+    ctx: &ExportContext,
+    course_id: &str,
+) -> anyhow::Result<u64> {
+    let course_files_info = match ctx
+        .prefetched
+        .peek(
+            client,
+            token_info,
+            &format!("{}courses/{course_id}/folder/0", token_info.api_base),
+        )
         .await
-        .context("failed to create user export dir")?;
+    {
+        Ok(course_files_info) => course_files_info,
+        Err(err) if is_denied(&err) => return Ok(0),
+        Err(err) => return Err(err.context("failed to request course files")),
+    };
+    estimate_directory_size(client, token_info, ctx, &course_files_info).await
+}
+
+#[async_recursion]
+async fn outline_directory(
+    client: &ClientWithMiddleware,
+    token_info: &TokenInfo,
+    ctx: &ExportContext,
+    directory_info: &Value,
+) -> anyhow::Result<Vec<Value>> {
+    let mut items = directory_info.get_array("folder-item").unwrap_or_default();
+    let mut next_link = directory_info
+        .get("links")
+        .and_then(|x| x.get_string("next"));
+    while let Some(link) = next_link.take() {
+        let page = get_raw(client, token_info, &link)
+            .await
+            .context("failed to request folder page")?;
+        items.extend(page.get_array("folder-item").unwrap_or_default());
+        next_link = page.get("links").and_then(|x| x.get_string("next"));
+    }
+
+    let mut outline = Vec::new();
+    for item in items {
+        let item_type = item.get_string("type").unwrap_or_default();
+        let mut entry = json!({
+            "id": item.get("id"),
+            "type": item_type,
+            "title": item.get_string("title"),
+        });
+        for key in FOLDER_INDEX_KEYS {
+            if let Some(value) = item.get(*key) {
+                entry[*key] = value.clone();
+            }
+        }
+        if item_type == "folder" {
+            let item_url = item.get_string("location").unwrap_or_default();
+            match ctx.prefetched.get_raw(client, token_info, &item_url).await {
+                Ok(folder_info) => {
+                    entry["children"] = outline_directory(client, token_info, ctx, &folder_info)
+                        .await?
+                        .into();
+                }
+                Err(err) if is_denied(&err) => {
+                    warn!("folder {:?} is not accessible", item.get_string("title"));
+                    entry["denied"] = true.into();
+                }
+                Err(err) => return Err(err.context("failed to request folder")),
+            }
+        }
+        outline.push(entry);
+    }
+    Ok(outline)
+}
+
+fn outline_md(md: &mut String, outline: &[Value], depth: usize) {
+    for entry in outline {
+        let title = entry.get_string("title").unwrap_or_default();
+        let item_type = entry.get_string("type").unwrap_or_default();
+        if entry.get("denied").is_some() {
+            *md += &format!("{}- **{title}** (not accessible)\n", "  ".repeat(depth));
+        } else if item_type == "folder" {
+            *md += &format!("{}- **{title}**\n", "  ".repeat(depth));
+        } else {
+            *md += &format!("{}- {title} ({item_type})\n", "  ".repeat(depth));
+        }
+        if let Some(children) = entry.get("children").and_then(|x| x.as_array()) {
+            outline_md(md, children, depth + 1);
+        }
+    }
+}
+
+// only the folders and the metadata of their items, a quick look at a course before the full export
+pub async fn export_course_outline(
+    export_path: PathBuf,
+    client: &ClientWithMiddleware,
+    token_info: &TokenInfo,
+    ctx: &ExportContext,
+    course: &Value,
+) -> anyhow::Result<()> {
+    let course_started = Instant::now();
+    let course_id = course
+        .get_string("id")
+        .ok_or(ExportError::missing_field("course", "id"))?;
+    let course_dir = export_path.join(&course_id);
+    ctx.storage.create_dir_all(&course_dir).await?;
+    info!("outlining course {}", course_id);
 
-    let user_info = client
-        .execute(Request::get(&format!("users/{}", user_id))?.into_schoology(token_info)?)
+    let outline = match ctx
+        .prefetched
+        .get(client, token_info, &format!("courses/{course_id}/folder/0"))
         .await
-        .context("failed to request user info")?
-        .json::<Value>()
+    {
+        Ok(course_files_info) => {
+            outline_directory(client, token_info, ctx, &course_files_info).await?
+        }
+        Err(err) if is_denied(&err) => {
+            warn!("the folders of course {} are not accessible", course_id);
+            Vec::new()
+        }
+        Err(err) => return Err(err.context("failed to request course files")),
+    };
+
+    let title = match (
+        course.get_string("course_title"),
+        course.get_string("section_title"),
+    ) {
+        (Some(course), Some(section)) => format!("{course}: {section}"),
+        (Some(course), None) => course,
+        _ => course_id.clone(),
+    };
+    let outline_path = course_dir.join("outline.json");
+    ctx.storage
+        .write(
+            &outline_path,
+            serde_json::to_string_pretty(&json!({
+                "id": course_id,
+                "title": title,
+                "items": outline,
+            }))?
+            .into(),
+        )
+        .await?;
+    let mut md = format!("# {title}\n\n");
+    outline_md(&mut md, &outline, 0);
+    ctx.storage
+        .write(&course_dir.join("outline.md"), md.into())
         .await?;
+    ctx.summary
+        .entity("course", &course_id, &outline_path, None, course_started);
+    Ok(())
+}
 
-    tokio::fs::write(
-        export_path.join("user_info.json"),
-        serde_json::to_string_pretty(&user_info)?,
-    )
-    .await?;
+// the feedback on a submission, the comments aren't tied to a revision so every revision gets them
+// next to what was turned in. their attachments are only stored once, in the item directory
+async fn export_submission_feedback(
+    item_directory: &Path,
+    revision_directories: &[PathBuf],
+    client: &ClientWithMiddleware,
+    token_info: &TokenInfo,
+    ctx: &ExportContext,
+    item_id: i64,
+    mut feedback: Value,
+) -> anyhow::Result<()> {
+    let directories = match revision_directories.is_empty() {
+        true => vec![item_directory.to_path_buf()],
+        false => revision_directories.to_vec(),
+    };
+    let feedback_path = directories[0].join("feedback.json");
 
-    tokio::fs::write(
-        export_path.join("user_image.png"),
-        client
-            .get(
-                user_info
-                    .get_string("picture_url")
-                    .context("failed to get user picture url")?,
+    for comment in feedback.get_array("comment").unwrap_or_default() {
+        let comment_id = comment.get_int("id").ok_or_else(|| {
+            ExportError::missing_field_of("feedback comment of assignment", item_id, "id")
+        })?;
+        if let Some(comment_user_id) = comment.get_int("uid") {
+            ctx.queue_user(comment_user_id)?;
+            ctx.people.record(
+                comment_user_id,
+                &feedback_path,
+                "submission comment",
+                comment.get_int("created"),
+            );
+        }
+        export_attachments(
+            &|file_name| item_directory.join(format!("feedback_{comment_id}_{file_name}")),
+            client,
+            token_info,
+            ctx,
+            &comment,
+        )
+        .await?;
+    }
+    ctx.redact(&mut feedback);
+
+    for directory in directories {
+        let prefix = match directory == item_directory {
+            true => "",
+            false => "../",
+        };
+        let mut feedback = feedback.clone();
+        feedback_attachment_paths(&mut feedback, prefix);
+        ctx.storage
+            .write(
+                &directory.join("feedback.json"),
+                serde_json::to_string_pretty(&feedback)?.into(),
             )
-            .send()
-            .await
-            .context("failed to request user picture url")?
-            .bytes()
-            .await?,
+            .await?;
+    }
+
+    Ok(())
+}
+
+// points the attachments of the feedback at the files export_attachments stored, by the names it claims
+fn feedback_attachment_paths(feedback: &mut Value, prefix: &str) {
+    let comments = feedback
+        .get_mut("comment")
+        .and_then(|x| x.as_array_mut())
+        .into_iter()
+        .flatten();
+    for comment in comments {
+        let Some(comment_id) = comment.get_int("id") else {
+            continue;
+        };
+        let mut names = UniqueNames::default();
+        let attachments = comment
+            .pointer_mut("/attachments/files/file")
+            .and_then(|x| x.as_array_mut())
+            .into_iter()
+            .flatten();
+        for attachment in attachments {
+            let (Some(file_id), Some(file_name)) =
+                (attachment.get_int("id"), attachment.get_string("filename"))
+            else {
+                continue;
+            };
+            let name = names.claim(&format!("{file_id}_{file_name}"));
+            attachment["export_path"] = json!(format!("{prefix}feedback_{comment_id}_{name}"));
+        }
+    }
+}
+
+// comments left on the item itself, separate from submission feedback
+async fn export_item_comments(
+    item_directory: &Path,
+    client: &ClientWithMiddleware,
+    token_info: &TokenInfo,
+    ctx: &ExportContext,
+    item_url: &str,
+) -> anyhow::Result<()> {
+    let Some(mut comments) = get_raw_optional(
+        client,
+        token_info,
+        &format!("{item_url}/comments?with_attachments=TRUE&richtext=1"),
     )
     .await
-    .context("failed to save user picture")?;
+    .context("failed to request item comments")?
+    else {
+        return Ok(());
+    };
+    let comment_list = comments.get_array("comment").unwrap_or_default();
+    if comment_list.is_empty() {
+        return Ok(());
+    }
 
-    Ok(user_info)
+    let comments_path = item_directory.join("comments.json");
+    for comment in &comment_list {
+        if let Some(comment_user_id) = comment.get_int("uid") {
+            ctx.queue_user(comment_user_id)?;
+            ctx.people.record(
+                comment_user_id,
+                &comments_path,
+                "item comment",
+                comment.get_int("created"),
+            );
+        }
+    }
+    ctx.redact(&mut comments);
+    ctx.storage
+        .write(
+            &comments_path,
+            serde_json::to_string_pretty(&comments)?.into(),
+        )
+        .await?;
+    for comment in comment_list {
+        let comment_id = comment
+            .get_int("id")
+            .ok_or_else(|| ExportError::missing_field_of("comment of", item_url, "id"))?;
+        export_attachments(
+            &|file_name| item_directory.join(format!("comment_{comment_id}_{file_name}")),
+            client,
+            token_info,
+            ctx,
+            &comment,
+        )
+        .await?;
+    }
+
+    Ok(())
 }
 
-pub async fn export_attachments(
-    export_path_mapper: &(dyn Fn(String) -> PathBuf + Sync + Send),
+// the review is only available when the teacher lets students view their submissions
+async fn export_assessment_attempts(
+    item_directory: &Path,
     client: &ClientWithMiddleware,
     token_info: &TokenInfo,
-    info: &Value,
+    ctx: &ExportContext,
+    section_url: &str,
+    assessment_id: &str,
 ) -> anyhow::Result<()> {
-    if let Some(file_attachments) = info
-        .get("attachments")
-        .and_then(|x| x.get("files"))
-        .and_then(|x| x.get_array("file"))
-    {
-        for attachment in file_attachments {
-            let download_url = attachment
-                .get_string("download_path")
-                .context("failed to get file attachment download path")?;
-            let file_name = attachment
-                .get_string("filename")
-                .context("failed to get file attachment name")?;
-            let file_id = attachment
-                .get_int("id")
-                .context("failed to get file attachment id")?;
-            info!("exporting attachment {:?}", file_name);
-            tokio::fs::write(
-                export_path_mapper(format!("{}_{}", file_id, file_name.replace("/", "_"))),
-                client
-                    .execute(Request::get_raw(&download_url)?.into_schoology(token_info)?)
-                    .await
-                    .context("failed to request file attachment")?
-                    .bytes()
-                    .await?,
+    let assessment_url = format!("{section_url}/assessments/{assessment_id}");
+    let Some(attempts) = get_raw_optional(
+        client,
+        token_info,
+        &format!("{assessment_url}/attempts?user_id={}", ctx.uid),
+    )
+    .await
+    .context("failed to request assessment attempts")?
+    else {
+        return Ok(());
+    };
+
+    for attempt in attempts.get_array("attempt").unwrap_or_default() {
+        let attempt_id = attempt.get_string_lossy("id").ok_or_else(|| {
+            ExportError::missing_field_of("attempt of assessment", assessment_id, "id")
+        })?;
+        info!("exporting assessment attempt {}", attempt_id);
+        let attempt_directory = item_directory.join(format!("attempt_{attempt_id}"));
+        ctx.storage.create_dir_all(&attempt_directory).await?;
+        ctx.storage
+            .write(
+                &attempt_directory.join("info.json"),
+                serde_json::to_string_pretty(&attempt)?.into(),
             )
-            .await
-            .context("failed to save file attachment")?;
+            .await?;
+
+        if let Some(review) = get_raw_optional(
+            client,
+            token_info,
+            &format!("{assessment_url}/attempts/{attempt_id}/review"),
+        )
+        .await
+        .context("failed to request assessment attempt review")?
+        {
+            ctx.storage
+                .write(
+                    &attempt_directory.join("review.json"),
+                    serde_json::to_string_pretty(&review)?.into(),
+                )
+                .await?;
         }
     }
+
     Ok(())
 }
 
+// ordering and visibility of folder items, which are lost in the per-item json
+const FOLDER_INDEX_KEYS: &[&str] = &[
+    "display_weight",
+    "available",
+    "published",
+    "publish_start",
+    "publish_end",
+    "available_start",
+    "available_end",
+    "completion_status",
+    "color",
+];
+
 #[async_recursion]
 pub async fn export_directory(
     export_path: PathBuf,
     client: &ClientWithMiddleware,
     token_info: &TokenInfo,
+    ctx: &ExportContext,
+    completion: &HashMap<i64, Value>,
     directory_info: &Value,
 ) -> anyhow::Result<()> {
-    tokio::fs::create_dir(&export_path).await?;
-    let Some(items) = directory_info.get_array("folder-item") else {
+    ctx.storage.create_dir_all(&export_path).await?;
+    let Some(mut items) = directory_info.get_array("folder-item") else {
         return Ok(());
     };
+    // very large folders are paginated
+    let mut next_link = directory_info
+        .get("links")
+        .and_then(|x| x.get_string("next"));
+    while let Some(link) = next_link.take() {
+        let page = get_raw(client, token_info, &link)
+            .await
+            .context("failed to request folder page")?;
+        items.extend(page.get_array("folder-item").unwrap_or_default());
+        next_link = page.get("links").and_then(|x| x.get_string("next"));
+    }
+    ctx.prefetched
+        .prefetch(client, token_info, items.iter().flat_map(item_urls))
+        .await;
+    let mut names = UniqueNames::default();
+    let mut index = Vec::new();
     for item in items {
-        let item_id = item.get_int("id").context("failed to get item id")?;
+        let item_started = Instant::now();
+        let item_id = item
+            .get_int("id")
+            .ok_or(ExportError::missing_field("item", "id"))?;
         let item_title = item
             .get_string("title")
-            .context("failed to get item title")?;
+            .ok_or_else(|| ExportError::missing_field_of("item", item_id, "title"))?;
         info!("exporting item {:?}", item_title);
 
         let item_url = item
             .get_string("location")
-            .context("failed to get item url")?;
-        let item_directory =
-            export_path.join(format!("{}_{}", item_id, item_title.replace("/", "_")));
+            .ok_or_else(|| ExportError::missing_field_of("item", item_id, "location"))?;
+        let item_name = match ctx.layout {
+            Layout::Title => names.claim(&format!("{item_id}_{item_title}")),
+            Layout::Id => item_id.to_string(),
+        };
+        let item_directory = export_path.join(&item_name);
 
-        match item
+        let item_type = item
             .get_string("type")
-            .context("failed to get item type")?
-            .as_str()
-        {
-            "folder" => {
-                let folder_info = client
-                    .execute(Request::get_raw(&item_url)?.into_schoology(token_info)?)
-                    .await
-                    .context("failed to request folder")?
-                    .json::<Value>()
-                    .await?;
-                export_directory(item_directory, client, token_info, &folder_info).await?;
-            }
-            "page" => {
-                let page_info = client
-                    .execute(
-                        Request::get_raw(&(item_url + "?with_attachments=TRUE&richtext=1"))?
-                            .into_schoology(token_info)?,
-                    )
-                    .await
-                    .context("failed to request page")?
-                    .json::<Value>()
-                    .await?;
-                tokio::fs::create_dir(&item_directory).await?;
-                tokio::fs::write(
-                    item_directory.join("page.html"),
-                    page_info
-                        .get_string("body")
-                        .context("failed to get page body")?,
-                )
-                .await?;
-                tokio::fs::write(
-                    item_directory.join("info.json"),
-                    serde_json::to_string_pretty(&page_info)?,
-                )
-                .await?;
-                export_attachments(
-                    &|file_name| item_directory.join(format!("attachment_{file_name}")),
-                    client,
-                    token_info,
-                    &page_info,
-                )
+            .ok_or_else(|| ExportError::missing_field_of("item", item_id, "type"))?;
+        ctx.exported_items
+            .lock()
+            .unwrap()
+            .insert((item_type.clone(), item_id));
+        if ctx.layout == Layout::Id {
+            ctx.storage.create_dir_all(&item_directory).await?;
+            ctx.storage
+                .write(&item_directory.join("title.txt"), item_title.clone().into())
                 .await?;
+        }
+        let mut index_entry = json!({
+            "position": index.len(),
+            "id": item_id,
+            "type": item_type,
+            "title": item_title,
+            "path": item_name,
+        });
+        for key in FOLDER_INDEX_KEYS {
+            if let Some(value) = item.get(*key) {
+                index_entry[*key] = value.clone();
             }
-            "document" => {
-                let document_info = client
-                    .execute(
-                        Request::get_raw(&(item_url + "?with_attachments=TRUE&richtext=1"))?
-                            .into_schoology(token_info)?,
-                    )
-                    .await
-                    .context("failed to get document info")?
-                    .json::<Value>()
-                    .await?;
-
-                tokio::fs::create_dir(&item_directory).await?;
-                tokio::fs::write(
-                    item_directory.join("info.json"),
-                    serde_json::to_string_pretty(&document_info)?,
-                )
-                .await?;
+        }
+        index.push(index_entry);
+        let result: anyhow::Result<()> = async {
+            match item_type.as_str() {
+                "folder" => {
+                    let folder_info = ctx
+                        .prefetched
+                        .get_raw(client, token_info, &item_url)
+                        .await
+                        .context("failed to request folder")?;
 
-                export_attachments(
-                    &|file_name| item_directory.join(format!("attachment_{file_name}")),
-                    client,
-                    token_info,
-                    &document_info,
-                )
-                .await?;
-            }
-            "assignment" | "assessment_v2" => {
-                let assignment_info = client
-                    .execute(
-                        Request::get_raw(&format!("{item_url}?with_attachments=TRUE&richtext=1"))?
-                            .into_schoology(token_info)?,
+                    // the folder's own fields, the listing entry has the description and color
+                    let mut folder = item.clone();
+                    if let (Value::Object(folder), Value::Object(folder_fields)) =
+                        (&mut folder, &folder_info)
+                    {
+                        for (key, value) in folder_fields {
+                            if key != "folder-item" {
+                                folder.insert(key.clone(), value.clone());
+                            }
+                        }
+                    }
+                    ctx.storage.create_dir_all(&item_directory).await?;
+                    for key in ["body", "description"] {
+                        if let Some(description) = folder.get_string(key) {
+                            folder[key] = export_embedded_files(
+                                &item_directory,
+                                "folder_files",
+                                client,
+                                ctx,
+                                &description,
+                            )
+                            .await?
+                            .into();
+                        }
+                    }
+                    ctx.storage
+                        .write(
+                            &item_directory.join("_folder.json"),
+                            serde_json::to_string_pretty(&folder)?.into(),
+                        )
+                        .await?;
+                    export_attachments(
+                        &|file_name| item_directory.join(format!("attachment_{file_name}")),
+                        client,
+                        token_info,
+                        ctx,
+                        &folder,
                     )
-                    .await
-                    .context("failed to get assignment info")?
-                    .json::<Value>()
                     .await?;
-                tokio::fs::create_dir(&item_directory).await?;
-                tokio::fs::write(
-                    item_directory.join("info.json"),
-                    serde_json::to_string_pretty(&assignment_info)?,
-                )
-                .await?;
 
-                let assignment_submissions = client
-                    .execute(
-                        Request::get_raw(
-                            &(item_url.replace("assignments", "submissions")
-                                + "?with_attachments=TRUE&all_revisions=TRUE"),
-                        )?
-                        .into_schoology(token_info)?,
+                    export_directory(
+                        item_directory.clone(),
+                        client,
+                        token_info,
+                        ctx,
+                        completion,
+                        &folder_info,
                     )
-                    .await
-                    .context("failed to request assignment submissions")?
-                    .json::<Value>()
                     .await?;
-
-                for revision in assignment_submissions
-                    .get_array("revision")
-                    .context("failed to get assignment submissions")?
-                {
-                    let revision_id = revision
-                        .get_int("revision_id")
-                        .context("failed to get assignment submission revision id")?;
-                    info!("exporting revision {}", revision_id);
-
-                    let revision_directory =
-                        item_directory.join(format!("revision_{}", revision_id));
-
-                    tokio::fs::create_dir(&revision_directory).await?;
-                    tokio::fs::write(
-                        revision_directory.join("info.json"),
-                        serde_json::to_string_pretty(&revision)?,
+                }
+                "page" => {
+                    let page_info = ctx
+                        .prefetched
+                        .get_raw(
+                            client,
+                            token_info,
+                            &format!("{item_url}?with_attachments=TRUE&richtext=1"),
+                        )
+                        .await
+                        .context("failed to request page")?;
+                    if let Some(author_id) = page_info.get_int("uid") {
+                        ctx.queue_user(author_id)?;
+                        ctx.people.record(
+                            author_id,
+                            &item_directory.join("info.json"),
+                            "page author",
+                            page_info
+                                .get_int("last_modified")
+                                .or(page_info.get_int("created")),
+                        );
+                    }
+                    let page_body = match page_info.get_string("body") {
+                        Some(body) => body,
+                        None => {
+                            let plain_page_info = get_raw(client, token_info, &item_url)
+                                .await
+                                .context("failed to request plain text page")?;
+                            if let Some(body) = plain_page_info.get_string("body") {
+                                warn!(
+                                    "page {:?} has no rich text body, using plain text",
+                                    item_title
+                                );
+                                body
+                            } else {
+                                warn!("page {:?} has no body", item_title);
+                                String::new()
+                            }
+                        }
+                    };
+                    ctx.storage.create_dir_all(&item_directory).await?;
+                    let page_body = export_embedded_files(
+                        &item_directory,
+                        "page_files",
+                        client,
+                        ctx,
+                        &page_body,
                     )
                     .await?;
-
+                    ctx.storage
+                        .write(&item_directory.join("page.html"), page_body.clone().into())
+                        .await?;
+                    if ctx.format == Format::Markdown {
+                        let document = markdown_document(
+                            &[
+                                ("id", item_id.into()),
+                                ("title", item_title.clone().into()),
+                                (
+                                    "course",
+                                    item_url
+                                        .split("/sections/")
+                                        .nth(1)
+                                        .and_then(|x| x.split('/').next())
+                                        .into(),
+                                ),
+                                ("date", date(page_info.get_int("created"))),
+                            ],
+                            &html_to_markdown(&page_body),
+                        );
+                        ctx.storage
+                            .write(&item_directory.join("page.md"), document.into())
+                            .await?;
+                    }
+                    ctx.render_pdf(&item_directory.join("page.pdf"), &item_title, &page_body)
+                        .await;
+                    ctx.storage
+                        .write(
+                            &item_directory.join("info.json"),
+                            serde_json::to_string_pretty(&page_info)?.into(),
+                        )
+                        .await?;
                     export_attachments(
-                        &|file_name| revision_directory.join(file_name),
+                        &|file_name| item_directory.join(format!("attachment_{file_name}")),
                         client,
                         token_info,
-                        &revision,
+                        ctx,
+                        &page_info,
                     )
                     .await?;
+                    export_item_comments(&item_directory, client, token_info, ctx, &item_url)
+                        .await
+                        .context("failed to export item comments")?;
                 }
+                "document" => {
+                    let document_info = ctx
+                        .prefetched
+                        .get_raw(
+                            client,
+                            token_info,
+                            &format!("{item_url}?with_attachments=TRUE&richtext=1"),
+                        )
+                        .await
+                        .context("failed to request document")?;
 
-                let assignment_grade = client
-                    .execute(
-                        Request::get_raw(
-                            &(item_url.replace("assignments/", "grades?assignment_id=")),
-                        )?
-                        .into_schoology(token_info)?,
-                    )
-                    .await
-                    .context("failed to request assignment grade")?
-                    .json::<Value>()
-                    .await
-                    .context("abc")?;
+                    ctx.storage.create_dir_all(&item_directory).await?;
+                    ctx.storage
+                        .write(
+                            &item_directory.join("info.json"),
+                            serde_json::to_string_pretty(&document_info)?.into(),
+                        )
+                        .await?;
 
-                tokio::fs::write(
-                    item_directory.join("grade.json"),
-                    serde_json::to_string_pretty(&assignment_grade)?,
-                )
-                .await?;
-            }
-            "discussion" => {
-                let discussion_info = client
-                    .execute(
-                        Request::get_raw(
-                            &(format!("{item_url}?with_attachments=TRUE&richtext=1")),
-                        )?
-                        .into_schoology(token_info)?,
+                    export_attachments(
+                        &|file_name| item_directory.join(format!("attachment_{file_name}")),
+                        client,
+                        token_info,
+                        ctx,
+                        &document_info,
                     )
-                    .await
-                    .context("failed to get discussion info")?
-                    .json::<Value>()
                     .await?;
+                    export_links(&item_directory, client, ctx, &document_info).await?;
+                    export_item_comments(&item_directory, client, token_info, ctx, &item_url)
+                        .await
+                        .context("failed to export item comments")?;
+                }
+                "assignment" | "assessment_v2" => {
+                    let assignment_info = ctx
+                        .prefetched
+                        .get_raw(
+                            client,
+                            token_info,
+                            &format!("{item_url}?with_attachments=TRUE&richtext=1"),
+                        )
+                        .await
+                        .context("failed to request assignment")?;
+                    ctx.storage.create_dir_all(&item_directory).await?;
+                    ctx.storage
+                        .write(
+                            &item_directory.join("info.json"),
+                            serde_json::to_string_pretty(&assignment_info)?.into(),
+                        )
+                        .await?;
+                    ctx.timeline
+                        .record(&item_type, item_id, &assignment_info, &item_directory);
+                    if let Some(description) = assignment_info.get_string("description") {
+                        ctx.render_pdf(
+                            &item_directory.join("description.pdf"),
+                            &item_title,
+                            &description,
+                        )
+                        .await;
+                    }
 
-                tokio::fs::create_dir(&item_directory).await?;
-                tokio::fs::write(
-                    item_directory.join("info.json"),
-                    serde_json::to_string_pretty(&discussion_info)?,
-                )
-                .await?;
+                    let assignment_submissions = ctx
+                        .prefetched
+                        .get_raw(
+                            client,
+                            token_info,
+                            &(item_url.replace("assignments", "submissions")
+                                + "?with_attachments=TRUE&all_revisions=TRUE&with_drafts=TRUE"),
+                        )
+                        .await
+                        .context("failed to request assignment submissions")?;
+
+                    let revisions = assignment_submissions
+                        .get_array("revision")
+                        .ok_or_else(|| {
+                            ExportError::missing_field_of("assignment", item_id, "revision")
+                        })?;
+                    // saved in the dropbox but never submitted
+                    let drafts = assignment_submissions
+                        .get_array("draft")
+                        .unwrap_or_default();
+                    let tz_offset = ctx
+                        .prefetched
+                        .peek(
+                            client,
+                            token_info,
+                            &format!("{}users/{}", token_info.api_base, ctx.uid),
+                        )
+                        .await
+                        .ok()
+                        .and_then(|x| x.get_string_lossy("tz_offset"))
+                        .and_then(|x| x.parse::<f64>().ok())
+                        .unwrap_or_default();
+                    ctx.storage
+                        .write(
+                            &item_directory.join("submission_status.json"),
+                            serde_json::to_string_pretty(&submission_status(
+                                &assignment_info,
+                                &revisions,
+                                &drafts,
+                                tz_offset,
+                            ))?
+                            .into(),
+                        )
+                        .await?;
+
+                    let mut revision_directories = Vec::new();
+                    for revision in revisions {
+                        let revision_id = revision
+                            .get_int("revision_id")
+                            .ok_or_else(|| {
+                                ExportError::missing_field_of(
+                                    "revision of assignment",
+                                    item_id,
+                                    "revision_id",
+                                )
+                            })?;
+                        info!("exporting revision {}", revision_id);
+
+                        let revision_directory =
+                            item_directory.join(format!("revision_{}", revision_id));
+
+                        ctx.storage.create_dir_all(&revision_directory).await?;
+                        ctx.storage
+                            .write(
+                                &revision_directory.join("info.json"),
+                                serde_json::to_string_pretty(&revision)?.into(),
+                            )
+                            .await?;
+
+                        export_attachments(
+                            &|file_name| revision_directory.join(file_name),
+                            client,
+                            token_info,
+                            ctx,
+                            &revision,
+                        )
+                        .await?;
+                        export_attachment_renditions(
+                            &revision_directory,
+                            client,
+                            token_info,
+                            ctx,
+                            &revision,
+                        )
+                        .await?;
+                        revision_directories.push(revision_directory);
+                    }
+                    for (idx, draft) in drafts.iter().enumerate() {
+                        let draft_id = draft_id(draft).unwrap_or(idx.to_string());
+                        info!("exporting draft {}", draft_id);
+
+                        let draft_directory = item_directory.join(format!("draft_{draft_id}"));
+                        ctx.storage.create_dir_all(&draft_directory).await?;
+                        ctx.storage
+                            .write(
+                                &draft_directory.join("info.json"),
+                                serde_json::to_string_pretty(draft)?.into(),
+                            )
+                            .await?;
+                        if let Some(body) = draft
+                            .get_string("body")
+                            .or_else(|| draft.get_string("content"))
+                            .filter(|x| !x.is_empty())
+                        {
+                            let body = export_embedded_files(
+                                &draft_directory,
+                                "body_files",
+                                client,
+                                ctx,
+                                &body,
+                            )
+                            .await?;
+                            ctx.storage
+                                .write(&draft_directory.join("body.html"), body.into())
+                                .await?;
+                        }
+
+                        export_attachments(
+                            &|file_name| draft_directory.join(file_name),
+                            client,
+                            token_info,
+                            ctx,
+                            draft,
+                        )
+                        .await?;
+                        export_attachment_renditions(
+                            &draft_directory,
+                            client,
+                            token_info,
+                            ctx,
+                            draft,
+                        )
+                        .await?;
+                    }
 
-                let discussion_replies = client
-                    .execute(
-                        Request::get_raw(
-                            &(item_url + "/comments?with_attachments=TRUE&richtext=1"),
-                        )?
-                        .into_schoology(token_info)?,
+                    if let Some(feedback) = get_raw_optional(
+                        client,
+                        token_info,
+                        &format!(
+                            "{}/{}/comments?with_attachments=TRUE&richtext=1",
+                            item_url.replace("assignments", "submissions"),
+                            ctx.uid
+                        ),
                     )
                     .await
-                    .context("failed to get discussion info")?
-                    .json::<Value>()
-                    .await?;
-                tokio::fs::write(
-                    item_directory.join("replies.json"),
-                    serde_json::to_string_pretty(&discussion_replies)?,
-                )
-                .await?;
-                for reply in discussion_replies
-                    .get_array("comment")
-                    .context("failed to get discussion replies")?
-                {
-                    let reply_id = reply.get_int("id").context("failed to get reply id")?;
-                    export_attachments(
-                        &|file_name| item_directory.join(format!("reply_{reply_id}_{file_name}")),
+                    .context("failed to request submission comments")?
+                    {
+                        export_submission_feedback(
+                            &item_directory,
+                            &revision_directories,
+                            client,
+                            token_info,
+                            ctx,
+                            item_id,
+                            feedback,
+                        )
+                        .await?;
+                    }
+
+                    let assignment_grade = ctx
+                        .prefetched
+                        .get_raw(
+                            client,
+                            token_info,
+                            &item_url.replace("assignments/", "grades?assignment_id="),
+                        )
+                        .await
+                        .context("failed to request assignment grade")?;
+
+                    ctx.storage
+                        .write(
+                            &item_directory.join("grade.json"),
+                            serde_json::to_string_pretty(&assignment_grade)?.into(),
+                        )
+                        .await?;
+                    ctx.storage
+                        .write(
+                            &item_directory.join("grade_history.json"),
+                            serde_json::to_string_pretty(&grade_history(&assignment_grade))?
+                                .into(),
+                        )
+                        .await?;
+                    if let Some(statistics) = grade_statistics(&assignment_grade) {
+                        ctx.storage
+                            .write(
+                                &item_directory.join("statistics.json"),
+                                serde_json::to_string_pretty(&statistics)?.into(),
+                            )
+                            .await?;
+                    }
+
+                    if let (Some(rubric_id), Some((section_url, _))) = (
+                        assignment_info
+                            .get_string_lossy("grading_rubric")
+                            .filter(|x| x != "0"),
+                        item_url.rsplit_once("/assignments/"),
+                    ) {
+                        info!("exporting rubric {}", rubric_id);
+                        let rubric = get_raw_optional(
+                            client,
+                            token_info,
+                            &format!("{section_url}/grading_rubrics/{rubric_id}"),
+                        )
+                        .await
+                        .context("failed to request assignment rubric")?;
+                        let rubric_scores = get_raw_optional(
+                            client,
+                            token_info,
+                            &format!(
+                                "{}&with_rubric=TRUE",
+                                item_url.replace("assignments/", "grades?assignment_id=")
+                            ),
+                        )
+                        .await
+                        .context("failed to request assignment rubric scores")?;
+
+                        ctx.storage
+                            .write(
+                                &item_directory.join("rubric.json"),
+                                serde_json::to_string_pretty(&json!({
+                                    "rubric": rubric,
+                                    "scores": rubric_scores,
+                                }))?
+                                .into(),
+                            )
+                            .await?;
+                    }
+
+                    if item_type == "assessment_v2" {
+                        if let Some((section_url, assessment_id)) =
+                            item_url.rsplit_once("/assignments/")
+                        {
+                            export_assessment_attempts(
+                                &item_directory,
+                                client,
+                                token_info,
+                                ctx,
+                                section_url,
+                                assessment_id,
+                            )
+                            .await
+                            .context("failed to export assessment attempts")?;
+                        }
+                    }
+                    export_item_comments(&item_directory, client, token_info, ctx, &item_url)
+                        .await
+                        .context("failed to export item comments")?;
+                }
+                // the content lives in the tool, so only its launch metadata and grade can be kept
+                "external_tool" => {
+                    let tool_info = ctx
+                        .prefetched
+                        .get_raw(
+                            client,
+                            token_info,
+                            &format!("{item_url}?with_attachments=TRUE&richtext=1"),
+                        )
+                        .await
+                        .context("failed to request external tool")?;
+                    ctx.storage.create_dir_all(&item_directory).await?;
+                    ctx.storage
+                        .write(
+                            &item_directory.join("info.json"),
+                            serde_json::to_string_pretty(&tool_info)?.into(),
+                        )
+                        .await?;
+
+                    if let (Some(grade_item_id), Some((api_url, section_path))) = (
+                        tool_info
+                            .get_string_lossy("grade_item_id")
+                            .filter(|x| x != "0"),
+                        item_url.split_once("/sections/"),
+                    ) {
+                        let section_id = section_path.split('/').next().unwrap_or_default();
+                        if let Some(tool_grade) = get_raw_optional(
+                            client,
+                            token_info,
+                            &format!(
+                                "{api_url}/sections/{section_id}/grades?assignment_id={grade_item_id}"
+                            ),
+                        )
+                        .await
+                        .context("failed to request external tool grade")?
+                        {
+                            ctx.storage
+                                .write(
+                                    &item_directory.join("grade.json"),
+                                    serde_json::to_string_pretty(&tool_grade)?.into(),
+                                )
+                                .await?;
+                        }
+                    }
+                }
+                "discussion" => {
+                    let discussion_info = ctx
+                        .prefetched
+                        .get_raw(
+                            client,
+                            token_info,
+                            &format!("{item_url}?with_attachments=TRUE&richtext=1"),
+                        )
+                        .await
+                        .context("failed to request discussion")?;
+
+                    ctx.storage.create_dir_all(&item_directory).await?;
+                    ctx.storage
+                        .write(
+                            &item_directory.join("info.json"),
+                            serde_json::to_string_pretty(&discussion_info)?.into(),
+                        )
+                        .await?;
+                    ctx.timeline
+                        .record(&item_type, item_id, &discussion_info, &item_directory);
+
+                    // graded discussions have a grade item of their own, like assignments
+                    let discussion_grade = match (
+                        discussion_info
+                            .get_string_lossy("grade_item_id")
+                            .filter(|x| x != "0"),
+                        item_url.split_once("/sections/"),
+                    ) {
+                        (Some(grade_item_id), Some((api_url, section_path))) => {
+                            let section_id = section_path.split('/').next().unwrap_or_default();
+                            get_raw_optional(
+                                client,
+                                token_info,
+                                &format!(
+                                    "{api_url}/sections/{section_id}/grades?assignment_id={grade_item_id}"
+                                ),
+                            )
+                            .await
+                            .context("failed to request discussion grade")?
+                        }
+                        _ => None,
+                    };
+                    if let Some(discussion_grade) = &discussion_grade {
+                        ctx.storage
+                            .write(
+                                &item_directory.join("grade.json"),
+                                serde_json::to_string_pretty(discussion_grade)?.into(),
+                            )
+                            .await?;
+                        ctx.storage
+                            .write(
+                                &item_directory.join("grade_history.json"),
+                                serde_json::to_string_pretty(&grade_history(discussion_grade))?
+                                    .into(),
+                            )
+                            .await?;
+                        if let Some(statistics) = grade_statistics(discussion_grade) {
+                            ctx.storage
+                                .write(
+                                    &item_directory.join("statistics.json"),
+                                    serde_json::to_string_pretty(&statistics)?.into(),
+                                )
+                                .await?;
+                        }
+                    }
+
+                    let mut discussion_replies = get_raw(
                         client,
                         token_info,
-                        &reply,
+                        &(item_url + "/comments?with_attachments=TRUE&richtext=1"),
                     )
-                    .await?;
+                    .await
+                    .context("failed to request discussion replies")?;
+                    let replies_path = item_directory.join("replies.json");
+                    for reply in discussion_replies.get_array("comment").unwrap_or_default() {
+                        if let Some(reply_user_id) = reply.get_int("uid") {
+                            ctx.people.record(
+                                reply_user_id,
+                                &replies_path,
+                                "discussion reply",
+                                reply.get_int("created"),
+                            );
+                        }
+                    }
+                    // the posts the grade is based on, next to the score and feedback
+                    if discussion_grade.is_some() {
+                        let my_posts = discussion_replies
+                            .get_array("comment")
+                            .unwrap_or_default()
+                            .into_iter()
+                            .filter(|x| x.get_int("uid") == Some(ctx.uid))
+                            .collect::<Vec<_>>();
+                        ctx.storage
+                            .write(
+                                &item_directory.join("my_posts.json"),
+                                serde_json::to_string_pretty(&json!({
+                                    "grade": discussion_grade,
+                                    "posts": my_posts,
+                                }))?
+                                .into(),
+                            )
+                            .await?;
+                    }
+                    ctx.redact(&mut discussion_replies);
+                    ctx.storage
+                        .write(
+                            &replies_path,
+                            serde_json::to_string_pretty(&discussion_replies)?.into(),
+                        )
+                        .await?;
+                    for reply in discussion_replies
+                        .get_array("comment")
+                        .ok_or_else(|| {
+                            ExportError::missing_field_of("discussion", item_id, "comment")
+                        })?
+                    {
+                        let reply_id = reply
+                            .get_int("id")
+                            .ok_or_else(|| {
+                                ExportError::missing_field_of("reply of discussion", item_id, "id")
+                            })?;
+                        export_attachments(
+                            &|file_name| {
+                                item_directory.join(format!("reply_{reply_id}_{file_name}"))
+                            },
+                            client,
+                            token_info,
+                            ctx,
+                            &reply,
+                        )
+                        .await?;
+                    }
+                }
+                x => {
+                    error!("item: {:#?}", item);
+                    return Err(ExportError::UnexpectedType {
+                        entity: "item",
+                        id: item_id.to_string(),
+                        kind: x.to_string(),
+                    }
+                    .into());
                 }
             }
-            x => {
-                error!("item: {:#?}", item);
-                return Err(anyhow!("unknown type {:?}", x));
+            Ok(())
+        }
+        .await;
+        if let Err(err) = result {
+            let Some(denied @ ExportError::Denied { url, status }) = err.downcast_ref() else {
+                return Err(err);
+            };
+            warn!("skipping {:?}: {}", item_title, denied);
+            ctx.summary
+                .failure(format!("skipped {item_type} {item_id}: {denied}"));
+            ctx.storage.create_dir_all(&item_directory).await?;
+            let mut denied = json!({
+                "status": status.as_u16(),
+                "url": url,
+                "item": item,
+            });
+            if let Some(web_session) = &ctx.web_session {
+                match export_web_page(
+                    &item_directory,
+                    client,
+                    ctx,
+                    web_session,
+                    &item_type,
+                    item_id,
+                    // moved into the export above
+                    &item.get_string("location").unwrap_or_default(),
+                )
+                .await
+                {
+                    Ok(true) => denied["web_fallback"] = json!("web.html"),
+                    Ok(false) => {}
+                    Err(err) => {
+                        warn!(
+                            "failed to export the web page of {:?}, is the session cookie valid? {:#}",
+                            item_title, err
+                        );
+                    }
+                }
             }
+            ctx.storage
+                .write(
+                    &item_directory.join("denied.json"),
+                    serde_json::to_string_pretty(&denied)?.into(),
+                )
+                .await?;
+            continue;
         }
+        if let Some(item_completion) = completion.get(&item_id) {
+            ctx.storage
+                .write(
+                    &item_directory.join("completion.json"),
+                    serde_json::to_string_pretty(item_completion)?.into(),
+                )
+                .await?;
+        }
+        ctx.summary
+            .entity(&item_type, item_id, &item_directory, None, item_started);
     }
+    ctx.storage
+        .write(
+            &export_path.join("folder_index.json"),
+            serde_json::to_string_pretty(&index)?.into(),
+        )
+        .await?;
     Ok(())
 }
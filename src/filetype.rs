@@ -0,0 +1,132 @@
+use reqwest::header::{HeaderMap, CONTENT_DISPOSITION, CONTENT_TYPE};
+
+use crate::sanitize::sanitize_filename;
+
+// file signatures that can't be mistaken for anything else
+const SIGNATURES: &[(&[u8], &str)] = &[
+    (b"%PDF-", "pdf"),
+    (b"\x89PNG\r\n\x1a\n", "png"),
+    (b"\xff\xd8\xff", "jpg"),
+    (b"GIF87a", "gif"),
+    (b"GIF89a", "gif"),
+    (b"{\\rtf", "rtf"),
+];
+
+const CONTENT_TYPES: &[(&str, &str)] = &[
+    ("application/pdf", "pdf"),
+    ("image/png", "png"),
+    ("image/jpeg", "jpg"),
+    ("image/gif", "gif"),
+    ("image/webp", "webp"),
+    ("image/svg+xml", "svg"),
+    ("text/plain", "txt"),
+    ("text/csv", "csv"),
+    ("text/html", "html"),
+    ("application/rtf", "rtf"),
+    ("application/zip", "zip"),
+    ("application/msword", "doc"),
+    ("application/vnd.ms-excel", "xls"),
+    ("application/vnd.ms-powerpoint", "ppt"),
+    (
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        "docx",
+    ),
+    (
+        "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        "xlsx",
+    ),
+    (
+        "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+        "pptx",
+    ),
+    ("audio/mpeg", "mp3"),
+    ("video/mp4", "mp4"),
+    ("video/quicktime", "mov"),
+];
+
+// the extensions of the signatures above, only these are replaced when the content disagrees
+const SIGNATURE_EXTENSIONS: &[&str] = &["pdf", "png", "jpg", "jpeg", "gif", "rtf"];
+
+fn extension(name: &str) -> Option<String> {
+    let (stem, extension) = name.rsplit_once('.')?;
+    (!stem.is_empty()
+        && (1..=5).contains(&extension.len())
+        && extension.chars().all(|x| x.is_ascii_alphanumeric()))
+    .then(|| extension.to_ascii_lowercase())
+}
+
+fn same_type(a: &str, b: &str) -> bool {
+    a == b || matches!((a, b), ("jpg", "jpeg") | ("jpeg", "jpg"))
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+    while idx < bytes.len() {
+        match (bytes[idx], text.get(idx + 1..idx + 3)) {
+            (b'%', Some(hex)) if u8::from_str_radix(hex, 16).is_ok() => {
+                decoded.push(u8::from_str_radix(hex, 16).unwrap_or_default());
+                idx += 3;
+            }
+            (x, _) => {
+                decoded.push(x);
+                idx += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+// filename*=UTF-8''... wins over filename="..."
+fn disposition_filename(disposition: &str) -> Option<String> {
+    let params = disposition
+        .split(';')
+        .filter_map(|x| x.split_once('='))
+        .map(|(key, value)| (key.trim().to_ascii_lowercase(), value.trim()))
+        .collect::<Vec<_>>();
+    if let Some((_, value)) = params.iter().find(|(key, _)| key == "filename*") {
+        if let Some((_, encoded)) = value.split_once("''") {
+            return Some(percent_decode(encoded));
+        }
+    }
+    params
+        .iter()
+        .find(|(key, _)| key == "filename")
+        .map(|(_, value)| value.trim_matches('"').to_string())
+}
+
+// the name an attachment should be saved as when its extension is missing or contradicts the content
+pub fn corrected_name(name: &str, headers: &HeaderMap, head: &[u8]) -> Option<String> {
+    let sniffed = SIGNATURES
+        .iter()
+        .find(|(signature, _)| head.starts_with(signature))
+        .map(|(_, extension)| extension.to_string());
+    let current = extension(name);
+    match (&current, &sniffed) {
+        (Some(current), Some(sniffed))
+            if SIGNATURE_EXTENSIONS.contains(&current.as_str()) && !same_type(current, sniffed) =>
+        {
+            let stem = &name[..name.len() - current.len() - 1];
+            return Some(format!("{stem}.{sniffed}"));
+        }
+        (Some(_), _) => return None,
+        (None, _) => {}
+    }
+
+    let extension = headers
+        .get(CONTENT_DISPOSITION)
+        .and_then(|x| x.to_str().ok())
+        .and_then(disposition_filename)
+        .and_then(|x| extension(&x))
+        .or(sniffed)
+        .or_else(|| {
+            let content_type = headers.get(CONTENT_TYPE)?.to_str().ok()?;
+            let mime = content_type.split(';').next()?.trim().to_ascii_lowercase();
+            CONTENT_TYPES
+                .iter()
+                .find(|(x, _)| *x == mime)
+                .map(|(_, extension)| extension.to_string())
+        })?;
+    Some(format!("{name}.{}", sanitize_filename(&extension)))
+}
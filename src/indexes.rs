@@ -0,0 +1,141 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use log::info;
+use serde_json::{json, Value};
+
+use crate::{compress::read_json, ValueHelper};
+
+// the files a directory of an entity describes it with, in the order they are looked at
+const INFO_FILES: &[&str] = &["info.json", "user_info.json", "_folder.json", "period.json"];
+
+fn title(info: &Value) -> Option<String> {
+    info.get_string("title")
+        .or_else(|| info.get_string("name_display"))
+        .or_else(|| info.get_string("subject"))
+        .or_else(|| {
+            let course = info.get_string("course_title")?;
+            Some(match info.get_string("section_title") {
+                Some(section) => format!("{course}: {section}"),
+                None => course,
+            })
+        })
+}
+
+// display name, due date and author of an entity directory, None if it isn't one
+fn entry(dir: &Path, names: &HashMap<String, String>) -> Option<Value> {
+    let info = INFO_FILES
+        .iter()
+        .find_map(|x| read_json(&dir.join(x)).filter(|x| x.is_object()));
+    let title_txt = std::fs::read_to_string(dir.join("title.txt"))
+        .ok()
+        .map(|x| x.trim().to_string());
+    if info.is_none() && title_txt.is_none() {
+        return None;
+    }
+    let info = info.unwrap_or_default();
+    let name = dir.file_name()?.to_string_lossy().to_string();
+    let author_id = info
+        .get_string_lossy("uid")
+        .or_else(|| info.get_string_lossy("created_by"))
+        .filter(|x| x != "0");
+    Some(json!({
+        "id": info.get("id").cloned().unwrap_or(name.clone().into()),
+        "title": title_txt.or_else(|| title(&info)),
+        "due": info.get_string("due").filter(|x| !x.is_empty()),
+        "author_id": author_id,
+        "author": author_id.as_ref().and_then(|x| names.get(x)),
+        "path": name,
+    }))
+}
+
+fn cell(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(x) => x.replace('|', "\\|").replace('\n', " "),
+        x => x.to_string(),
+    }
+}
+
+fn index_md(entries: &[Value]) -> String {
+    let mut md = String::from("| id | title | due | author |\n| --- | --- | --- | --- |\n");
+    for entry in entries {
+        md += &format!(
+            "| [{}]({}/) | {} | {} | {} |\n",
+            cell(&entry["id"]),
+            entry["path"]
+                .as_str()
+                .unwrap_or_default()
+                .replace(' ', "%20"),
+            cell(&entry["title"]),
+            cell(&entry["due"]),
+            cell(&entry["author"]),
+        );
+    }
+    md
+}
+
+fn subdirs(dir: &Path) -> Vec<PathBuf> {
+    let mut dirs = std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|x| x.file_type().is_ok_and(|x| x.is_dir()))
+        .map(|x| x.path())
+        .collect::<Vec<_>>();
+    dirs.sort();
+    dirs
+}
+
+fn write_dir_indexes(
+    dir: &Path,
+    names: &HashMap<String, String>,
+    written: &mut usize,
+) -> anyhow::Result<()> {
+    let dirs = subdirs(dir);
+    let entries = dirs
+        .iter()
+        .filter_map(|x| entry(x, names))
+        .collect::<Vec<_>>();
+    // users/index.json of the people directory is already an index of its own
+    let taken = read_json(&dir.join("index.json")).is_some_and(|x| !x.is_array());
+    if !entries.is_empty() && !taken {
+        std::fs::write(
+            dir.join("index.json"),
+            serde_json::to_string_pretty(&entries)?,
+        )
+        .with_context(|| format!("failed to write index of {:?}", dir))?;
+        std::fs::write(dir.join("index.md"), index_md(&entries))
+            .with_context(|| format!("failed to write index of {:?}", dir))?;
+        *written += 1;
+    }
+    for dir in dirs {
+        write_dir_indexes(&dir, names, written)?;
+    }
+    Ok(())
+}
+
+fn write_indexes_blocking(export_dir: &Path) -> anyhow::Result<()> {
+    let names = read_json(&export_dir.join("users").join("index.json"))
+        .and_then(|x| x.as_object().cloned())
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|(uid, user)| Some((uid, user.get_string("name")?)))
+        .collect::<HashMap<_, _>>();
+    let mut written = 0;
+    // the top level directories have names of their own
+    for dir in subdirs(export_dir) {
+        write_dir_indexes(&dir, &names, &mut written)?;
+    }
+    info!("wrote the index of {} directories", written);
+    Ok(())
+}
+
+// index.json and index.md in every directory of entities named by id, with their titles,
+// due dates and authors so the tree can be browsed by hand
+pub async fn write_indexes(export_dir: PathBuf) -> anyhow::Result<()> {
+    tokio::task::spawn_blocking(move || write_indexes_blocking(&export_dir)).await?
+}
@@ -0,0 +1,258 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context};
+use clap::Args;
+
+use crate::{
+    compress::{self, read_json},
+    list::print_table,
+    manifest::ManifestEntry,
+    storage::{LocalStorage, Storage},
+    ValueHelper,
+};
+
+#[derive(Args, Clone)]
+#[group(required = true, multiple = false)]
+pub struct InspectQuery {
+    /// List the exported courses with their items and attachments
+    #[arg(long)]
+    courses: bool,
+    /// Count the exported items, attachments and failures
+    #[arg(long)]
+    stats: bool,
+    /// List the files mentioning a term, case insensitive
+    #[arg(long, value_name = "TERM")]
+    search: Option<String>,
+}
+
+// everything below the directory, relative to it
+pub fn export_files(export_dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut dirs = vec![export_dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            match entry.file_type() {
+                Ok(x) if x.is_dir() => dirs.push(entry.path()),
+                // compressed json is listed as the file it stands for
+                Ok(_) => files.push(compress::uncompressed_path(
+                    entry
+                        .path()
+                        .strip_prefix(export_dir)
+                        .unwrap_or(&entry.path()),
+                )),
+                Err(_) => {}
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+fn read_manifest(export_dir: &Path) -> Vec<ManifestEntry> {
+    std::fs::read_to_string(export_dir.join("manifest.json"))
+        .ok()
+        .and_then(|x| serde_json::from_str(&x).ok())
+        .unwrap_or_default()
+}
+
+// folder items by type, counted from the folder indexes
+fn count_items<'a>(
+    files: impl Iterator<Item = &'a PathBuf>,
+    export_dir: &Path,
+) -> BTreeMap<String, u64> {
+    let mut counts = BTreeMap::new();
+    for path in files.filter(|x| x.ends_with("folder_index.json")) {
+        let items = read_json(&export_dir.join(path))
+            .and_then(|x| x.as_array().cloned())
+            .unwrap_or_default();
+        for item in items {
+            *counts
+                .entry(item.get_string("type").unwrap_or_default())
+                .or_default() += 1;
+        }
+    }
+    counts
+}
+
+fn courses(export_dir: &Path) -> anyhow::Result<()> {
+    let courses = read_json(&export_dir.join("courses").join("info.json"))
+        .context("no courses in the export")?;
+    let files = export_files(export_dir);
+    let manifest = read_manifest(export_dir);
+    let rows = courses
+        .get_array("section")
+        .unwrap_or_default()
+        .into_iter()
+        .map(|course| {
+            let course_id = course.get_string_lossy("id").unwrap_or_default();
+            let course_dir = Path::new("courses").join(&course_id);
+            let items = count_items(
+                files.iter().filter(|x| x.starts_with(&course_dir)),
+                export_dir,
+            );
+            let (attachments, bytes) = manifest
+                .iter()
+                .filter(|x| x.path.starts_with(&course_dir))
+                .fold((0, 0), |(count, bytes), x| (count + 1, bytes + x.bytes));
+            [
+                course_id,
+                format!(
+                    "{}: {}",
+                    course.get_string("course_title").unwrap_or_default(),
+                    course.get_string("section_title").unwrap_or_default()
+                ),
+                items.values().sum::<u64>().to_string(),
+                items
+                    .get("assignment")
+                    .copied()
+                    .unwrap_or_default()
+                    .to_string(),
+                format!("{attachments} ({})", format_bytes(bytes)),
+            ]
+        })
+        .collect();
+    print_table(["ID", "TITLE", "ITEMS", "ASSIGNMENTS", "ATTACHMENTS"], rows);
+    Ok(())
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    match bytes {
+        0..1_000 => format!("{bytes} B"),
+        1_000..1_000_000 => format!("{:.1} kB", bytes as f64 / 1e3),
+        1_000_000..1_000_000_000 => format!("{:.1} MB", bytes as f64 / 1e6),
+        _ => format!("{:.1} GB", bytes as f64 / 1e9),
+    }
+}
+
+fn stats(export_dir: &Path) -> anyhow::Result<()> {
+    let files = export_files(export_dir);
+    let manifest = read_manifest(export_dir);
+    let mut rows = vec![
+        [
+            "courses".to_string(),
+            read_json(&export_dir.join("courses").join("info.json"))
+                .and_then(|x| x.get_array("section"))
+                .unwrap_or_default()
+                .len()
+                .to_string(),
+        ],
+        [
+            "updates".to_string(),
+            files
+                .iter()
+                .filter(|x| x.starts_with("updates") && x.extension().is_some_and(|x| x == "json"))
+                .filter_map(|x| read_json(&export_dir.join(x)))
+                .map(|x| x.get_array("update").unwrap_or_default().len())
+                .sum::<usize>()
+                .to_string(),
+        ],
+        [
+            "messages".to_string(),
+            read_json(&export_dir.join("messages").join("mailbox.json"))
+                .and_then(|x| x.as_array().map(|x| x.len()))
+                .unwrap_or_default()
+                .to_string(),
+        ],
+        [
+            "users".to_string(),
+            std::fs::read_dir(export_dir.join("users"))
+                .map(|x| x.flatten().filter(|x| x.path().is_dir()).count())
+                .unwrap_or_default()
+                .to_string(),
+        ],
+    ];
+    for (item_type, count) in count_items(files.iter(), export_dir) {
+        rows.push([item_type, count.to_string()]);
+    }
+    rows.push([
+        "attachments".to_string(),
+        format!(
+            "{} ({})",
+            manifest.len(),
+            format_bytes(manifest.iter().map(|x| x.bytes).sum())
+        ),
+    ]);
+    rows.push(["files".to_string(), files.len().to_string()]);
+    rows.push([
+        "size".to_string(),
+        format_bytes(LocalStorage.size(export_dir)),
+    ]);
+    if let Some(summary) = read_json(&export_dir.join("summary.json")) {
+        rows.push([
+            "failures".to_string(),
+            summary
+                .get_array("failures")
+                .unwrap_or_default()
+                .len()
+                .to_string(),
+        ]);
+    }
+    print_table(["", "COUNT"], rows);
+    Ok(())
+}
+
+const SEARCHED_EXTENSIONS: &[&str] = &["json", "html", "md", "txt", "csv"];
+
+fn search(export_dir: &Path, term: &str) -> anyhow::Result<()> {
+    let term = term.to_lowercase();
+    for path in export_files(export_dir) {
+        if !path
+            .extension()
+            .is_some_and(|x| SEARCHED_EXTENSIONS.iter().any(|y| x == *y))
+        {
+            continue;
+        }
+        let Ok(contents) = compress::read_to_string(&export_dir.join(&path)) else {
+            continue;
+        };
+        for line in contents.lines() {
+            let lowercase = line.to_lowercase();
+            let Some(position) = lowercase.find(&term) else {
+                continue;
+            };
+            // a bit of the line around the match, long html lines would flood the terminal
+            let start = lowercase[..position]
+                .char_indices()
+                .rev()
+                .nth(40)
+                .map_or(0, |(x, _)| x);
+            let end = lowercase[position..]
+                .char_indices()
+                .nth(term.chars().count() + 40)
+                .map_or(lowercase.len(), |(x, _)| position + x);
+            println!(
+                "{}: {}",
+                path.display(),
+                // lowercasing can change the length of a few characters
+                line.get(start..end)
+                    .filter(|_| line.len() == lowercase.len())
+                    .or(lowercase.get(start..end))
+                    .unwrap_or_default()
+                    .trim()
+            );
+            break;
+        }
+    }
+    Ok(())
+}
+
+pub fn inspect(export_dir: &Path, query: &InspectQuery) -> anyhow::Result<()> {
+    if !export_dir.is_dir() {
+        return Err(anyhow!("{:?} is not a directory", export_dir));
+    }
+    if query.courses {
+        courses(export_dir)
+    } else if query.stats {
+        stats(export_dir)
+    } else if let Some(term) = &query.search {
+        search(export_dir, term)
+    } else {
+        Ok(())
+    }
+}
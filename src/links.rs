@@ -0,0 +1,338 @@
+use std::{path::Path, time::Instant};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use log::{info, warn};
+use reqwest::{header::CONTENT_TYPE, Url};
+use reqwest_middleware::ClientWithMiddleware;
+use serde_json::{json, Value};
+
+use crate::{
+    context::ExportContext,
+    error::ExportError,
+    richtext::{attribute_value, decode_entities},
+    sanitize::{sanitize_filename, UniqueNames},
+    ValueHelper,
+};
+
+// a page with hundreds of images would otherwise turn into a huge snapshot
+const MAX_INLINED_RESOURCES: usize = 50;
+const MAX_INLINED_BYTES: usize = 5_000_000;
+
+// video hosts with an oembed endpoint that works without an api key
+fn video_embed(url: &str) -> Option<(&'static str, String, Url)> {
+    let parsed = Url::parse(url).ok()?;
+    let host = parsed.host_str()?.trim_start_matches("www.");
+    let mut segments = parsed.path_segments()?.filter(|x| !x.is_empty());
+    let (provider, video_id, oembed) = match host {
+        "youtube.com" | "m.youtube.com" | "youtube-nocookie.com" => {
+            let video_id = match segments.next()? {
+                "watch" => parsed
+                    .query_pairs()
+                    .find(|(key, _)| key == "v")
+                    .map(|(_, value)| value.to_string())?,
+                "embed" | "shorts" | "live" | "v" => segments.next()?.to_string(),
+                _ => return None,
+            };
+            ("youtube", video_id, "https://www.youtube.com/oembed")
+        }
+        "youtu.be" => (
+            "youtube",
+            segments.next()?.to_string(),
+            "https://www.youtube.com/oembed",
+        ),
+        "vimeo.com" => (
+            "vimeo",
+            segments
+                .find(|x| x.chars().all(|x| x.is_ascii_digit()))?
+                .to_string(),
+            "https://vimeo.com/api/oembed.json",
+        ),
+        "player.vimeo.com" => (
+            "vimeo",
+            segments.nth(1)?.to_string(),
+            "https://vimeo.com/api/oembed.json",
+        ),
+        _ => return None,
+    };
+    let oembed = Url::parse_with_params(oembed, [("format", "json"), ("url", url)]).ok()?;
+    Some((provider, video_id, oembed))
+}
+
+async fn fetch(
+    client: &ClientWithMiddleware,
+    ctx: &ExportContext,
+    url: &str,
+) -> anyhow::Result<(Option<String>, Vec<u8>)> {
+    let response = client.get(url).send().await?;
+    if !response.status().is_success() {
+        return Err(ExportError::Status {
+            url: url.to_string(),
+            status: response.status(),
+        }
+        .into());
+    }
+    let content_type = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|x| x.to_str().ok())
+        .map(|x| x.to_ascii_lowercase());
+    let body = response.bytes().await?;
+    if let Some(limiter) = &ctx.limiter {
+        limiter.acquire(body.len()).await;
+    }
+    Ok((content_type, body.into()))
+}
+
+// drops everything between the tags, scripts only break or phone home in an archived copy
+fn strip_elements(html: &str, tag_name: &str) -> String {
+    let lower = html.to_ascii_lowercase();
+    let (open, close) = (format!("<{tag_name}"), format!("</{tag_name}"));
+    let mut stripped = String::with_capacity(html.len());
+    let mut copied_to = 0;
+    let mut search_from = 0;
+    while let Some(idx) = lower[search_from..].find(&open) {
+        let start = search_from + idx;
+        let end = lower[start..]
+            .find(&close)
+            .and_then(|x| lower[start + x..].find('>').map(|y| start + x + y + 1))
+            .unwrap_or(lower.len());
+        stripped.push_str(&html[copied_to..start]);
+        copied_to = end;
+        search_from = end;
+    }
+    stripped.push_str(&html[copied_to..]);
+    stripped
+}
+
+// the stylesheets and images of a page, inlined so the snapshot is a single file
+async fn snapshot_html(
+    client: &ClientWithMiddleware,
+    ctx: &ExportContext,
+    url: &Url,
+    html: &str,
+) -> String {
+    let html = strip_elements(html, "script");
+    let lower = html.to_ascii_lowercase();
+    let mut snapshot = String::with_capacity(html.len());
+    let mut copied_to = 0;
+    let mut search_from = 0;
+    let mut inlined = 0;
+    while let Some(idx) = lower[search_from..].find('<') {
+        let start = search_from + idx;
+        let end = lower[start..]
+            .find('>')
+            .map(|x| start + x + 1)
+            .unwrap_or(lower.len());
+        search_from = end;
+
+        let tag = &html[start..end];
+        let tag_name = lower[start + 1..end]
+            .split(|x: char| x.is_whitespace() || x == '/' || x == '>')
+            .next()
+            .unwrap_or_default();
+        let is_stylesheet = tag_name == "link"
+            && attribute_value(tag, "rel")
+                .is_some_and(|x| x.to_ascii_lowercase().contains("stylesheet"));
+        let attribute = match tag_name {
+            "img" => "src",
+            _ if is_stylesheet => "href",
+            _ => continue,
+        };
+        let Some(resource_url) = attribute_value(tag, attribute)
+            .filter(|x| !x.starts_with("data:"))
+            .and_then(|x| url.join(&decode_entities(&x)).ok())
+        else {
+            continue;
+        };
+        if inlined >= MAX_INLINED_RESOURCES {
+            break;
+        }
+        inlined += 1;
+
+        let (content_type, body) = match fetch(client, ctx, resource_url.as_str()).await {
+            Ok(x) if x.1.len() <= MAX_INLINED_BYTES => x,
+            Ok(_) => continue,
+            Err(err) => {
+                warn!("failed to archive {:?}: {:#}", resource_url.as_str(), err);
+                continue;
+            }
+        };
+        snapshot.push_str(&html[copied_to..start]);
+        copied_to = end;
+        if is_stylesheet {
+            snapshot.push_str(&format!(
+                "<style>/* {} */\n{}</style>",
+                resource_url,
+                String::from_utf8_lossy(&body)
+            ));
+        } else {
+            let data_url = format!(
+                "data:{};base64,{}",
+                content_type
+                    .as_deref()
+                    .unwrap_or("application/octet-stream"),
+                STANDARD.encode(&body)
+            );
+            snapshot.push_str(&tag.replacen(
+                &attribute_value(tag, "src").unwrap_or_default(),
+                &data_url,
+                1,
+            ));
+        }
+    }
+    snapshot.push_str(&html[copied_to..]);
+
+    // links that weren't inlined still point to the original site
+    let base = format!(
+        "<!-- archived from {} on {} -->\n<base href=\"{}\">",
+        url,
+        humantime::format_rfc3339_seconds(std::time::SystemTime::now()),
+        url
+    );
+    let head = snapshot
+        .to_ascii_lowercase()
+        .match_indices("<head")
+        .map(|(x, _)| x)
+        .find(|x| snapshot[x + 5..].starts_with(|x: char| x == '>' || x.is_ascii_whitespace()));
+    match head {
+        Some(head) => {
+            let head_end = snapshot[head..]
+                .find('>')
+                .map_or(snapshot.len(), |x| head + x + 1);
+            snapshot.insert_str(head_end, &base);
+            snapshot
+        }
+        None => format!("{base}\n{snapshot}"),
+    }
+}
+
+// the archived copy of a link, or the file it points to if it isn't a web page
+async fn archive_link(
+    links_dir: &Path,
+    name: &str,
+    client: &ClientWithMiddleware,
+    ctx: &ExportContext,
+    url: &str,
+) -> anyhow::Result<(String, u64)> {
+    let parsed = Url::parse(url)?;
+    let (content_type, body) = fetch(client, ctx, url).await?;
+    let (file_name, contents) = match content_type {
+        Some(x) if x.starts_with("text/html") => (
+            format!("{name}.html"),
+            snapshot_html(client, ctx, &parsed, &String::from_utf8_lossy(&body))
+                .await
+                .into_bytes(),
+        ),
+        _ => {
+            let extension = parsed
+                .path_segments()
+                .and_then(|mut x| x.next_back())
+                .and_then(|x| x.rsplit_once('.'))
+                .map(|(_, x)| sanitize_filename(x))
+                .unwrap_or_else(|| "bin".to_string());
+            (format!("{name}.{extension}"), body)
+        }
+    };
+    let bytes = contents.len() as u64;
+    ctx.storage
+        .write(&links_dir.join(&file_name), contents)
+        .await?;
+    Ok((file_name, bytes))
+}
+
+// web links and video embeds of a folder item, archived if content.archive_links is set
+pub async fn export_links(
+    export_path: &Path,
+    client: &ClientWithMiddleware,
+    ctx: &ExportContext,
+    info: &Value,
+) -> anyhow::Result<()> {
+    let attachments = info.get("attachments");
+    let links = ["links", "videos"]
+        .into_iter()
+        .flat_map(|kind| {
+            attachments
+                .and_then(|x| x.get(kind))
+                .and_then(|x| x.get_array(kind.trim_end_matches('s')))
+                .unwrap_or_default()
+        })
+        .collect::<Vec<_>>();
+    if links.is_empty() {
+        return Ok(());
+    }
+
+    let links_dir = export_path.join("links");
+    if ctx.config.content.archive_links {
+        ctx.storage.create_dir_all(&links_dir).await?;
+    }
+    let mut names = UniqueNames::default();
+    let mut index = Vec::new();
+    for link in links {
+        let Some(url) = link.get_string("url") else {
+            continue;
+        };
+        let title = link.get_string("title").unwrap_or_default();
+        let mut entry = json!({
+            "id": link.get("id"),
+            "url": url,
+            "title": title,
+        });
+        let embed = video_embed(&url);
+        if let Some((provider, video_id, _)) = &embed {
+            entry["provider"] = json!(provider);
+            entry["video_id"] = json!(video_id);
+        }
+        if !ctx.config.content.archive_links {
+            index.push(entry);
+            continue;
+        }
+
+        let started = Instant::now();
+        if let Some((_, _, oembed)) = &embed {
+            match fetch(client, ctx, oembed.as_str())
+                .await
+                .and_then(|(_, x)| serde_json::from_slice::<Value>(&x).map_err(anyhow::Error::from))
+            {
+                Ok(metadata) => entry["metadata"] = metadata,
+                Err(err) => {
+                    warn!("failed to request video metadata of {:?}: {:#}", url, err);
+                    entry["error"] = json!(format!("{err:#}"));
+                }
+            }
+            index.push(entry);
+            continue;
+        }
+
+        info!("archiving link {:?}", url);
+        let name = names.claim(match title.is_empty() {
+            true => "link",
+            false => &title,
+        });
+        match archive_link(&links_dir, &name, client, ctx, &url).await {
+            Ok((file_name, bytes)) => {
+                ctx.summary.entity(
+                    "link",
+                    &url,
+                    &links_dir.join(&file_name),
+                    Some(bytes),
+                    started,
+                );
+                entry["archived"] = json!(format!("links/{file_name}"));
+            }
+            // dead links are the reason for archiving, they aren't worth failing the item over
+            Err(err) => {
+                warn!("failed to archive {:?}: {:#}", url, err);
+                ctx.summary
+                    .failure(format!("failed to archive link {url:?}: {err:#}"));
+                entry["error"] = json!(format!("{err:#}"));
+            }
+        }
+        index.push(entry);
+    }
+    ctx.storage
+        .write(
+            &export_path.join("links.json"),
+            serde_json::to_string_pretty(&index)?.into(),
+        )
+        .await
+}
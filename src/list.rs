@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+
+use anyhow::Context;
+use clap::ValueEnum;
+use reqwest_middleware::ClientWithMiddleware;
+
+use crate::{
+    api_helpers::{get, get_all_pages, get_optional},
+    export::list_courses,
+    TokenInfo, ValueHelper,
+};
+
+#[derive(ValueEnum, Clone, Copy)]
+pub enum ListKind {
+    Courses,
+    Groups,
+    Messages,
+}
+
+pub fn print_table<const N: usize>(header: [&str; N], rows: Vec<[String; N]>) {
+    let header = header.map(|x| x.to_string());
+    let mut widths = [0; N];
+    for row in std::iter::once(&header).chain(&rows) {
+        for (width, column) in widths.iter_mut().zip(row) {
+            *width = (*width).max(column.chars().count());
+        }
+    }
+    for row in std::iter::once(&header).chain(&rows) {
+        let line = row
+            .iter()
+            .zip(widths)
+            .map(|(column, width)| format!("{column:width$}"))
+            .collect::<Vec<_>>()
+            .join("  ");
+        println!("{}", line.trim_end());
+    }
+}
+
+pub async fn list(
+    client: &ClientWithMiddleware,
+    token_info: &TokenInfo,
+    kind: ListKind,
+) -> anyhow::Result<()> {
+    let uid = get(client, token_info, "app-user-info")
+        .await
+        .context("failed to request uid")?
+        .get_int("api_uid")
+        .context("failed to get uid")?;
+
+    let rows = match kind {
+        ListKind::Courses => {
+            let terms = get_optional(client, token_info, "gradingperiods?limit=200")
+                .await
+                .context("failed to request grading periods")?
+                .and_then(|x| x.get_array("gradingperiods"))
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|x| Some((x.get_string_lossy("id")?, x.get_string("title")?)))
+                .collect::<HashMap<_, _>>();
+
+            list_courses(client, token_info, uid)
+                .await?
+                .into_iter()
+                .map(|x| {
+                    let term = x
+                        .get("grading_periods")
+                        .and_then(|x| x.as_array())
+                        .into_iter()
+                        .flatten()
+                        .filter_map(|x| {
+                            let id = x
+                                .as_i64()
+                                .map(|x| x.to_string())
+                                .or(x.as_str().map(|x| x.to_string()))?;
+                            Some(terms.get(&id).cloned().unwrap_or(id))
+                        })
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    let active = x.get_string_lossy("active").is_none_or(|x| x == "1");
+                    [
+                        x.get_string_lossy("id").unwrap_or_default(),
+                        format!(
+                            "{}: {}",
+                            x.get_string("course_title").unwrap_or_default(),
+                            x.get_string("section_title").unwrap_or_default()
+                        ),
+                        term,
+                        if active { "active" } else { "archived" }.to_string(),
+                    ]
+                })
+                .collect()
+        }
+        ListKind::Groups => {
+            get_all_pages(client, token_info, &format!("users/{uid}/groups"), "group")
+                .await
+                .context("failed to request groups")?
+                .into_iter()
+                .map(|x| {
+                    [
+                        x.get_string_lossy("id").unwrap_or_default(),
+                        x.get_string("title").unwrap_or_default(),
+                        String::new(),
+                        String::new(),
+                    ]
+                })
+                .collect()
+        }
+        ListKind::Messages => get_all_pages(client, token_info, "messages/inbox", "message")
+            .await
+            .context("failed to request messages")?
+            .into_iter()
+            .map(|x| {
+                [
+                    x.get_string_lossy("id").unwrap_or_default(),
+                    x.get_string("subject").unwrap_or_default(),
+                    String::new(),
+                    x.get_string("message_status").unwrap_or_default(),
+                ]
+            })
+            .collect(),
+    };
+
+    print_table(["ID", "TITLE", "TERM", "STATUS"], rows);
+    Ok(())
+}
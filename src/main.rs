@@ -1,25 +1,254 @@
 use std::{
-    path::PathBuf,
-    sync::Arc,
-    time::{Instant, SystemTime},
+    collections::{BTreeSet, HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
 };
 
-use anyhow::Context;
-use api_helpers::{get, get_raw, SchoologyRequestHelper};
-use export::{export_attachments, export_directory, export_school, export_user};
+use analytics::grade_report;
+use anonymize::anonymize;
+use anyhow::{anyhow, Context};
+use api_helpers::{
+    generate_oauth_header, get, get_all_pages, get_optional, get_raw, Prefetched,
+    SchoologyRequestHelper,
+};
+use clap::{Parser, Subcommand};
+use compress::{CompressedStorage, JsonCompression};
+use config::Config;
+use context::ExportContext;
+use credentials::{fill_credentials, save_keychain, Credentials};
+use export::{
+    estimate_course_size, export_access, export_attachments, export_blog, export_children,
+    export_collections, export_course, export_course_outline, export_events, export_grade_reports,
+    export_group_resources, export_message_markdown, export_notifications, export_posted_updates,
+    export_report_cards, export_school, export_school_resources, export_update_files,
+    export_update_likes, export_update_poll, export_updates_markdown, export_updates_pdf,
+    export_user, export_user_badges, list_courses,
+};
+use futures::{channel::mpsc, stream, StreamExt, TryFutureExt, TryStreamExt};
 use http::Extensions;
-use log::{debug, info};
-use reqwest::{Client, Request, Response};
+use indexes::write_indexes;
+use inspect::{inspect, InspectQuery};
+use list::{list, ListKind};
+use log::{debug, error, info, warn};
+use manifest::{verify_export, Manifest};
+use markdown::Format;
+use pause::PauseMiddleware;
+use people::{members_csv, People};
+use record::RecordingMiddleware;
+use reqwest::{header::HeaderValue, Client, Request, Response, StatusCode};
 use reqwest_middleware::{ClientBuilder, ClientWithMiddleware, Middleware, Next};
 use reqwest_retry::{policies::ExponentialBackoff, RetryTransientMiddleware};
+use sanitize::{Layout, UniqueNames};
 use serde_json::{json, Value};
-use tokio::io::{stdin, AsyncBufReadExt, BufReader};
-
+use split::{split, SplitBy};
+use storage::{open_storage, Storage};
+use summary::{init_logging, LogFormat, Summary};
+use sync::sync;
+use throttle::{parse_bandwidth, BackoffMiddleware, RateLimiter};
+use timeline::Timeline;
+use warc::WarcMiddleware;
+
+mod analytics;
+mod anonymize;
 mod api_helpers;
+mod compress;
+mod config;
+mod context;
+mod credentials;
+mod error;
 mod export;
+mod filetype;
+mod indexes;
+mod inspect;
+mod links;
+mod list;
+mod manifest;
+mod markdown;
+mod pause;
+#[cfg(feature = "pdf")]
+mod pdf;
+mod people;
+#[cfg_attr(not(feature = "tui"), allow(dead_code))]
+mod progress;
+mod record;
+mod reports;
+mod richtext;
+mod sanitize;
+#[cfg(feature = "search")]
+mod search;
+mod split;
+mod storage;
+mod summary;
+mod sync;
+mod throttle;
+mod timeline;
+#[cfg(feature = "tui")]
+mod tui;
+mod warc;
+
+#[derive(Parser, Clone)]
+#[command(
+    version,
+    about,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Path to the config file
+    #[arg(required = true)]
+    config: Option<PathBuf>,
+    /// Limit attachment downloads to this rate, e.g. 5MB/s
+    #[arg(long, global = true, value_parser = parse_bandwidth)]
+    max_bandwidth: Option<u64>,
+    /// Number of times a failed request is retried, overrides `max_retries` in the config
+    #[arg(long, global = true)]
+    max_retries: Option<u32>,
+    /// Give up on a request after this long, including its download, e.g. 30m
+    #[arg(long, global = true, value_parser = humantime::parse_duration)]
+    request_timeout: Option<Duration>,
+    /// Give up on connecting to a server after this long, e.g. 30s
+    #[arg(long, global = true, value_parser = humantime::parse_duration)]
+    connect_timeout: Option<Duration>,
+    /// Send this user agent with every request, overrides `user_agent` in the config
+    #[arg(long, global = true)]
+    user_agent: Option<String>,
+    /// Format of the log output
+    #[arg(long, global = true, value_enum, default_value_t)]
+    log_format: LogFormat,
+    /// Export even if the attachments won't fit on the disk
+    #[arg(long)]
+    force: bool,
+    /// Directory to export to, overrides `output` in the config
+    #[arg(long)]
+    output: Option<PathBuf>,
+    /// Continue an interrupted export in the output directory without asking, keeping the complete files
+    #[arg(long, conflicts_with = "dest")]
+    resume: bool,
+    /// Name of the export directory when no output is set, supports {date}, {timestamp}, {domain} and {user}
+    #[arg(long, default_value = "export_{timestamp}")]
+    name_template: String,
+    /// Upload the export to S3-compatible storage instead, e.g. s3://bucket/prefix
+    #[arg(long)]
+    dest: Option<String>,
+    /// How folder items are named, `id` keeps the tree stable when items are renamed
+    #[arg(long, value_enum, default_value_t)]
+    layout: Layout,
+    /// Also write updates, messages and pages as markdown with front matter
+    #[arg(long, value_enum, default_value_t)]
+    format: Format,
+    /// Only list attachments in the manifest, download them later with fetch-attachments
+    #[arg(long, conflicts_with = "dest")]
+    no_attachments: bool,
+    /// Only write outline.json and outline.md with the folders and items of every course, without their contents
+    #[arg(long)]
+    outline: bool,
+    /// Compress the JSON files of the export, which are then named e.g. info.json.zst
+    #[arg(long, value_enum, value_name = "ALGORITHM")]
+    compress_json: Option<JsonCompression>,
+    /// Replace the names, emails and pictures of all users with pseudonyms after the export
+    #[arg(long, conflicts_with_all = ["dest", "compress_json"])]
+    anonymize: bool,
+    /// Also write every course with its members, updates and events to its own directory in split/
+    #[arg(long, value_enum, conflicts_with = "dest")]
+    split_by: Option<SplitBy>,
+    /// Write the split courses as zip files instead of directories
+    #[arg(long, requires = "split_by")]
+    zip: bool,
+    /// Also write report.json with the grade trend of every course and a GPA after the export
+    #[arg(long, conflicts_with = "dest")]
+    grade_report: bool,
+    /// Also write the grade trends to grade_trends.csv for charting
+    #[arg(long, requires = "grade_report")]
+    grade_report_csv: bool,
+    /// Also build a full-text index of the export for the search command
+    #[cfg(feature = "search")]
+    #[arg(long, conflicts_with = "dest")]
+    search_index: bool,
+    /// Save every raw API response to this directory, named after a hash of the request
+    #[arg(long, value_name = "DIR")]
+    record_api: Option<PathBuf>,
+    /// Also write every request of the export and its response, downloads included, to this WARC file
+    #[arg(long, value_name = "FILE")]
+    warc: Option<PathBuf>,
+    /// Also render pages, assignment descriptions and updates to PDF with this headless chromium
+    #[cfg(feature = "pdf")]
+    #[arg(long, value_name = "CHROMIUM")]
+    pdf: Option<String>,
+    /// Show the progress of every phase and course, download throughput and errors instead of log lines
+    #[cfg(feature = "tui")]
+    #[arg(long)]
+    tui: bool,
+}
+
+#[derive(Subcommand, Clone)]
+enum Command {
+    /// Re-download attachments of an existing export that are missing or don't match the manifest
+    Verify {
+        /// Path to the config file
+        config: PathBuf,
+        /// Path to the export
+        export_dir: PathBuf,
+    },
+    /// Download the attachments a --no-attachments export only listed in its manifest
+    FetchAttachments {
+        /// Path to the config file
+        config: PathBuf,
+        /// Path to the export
+        export_dir: PathBuf,
+    },
+    /// List the courses, groups or messages of the account to pick IDs for the filters
+    List {
+        /// What to list
+        #[arg(value_enum)]
+        kind: ListKind,
+        /// Path to the config file
+        config: PathBuf,
+    },
+    /// Authorize the account and print the user token or save the credentials to the OS keychain
+    Login {
+        /// Path to the config file
+        config: PathBuf,
+        /// Save the app and user credentials to the keychain, so they can be removed from the config
+        #[arg(long)]
+        save_keychain: bool,
+    },
+    /// Answer questions about an existing export without requesting anything
+    Inspect {
+        /// Path to the export
+        export_dir: PathBuf,
+        #[command(flatten)]
+        query: InspectQuery,
+    },
+    /// Keep exporting into the same directory at an interval and log the changes to changelog.jsonl
+    Sync {
+        /// Path to the config file
+        config: PathBuf,
+        /// Time between the start of two exports, e.g. 24h
+        #[arg(long, value_parser = humantime::parse_duration)]
+        every: Duration,
+        /// Directory to export to
+        #[arg(long)]
+        output: PathBuf,
+    },
+    /// Search the updates, messages, pages and assignments of an export, indexing it first if needed
+    #[cfg(feature = "search")]
+    Search {
+        /// Path to the export
+        export_dir: PathBuf,
+        /// Words to look for, e.g. titration or "lab report"
+        query: String,
+        /// Number of results to show
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+}
 
 trait ValueHelper {
     fn get_string(&self, key: &str) -> Option<String>;
+    fn get_string_lossy(&self, key: &str) -> Option<String>;
     fn get_int(&self, key: &str) -> Option<i64>;
     fn get_array(&self, key: &str) -> Option<Vec<Value>>;
 }
@@ -31,12 +260,68 @@ impl ValueHelper for Value {
             .map(|x| x.to_string())
     }
 
+    fn get_string_lossy(&self, key: &str) -> Option<String> {
+        match self.get(key)? {
+            Value::String(x) => Some(x.clone()),
+            Value::Number(x) => Some(x.to_string()),
+            _ => None,
+        }
+    }
+
     fn get_int(&self, key: &str) -> Option<i64> {
         self.get(key).and_then(|x| x.as_i64())
     }
 
     fn get_array(&self, key: &str) -> Option<Vec<Value>> {
-        self.get(key).and_then(|x| x.as_array()).cloned()
+        if let Some(array) = self.get(key).and_then(|x| x.as_array()) {
+            return Some(array.clone());
+        }
+        let (array, variant) = array_variant(self, key)?;
+        // every variant is only logged the first time, it repeats on every page otherwise
+        if SEEN_VARIANTS
+            .lock()
+            .unwrap()
+            .insert((key.to_string(), variant))
+        {
+            info!("{:?} arrays of this server come {}", key, variant);
+        }
+        Some(array)
+    }
+}
+
+static SEEN_VARIANTS: Mutex<BTreeSet<(String, &str)>> = Mutex::new(BTreeSet::new());
+
+// deployments wrap arrays differently, e.g. {"updates": {"update": [...]}} instead of {"update": [...]}
+fn array_variant(value: &Value, key: &str) -> Option<(Vec<Value>, &'static str)> {
+    let plural = format!("{key}s");
+    let singular = key.strip_suffix('s').filter(|x| !x.is_empty());
+    let wrapped = [
+        (Some(key), singular, "wrapped in the singular key"),
+        (
+            Some(plural.as_str()),
+            Some(key),
+            "wrapped in the plural key",
+        ),
+        (Some(plural.as_str()), None, "under the plural key"),
+        (singular, None, "under the singular key"),
+    ];
+    for (outer, inner, variant) in wrapped {
+        let Some(outer) = outer.and_then(|x| value.get(x)) else {
+            continue;
+        };
+        let array = match inner {
+            Some(inner) => outer.get(inner),
+            None => Some(outer),
+        };
+        if let Some(array) = array.and_then(|x| x.as_array()) {
+            return Some((array.clone(), variant));
+        }
+    }
+    match value.get(key)? {
+        // php encodes an empty array like this
+        Value::Object(object) if object.is_empty() => Some((Vec::new(), "as an empty object")),
+        object @ Value::Object(_) => Some((vec![object.clone()], "as a single object")),
+        _ => None,
     }
 }
 
@@ -55,52 +340,158 @@ impl Middleware for LoggingMiddleware {
     }
 }
 
+// a revoked or expired user token is replaced by running the oauth flow again
+struct ReauthMiddleware {
+    domain: String,
+    // the app credentials without a user
+    app: TokenInfo,
+    user_agent: String,
+    // the latest user credentials for each user token that was rejected
+    renewed: tokio::sync::Mutex<HashMap<String, (String, String)>>,
+}
+
+fn oauth_user_token(request: &Request) -> Option<String> {
+    request
+        .headers()
+        .get("Authorization")?
+        .to_str()
+        .ok()?
+        .split("oauth_token=\"")
+        .nth(1)?
+        .split('"')
+        .next()
+        .filter(|x| !x.is_empty())
+        .map(|x| x.to_string())
+}
+
+impl ReauthMiddleware {
+    fn authorize(
+        &self,
+        request: &mut Request,
+        (token, secret): &(String, String),
+    ) -> anyhow::Result<()> {
+        let token_info = TokenInfo {
+            user_token: Some(token.clone()),
+            user_secret: Some(secret.clone()),
+            ..self.app.clone()
+        };
+        request.headers_mut().insert(
+            "Authorization",
+            HeaderValue::from_str(&generate_oauth_header(&token_info)?)?,
+        );
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for ReauthMiddleware {
+    async fn handle(
+        &self,
+        mut req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        let Some(user_token) = oauth_user_token(&req) else {
+            return next.run(req, extensions).await;
+        };
+        let sent = self.renewed.lock().await.get(&user_token).cloned();
+        if let Some(sent) = &sent {
+            self.authorize(&mut req, sent)?;
+        }
+        let retry = req.try_clone();
+        let response = next.clone().run(req, extensions).await?;
+        let Some(mut retry) = retry.filter(|_| response.status() == StatusCode::UNAUTHORIZED)
+        else {
+            return Ok(response);
+        };
+
+        let credentials = {
+            let mut renewed = self.renewed.lock().await;
+            match renewed.get(&user_token) {
+                // another request already logged in again while this one was waiting
+                Some(current) if Some(current) != sent.as_ref() => current.clone(),
+                _ => {
+                    if !pause::interactive() {
+                        return Ok(response);
+                    }
+                    warn!("the user token was rejected, authorize the export again");
+                    let client = Client::builder().user_agent(&self.user_agent).build()?;
+                    let client = ClientBuilder::new(client).build();
+                    let credentials = login(&client, &self.domain, &self.app).await?;
+                    renewed.insert(user_token, credentials.clone());
+                    credentials
+                }
+            }
+        };
+        self.authorize(&mut retry, &credentials)?;
+        next.run(retry, extensions).await
+    }
+}
+
+#[derive(Clone)]
 struct TokenInfo {
+    // requests are built against the api of the account the credentials belong to
+    pub api_base: String,
     pub client_token: String,
     pub client_secret: String,
     pub user_token: Option<String>,
     pub user_secret: Option<String>,
+    // user to act as when using two-legged admin credentials
+    pub run_as: Option<i64>,
 }
 
 impl TokenInfo {
     pub fn new(
+        api_base: String,
         app_token: String,
         app_secret: String,
         user_token: String,
         user_secret: String,
     ) -> Self {
         Self {
+            api_base,
             client_token: app_token,
             client_secret: app_secret,
             user_token: Some(user_token),
             user_secret: Some(user_secret),
+            run_as: None,
         }
     }
 
-    pub fn new_no_user(app_token: String, app_secret: String) -> Self {
+    pub fn new_no_user(api_base: String, app_token: String, app_secret: String) -> Self {
         Self {
+            api_base,
             client_token: app_token,
             client_secret: app_secret,
             user_token: None,
             user_secret: None,
+            run_as: None,
+        }
+    }
+
+    pub fn run_as(&self, uid: i64) -> Self {
+        Self {
+            run_as: Some(uid),
+            ..self.clone()
         }
     }
 }
 
+struct WebSession {
+    pub domain: String,
+    pub cookie: String,
+}
+
 async fn login(
     client: &ClientWithMiddleware,
     domain: &str,
-    app_token: &str,
-    app_secret: &str,
+    app: &TokenInfo,
 ) -> anyhow::Result<(String, String)> {
-    let token_resp =
-        client
-            .execute(Request::get("oauth/request_token")?.into_schoology(
-                &TokenInfo::new_no_user(app_token.to_string(), app_secret.to_string()),
-            )?)
-            .await?
-            .text()
-            .await?;
+    let token_resp = client
+        .execute(Request::get(&app.api_base, "oauth/request_token")?.into_schoology(app)?)
+        .await?
+        .text()
+        .await?;
 
     let mut token_split = token_resp.split('&').map(|x| x.split('=').nth(1));
 
@@ -113,22 +504,20 @@ async fn login(
         .flatten()
         .context("failed to get request secret from answer")?;
 
+    let authorized = pause::read_line();
     info!(
         "https://{domain}/oauth/authorize?oauth_callback=example.com&oauth_token={request_token}"
     );
     info!("open the above url and press ENTER once authorized");
-    BufReader::new(stdin())
-        .read_line(&mut String::new())
-        .await?;
+    authorized.await;
 
     let token_resp = client
         .execute(
-            Request::get("oauth/access_token")?.into_schoology(&TokenInfo::new(
-                app_token.to_string(),
-                app_secret.to_string(),
-                request_token.to_string(),
-                request_secret.to_string(),
-            ))?,
+            Request::get(&app.api_base, "oauth/access_token")?.into_schoology(&TokenInfo {
+                user_token: Some(request_token.to_string()),
+                user_secret: Some(request_secret.to_string()),
+                ..app.clone()
+            })?,
         )
         .await?
         .text()
@@ -148,350 +537,1448 @@ async fn login(
     Ok((client_token.to_string(), client_secret.to_string()))
 }
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    env_logger::Builder::new()
-        .filter_level(log::LevelFilter::Info)
-        .parse_default_env()
-        .init();
-
-    let start = Instant::now();
-
-    let client = Client::new();
-    let policy = ExponentialBackoff::builder().build_with_max_retries(10);
-    let client = ClientBuilder::new(client)
-        .with(LoggingMiddleware)
+fn build_client(
+    config: &Config,
+    record_api: Option<&Path>,
+    warc: Option<&Path>,
+) -> anyhow::Result<Arc<ClientWithMiddleware>> {
+    let client = Client::builder()
+        .user_agent(config.user_agent())
+        .timeout(Duration::from_secs(config.retry.request_timeout))
+        .connect_timeout(Duration::from_secs(config.retry.connect_timeout))
+        .build()
+        .context("failed to build http client")?;
+    let policy = ExponentialBackoff::builder().build_with_max_retries(config.retry.max_retries);
+    let mut client = ClientBuilder::new(client)
+        .with(PauseMiddleware)
+        .with(LoggingMiddleware);
+    if let Some(record_api) = record_api {
+        client = client.with(
+            RecordingMiddleware::new(record_api.to_path_buf(), config.api_base())
+                .context("failed to create api recording dir")?,
+        );
+    }
+    if let Some(warc) = warc {
+        client = client.with(WarcMiddleware::new(warc)?);
+    }
+    let client = client
+        .with(ReauthMiddleware {
+            domain: config.domain.clone(),
+            app: app_token_info(config),
+            user_agent: config.user_agent(),
+            renewed: Default::default(),
+        })
         .with(RetryTransientMiddleware::new_with_policy(policy))
+        .with(BackoffMiddleware::default())
         .build();
-    let client = Arc::new(client);
+    Ok(Arc::new(client))
+}
 
-    let creds =
-        tokio::fs::read_to_string(std::env::args().nth(1).context("path to creds not found")?)
-            .await
-            .context("failed to read creds file")?;
-    let mut creds = creds.split('\n');
+// uploads to the destination are kept away from the schoology middleware, so they don't pause,
+// re-login or use up the api rate limit
+fn build_storage_client(config: &Config) -> anyhow::Result<Arc<ClientWithMiddleware>> {
+    let client = Client::builder()
+        .user_agent(config.user_agent())
+        .timeout(Duration::from_secs(config.retry.request_timeout))
+        .connect_timeout(Duration::from_secs(config.retry.connect_timeout))
+        .build()
+        .context("failed to build storage http client")?;
+    let policy = ExponentialBackoff::builder().build_with_max_retries(config.retry.max_retries);
+    Ok(Arc::new(
+        ClientBuilder::new(client)
+            .with(RetryTransientMiddleware::new_with_policy(policy))
+            .build(),
+    ))
+}
 
-    let domain = creds.next().context("no schoology domain")?;
-    let client_token = creds.next().context("no app token")?;
-    let client_secret = creds.next().context("no app secret")?;
-    let user_token = creds.next();
-    let user_secret = creds.next();
+async fn load_config(path: &Path, args: &Args) -> anyhow::Result<Config> {
+    let mut config = Config::load(path).await?;
+    fill_credentials(&mut config)?;
+    if let Some(max_retries) = args.max_retries {
+        config.retry.max_retries = max_retries;
+    }
+    if let Some(request_timeout) = args.request_timeout {
+        config.retry.request_timeout = request_timeout.as_secs();
+    }
+    if let Some(connect_timeout) = args.connect_timeout {
+        config.retry.connect_timeout = connect_timeout.as_secs();
+    }
+    if let Some(user_agent) = &args.user_agent {
+        config.user_agent = Some(user_agent.clone());
+    }
+    Ok(config)
+}
+
+fn app_token_info(config: &Config) -> TokenInfo {
+    TokenInfo::new_no_user(
+        config.api_base(),
+        config.app_token.clone(),
+        config.app_secret.clone(),
+    )
+}
+
+async fn authenticate(client: &ClientWithMiddleware, config: &Config) -> anyhow::Result<TokenInfo> {
+    if config.admin {
+        return Ok(app_token_info(config));
+    }
 
-    let (user_token, user_secret) = if let Some(user_creds) =
-        user_token.and_then(|x| user_secret.map(|y| (x.to_string(), y.to_string())))
+    let (user_token, user_secret) = if let Some(user_creds) = config
+        .user_token
+        .clone()
+        .and_then(|x| config.user_secret.clone().map(|y| (x, y)))
     {
         user_creds
     } else {
-        let creds = login(&client, domain, client_token, client_secret).await?;
+        let creds = login(client, &config.domain, &app_token_info(config)).await?;
         debug!("creds: {:?}", creds);
         creds
     };
-    let token_info = TokenInfo::new(
-        client_token.to_string(),
-        client_secret.to_string(),
+    Ok(TokenInfo::new(
+        config.api_base(),
+        config.app_token.clone(),
+        config.app_secret.clone(),
         user_token,
         user_secret,
+    ))
+}
+
+fn enrollment_user_id(enrollment: &Value) -> Option<i64> {
+    enrollment
+        .get_string_lossy("uid")
+        .and_then(|x| x.parse().ok())
+}
+
+fn update_user_ids(update: &Value) -> Vec<i64> {
+    update
+        .get_int("uid")
+        .into_iter()
+        .chain(
+            update
+                .get_array("comments")
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|x| x.get_int("uid")),
+        )
+        .collect()
+}
+
+fn expand_name_template(template: &str, domain: &str, user: &str) -> anyhow::Result<String> {
+    let now = SystemTime::now();
+    Ok(template
+        .replace(
+            "{date}",
+            &humantime::format_rfc3339_seconds(now).to_string()[..10],
+        )
+        .replace(
+            "{timestamp}",
+            &now.duration_since(SystemTime::UNIX_EPOCH)?
+                .as_millis()
+                .to_string(),
+        )
+        .replace("{domain}", domain)
+        .replace("{user}", user))
+}
+
+// exporting into an existing export overwrites the files that are exported again
+async fn confirm_merge(export_dir: &Path) -> anyhow::Result<bool> {
+    let is_empty = match std::fs::read_dir(export_dir) {
+        Ok(mut entries) => entries.next().is_none(),
+        Err(_) => return Ok(true),
+    };
+    if is_empty {
+        return Ok(true);
+    }
+    if !pause::interactive() {
+        return Ok(false);
+    }
+
+    let answer = pause::read_line();
+    info!(
+        "{:?} already exists, type \"merge\" and press ENTER to export into it anyway",
+        export_dir
     );
+    Ok(answer.await.trim() == "merge")
+}
+
+fn compressed(storage: Arc<dyn Storage>, compression: Option<JsonCompression>) -> Arc<dyn Storage> {
+    match compression {
+        Some(compression) => Arc::new(CompressedStorage::new(storage, compression)),
+        None => storage,
+    }
+}
+
+// the storage of one export, the bytes that reach the destination are counted in its summary
+fn export_storage(
+    summary: &Arc<Summary>,
+    storage: Arc<dyn Storage>,
+    compression: Option<JsonCompression>,
+) -> Arc<dyn Storage> {
+    compressed(summary.counting(storage), compression)
+}
+
+// exports every user of the school with two-legged admin credentials
+async fn export_school_users(
+    args: Args,
+    config: Config,
+    client: Arc<ClientWithMiddleware>,
+    token_info: TokenInfo,
+    storage: Arc<dyn Storage>,
+    limiter: Option<RateLimiter>,
+    merge: bool,
+) -> anyhow::Result<()> {
+    let export_dir = match args.output.clone().or(config.output.clone()) {
+        Some(output) => output,
+        None => PathBuf::from(expand_name_template(
+            &args.name_template,
+            &config.domain,
+            "admin",
+        )?),
+    };
+    if args.dest.is_none() && !merge && !confirm_merge(&export_dir).await? {
+        return Err(anyhow!("{:?} already exists", export_dir));
+    }
+    storage
+        .create_dir_all(&export_dir)
+        .await
+        .context("failed to create export dir")?;
+
+    let mut users = Vec::new();
+    for (name, key) in [
+        ("users", "user"),
+        ("courses", "course"),
+        ("groups", "group"),
+    ] {
+        info!("exporting school {}", name);
+        let items = get_all_pages(&client, &token_info, &format!("{name}?limit=200"), key)
+            .await
+            .with_context(|| format!("failed to request school {name}"))?;
+        compressed(storage.clone(), args.compress_json)
+            .write(
+                &export_dir.join(format!("{name}.json")),
+                serde_json::to_string_pretty(&items)?.into(),
+            )
+            .await?;
+        if name == "users" {
+            users = items;
+        }
+    }
+
+    let mut failed = 0;
+    let mut exported = Vec::new();
+    for user in &users {
+        let Some(uid) = user
+            .get_string_lossy("id")
+            .and_then(|x| x.parse::<i64>().ok())
+        else {
+            continue;
+        };
+        info!("exporting realm of user {}", uid);
+        let user_export_dir = export_dir.join("users").join(uid.to_string());
+        let summary = Arc::new(Summary::new(args.log_format, storage.clone()));
+        let user_storage = export_storage(&summary, storage.clone(), args.compress_json);
+        let result = run_export(
+            args.clone(),
+            config.clone(),
+            client.clone(),
+            token_info.run_as(uid),
+            uid,
+            user_export_dir.clone(),
+            summary.clone(),
+            user_storage,
+            limiter.clone(),
+        )
+        .await;
+        if args.dest.is_some() || user_export_dir.exists() {
+            summary
+                .write(&user_export_dir, result.as_ref().err())
+                .await
+                .context("failed to write summary")?;
+        }
+        // one user without access to something shouldn't stop the rest of the school
+        match result {
+            Ok(()) => exported.push(uid),
+            Err(err) => {
+                error!("failed to export user {}: {:#}", uid, err);
+                failed += 1;
+            }
+        }
+    }
+    info!("exported {} users, {} failed", users.len() - failed, failed);
+    let mut exported = exported.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+    // the directories of the users are renamed along with everything else
+    if args.anonymize {
+        let numbers = anonymize(export_dir.clone())
+            .await
+            .context("failed to anonymize export")?;
+        for uid in &mut exported {
+            if let Some(number) = numbers.get(uid) {
+                *uid = number.clone();
+            }
+        }
+    }
+    if args.dest.is_none() {
+        for uid in &exported {
+            write_indexes(export_dir.join("users").join(uid))
+                .await
+                .context("failed to write directory indexes")?;
+        }
+    }
+    if args.grade_report {
+        for uid in &exported {
+            grade_report(export_dir.join("users").join(uid), args.grade_report_csv)
+                .await
+                .context("failed to write grade report")?;
+        }
+    }
+    #[cfg(feature = "search")]
+    if args.search_index {
+        for uid in &exported {
+            search::build_index(export_dir.join("users").join(uid))
+                .await
+                .context("failed to build search index")?;
+        }
+    }
+    // every user is an export of its own
+    if let Some(split_by) = args.split_by {
+        for uid in exported {
+            split(export_dir.join("users").join(uid), split_by, args.zip)
+                .await
+                .context("failed to split export")?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn export_other_user(
+    export_users_dir: &Path,
+    client: &ClientWithMiddleware,
+    token_info: &TokenInfo,
+    ctx: &ExportContext,
+    uid: i64,
+) -> anyhow::Result<()> {
+    let user_started = Instant::now();
+    let user_dir = export_users_dir.join(uid.to_string());
+    let user_info = export_user(user_dir.clone(), client, token_info, ctx, uid)
+        .await
+        .context("failed to export user")?;
+    ctx.people.set_profile(uid, &user_dir, &user_info);
+    if ctx.config.content.other_user_blogs {
+        export_blog(user_dir.join("blog"), client, token_info, ctx, uid)
+            .await
+            .context("failed to export blog")?;
+    }
+    export_user_badges(user_dir.join("badges"), client, token_info, ctx, uid)
+        .await
+        .context("failed to export badges")?;
+    ctx.summary
+        .entity("user", uid, &user_dir, None, user_started);
+    Ok(())
+}
 
-    let export_dir = PathBuf::from(format!(
-        "export_{}",
-        SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)?
-            .as_millis()
-    ));
-    tokio::fs::create_dir(&export_dir)
+#[allow(clippy::too_many_arguments)]
+async fn run_export(
+    args: Args,
+    config: Config,
+    client: Arc<ClientWithMiddleware>,
+    token_info: TokenInfo,
+    uid: i64,
+    export_dir: PathBuf,
+    summary: Arc<Summary>,
+    storage: Arc<dyn Storage>,
+    limiter: Option<RateLimiter>,
+) -> anyhow::Result<()> {
+    pause::listen();
+    // the session belongs to whoever logged into the browser, not the users of an admin export
+    let web_session = config
+        .session_cookie
+        .clone()
+        .or_else(|| std::env::var("SCHOOLOGY_SESSION_COOKIE").ok())
+        .filter(|_| token_info.run_as.is_none())
+        .map(|cookie| WebSession {
+            domain: config.domain.clone(),
+            cookie,
+        });
+
+    storage
+        .create_dir_all(&export_dir)
         .await
         .context("failed to create export dir")?;
 
     let export_school_dir = export_dir.join("school");
-    tokio::fs::create_dir(&export_school_dir)
+    storage
+        .create_dir_all(&export_school_dir)
         .await
         .context("failed to create export school dir")?;
 
     let export_building_dir = export_dir.join("building");
-    tokio::fs::create_dir(&export_building_dir)
+    storage
+        .create_dir_all(&export_building_dir)
         .await
         .context("failed to create export building dir")?;
 
     let export_updates_dir = export_dir.join("updates");
-    tokio::fs::create_dir(&export_updates_dir)
+    storage
+        .create_dir_all(&export_updates_dir)
         .await
         .context("failed to create export updates dir")?;
 
     let export_messages_dir = export_dir.join("messages");
-    tokio::fs::create_dir(&export_messages_dir)
+    storage
+        .create_dir_all(&export_messages_dir)
         .await
         .context("failed to create export messages dir")?;
 
     let export_users_dir = export_dir.join("users");
-    tokio::fs::create_dir(&export_users_dir)
+    storage
+        .create_dir_all(&export_users_dir)
         .await
         .context("failed to create export users dir")?;
 
     let export_courses_dir = export_dir.join("courses");
-    tokio::fs::create_dir(&export_courses_dir)
+    storage
+        .create_dir_all(&export_courses_dir)
         .await
         .context("failed to create export courses dir")?;
 
-    let uid = client
-        .execute(Request::get("app-user-info")?.into_schoology(&token_info)?)
-        .await
-        .context("failed to request uid")?
-        .json::<Value>()
-        .await?
-        .get_int("api_uid")
-        .context("failed to get uid")?;
+    storage
+        .write(&export_users_dir.join("self"), uid.to_string().into())
+        .await?;
 
-    info!("logged in as user {}", uid);
+    let courses = json!({ "section": list_courses(&client, &token_info, uid).await? });
 
-    tokio::fs::write(export_users_dir.join("self"), uid.to_string()).await?;
+    storage
+        .write(
+            &export_courses_dir.join("info.json"),
+            serde_json::to_string_pretty(&courses)?.into(),
+        )
+        .await?;
 
-    let mut exported_users: Vec<i64> = Vec::new();
-    let user_info = export_user(
-        export_users_dir.join(uid.to_string()),
-        &client,
-        &token_info,
+    let courses_list = courses
+        .get_array("section")
+        .context("failed to get courses")?
+        .into_iter()
+        .filter(|x| {
+            x.get_string("id")
+                .is_some_and(|x| config.filters.includes_course(&x))
+        })
+        .collect::<Vec<_>>();
+
+    let mut teachers = HashSet::new();
+    let mut enrollments = Vec::new();
+    for course in &courses_list {
+        let course_id = course.get_string("id").context("failed to get course id")?;
+        let course_enrollments = get_all_pages(
+            &client,
+            &token_info,
+            &format!("sections/{course_id}/enrollments?limit=200"),
+            "enrollment",
+        )
+        .await
+        .context("failed to request course enrollments")?;
+        for enrollment in &course_enrollments {
+            if enrollment.get_string_lossy("admin").as_deref() == Some("1") {
+                if let Some(teacher_id) = enrollment_user_id(enrollment) {
+                    teachers.insert(teacher_id);
+                }
+            }
+        }
+        enrollments.push((course_id, course_enrollments));
+    }
+
+    let (user_sender, user_receiver) = mpsc::unbounded::<i64>();
+    // attachments that are still intact from an earlier export into the same directory aren't downloaded again
+    let manifest = match args.dest {
+        Some(_) => Manifest::default(),
+        None => Manifest::load(&export_dir).await,
+    };
+    let ctx = ExportContext {
+        config,
         uid,
-    )
-    .await?;
-    exported_users.push(uid);
-    macro_rules! export_user {
-        ($uid:ident) => {
-            if !exported_users.contains(&$uid) {
-                export_user(
-                    export_users_dir.join($uid.to_string()),
-                    &client,
-                    &token_info,
-                    $uid,
-                )
+        teachers,
+        limiter,
+        layout: args.layout,
+        format: args.format,
+        no_attachments: args.no_attachments,
+        people: People::default(),
+        timeline: Timeline::default(),
+        manifest,
+        prefetched: Prefetched::default(),
+        exported_users: Mutex::new(HashSet::new()),
+        exported_items: Mutex::new(HashSet::new()),
+        user_queue: user_sender,
+        summary,
+        storage,
+        web_session,
+        #[cfg(feature = "pdf")]
+        pdf: args.pdf.clone().map(pdf::PdfRenderer::new),
+    };
+
+    if args.outline {
+        ctx.summary.phase("outline");
+        for course in &courses_list {
+            export_course_outline(
+                export_courses_dir.clone(),
+                &client,
+                &token_info,
+                &ctx,
+                course,
+            )
+            .await
+            .context("failed to export course outline")?;
+        }
+        return Ok(());
+    }
+
+    // nothing is downloaded in a metadata-only export
+    if !ctx.no_attachments {
+        ctx.summary.phase("estimate");
+        let mut estimated_bytes = 0;
+        for course in &courses_list {
+            let course_id = course.get_string("id").context("failed to get course id")?;
+            estimated_bytes += estimate_course_size(&client, &token_info, &ctx, &course_id)
                 .await
-                .context("failed to export user")?;
-                exported_users.push($uid);
+                .context("failed to estimate course size")?;
+        }
+        // uploads don't go through the disk
+        if args.dest.is_none() {
+            let available_bytes =
+                fs2::available_space(&export_dir).context("failed to get available disk space")?;
+            info!(
+                "course attachments take up at least {:.1} MB, {:.1} MB available",
+                estimated_bytes as f64 / 1e6,
+                available_bytes as f64 / 1e6
+            );
+            if estimated_bytes > available_bytes {
+                if args.force {
+                    warn!("the export probably won't fit on the disk");
+                } else {
+                    return Err(anyhow!(
+                        "not enough disk space, {} bytes needed but only {} bytes available (use --force to export anyway)",
+                        estimated_bytes,
+                        available_bytes
+                    ));
+                }
             }
-        };
+        }
     }
 
-    let school_id = user_info
-        .get_int("school_id")
-        .context("failed to get school id")?;
-
-    export_school(export_school_dir, &client, &token_info, school_id).await?;
+    ctx.summary.phase("profile");
+    ctx.claim_user(uid);
+    let user_started = Instant::now();
+    let user_dir = export_users_dir.join(uid.to_string());
+    let user_info = export_user(user_dir.clone(), &client, &token_info, &ctx, uid).await?;
+    ctx.people.set_profile(uid, &user_dir, &user_info);
+    export_blog(user_dir.join("blog"), &client, &token_info, &ctx, uid)
+        .await
+        .context("failed to export blog")?;
+    export_user_badges(export_dir.join("badges"), &client, &token_info, &ctx, uid)
+        .await
+        .context("failed to export badges")?;
+    export_children(
+        export_dir.join("children"),
+        &client,
+        &token_info,
+        &ctx,
+        &user_info,
+    )
+    .await
+    .context("failed to export children")?;
+    ctx.summary
+        .entity("user", uid, &user_dir, None, user_started);
+
+    // other users are exported in the background while the rest of the export continues
+    let export_users = user_receiver
+        .map(|uid| export_other_user(&export_users_dir, &client, &token_info, &ctx, uid))
+        .buffer_unordered(ctx.config.user_parallelism.max(1))
+        .try_for_each(|_| async { Ok(()) });
+
+    let export_walk = async {
+        let school_id = user_info
+            .get_int("school_id")
+            .context("failed to get school id")?;
+
+        ctx.summary.phase("school");
+        export_school(
+            export_school_dir.clone(),
+            &client,
+            &token_info,
+            &ctx,
+            school_id,
+        )
+        .await?;
+        export_school_resources(
+            export_school_dir.join("resources"),
+            &client,
+            &token_info,
+            &ctx,
+            school_id,
+        )
+        .await
+        .context("failed to export school resources")?;
+
+        let building_id = user_info
+            .get_int("building_id")
+            .context("failed to get building id")?;
+
+        export_school(
+            export_building_dir.clone(),
+            &client,
+            &token_info,
+            &ctx,
+            building_id,
+        )
+        .await?;
+        export_school_resources(
+            export_building_dir.join("resources"),
+            &client,
+            &token_info,
+            &ctx,
+            building_id,
+        )
+        .await
+        .context("failed to export building resources")?;
+
+        // fetches the profiles of everyone on a page in one request instead of one request per user
+        macro_rules! prefetch_users {
+            ($uids:expr) => {
+                ctx.prefetched
+                    .prefetch(
+                        &client,
+                        &token_info,
+                        $uids
+                            .filter(|x| !ctx.is_user_claimed(*x) && ctx.should_export_user(*x))
+                            .map(|x| format!("users/{x}"))
+                            .collect::<Vec<_>>(),
+                    )
+                    .await;
+            };
+        }
 
-    let building_id = user_info
-        .get_int("building_id")
-        .context("failed to get building id")?;
+        let mut exported_updates = HashSet::new();
+        let posted_updates = Mutex::new(Vec::new());
+        let export_update = |update: Value, updates_path: PathBuf| {
+            let (client, token_info, ctx) = (&client, &token_info, &ctx);
+            let (export_updates_dir, posted_updates) = (&export_updates_dir, &posted_updates);
+            async move {
+                let update_started = Instant::now();
+                let update_id = update.get_int("id").context("failed to get update id")?;
 
-    export_school(export_building_dir, &client, &token_info, building_id).await?;
+                let update_user_id = update
+                    .get_int("uid")
+                    .context("failed to get update user id")?;
+                ctx.queue_user(update_user_id)?;
+                ctx.people.record(
+                    update_user_id,
+                    &updates_path,
+                    "update",
+                    update.get_int("created"),
+                );
+
+                for comment in update.get_array("comments").unwrap_or_default() {
+                    let comment_user_id = comment
+                        .get_int("uid")
+                        .context("failed to get update comment user id")?;
+                    ctx.queue_user(comment_user_id)?;
+                    ctx.people.record(
+                        comment_user_id,
+                        &updates_path,
+                        "update comment",
+                        comment.get_int("created"),
+                    );
+                }
+
+                export_attachments(
+                    &|file_name| export_updates_dir.join(format!("update_{update_id}_{file_name}")),
+                    client,
+                    token_info,
+                    ctx,
+                    &update,
+                )
+                .await?;
+                export_update_poll(export_updates_dir, client, token_info, ctx, &update).await?;
+                export_update_likes(export_updates_dir, client, token_info, ctx, &update).await?;
+                if update_user_id == ctx.uid {
+                    posted_updates
+                        .lock()
+                        .unwrap()
+                        .push((update.clone(), updates_path.clone()));
+                }
+                ctx.summary
+                    .entity("update", update_id, &updates_path, None, update_started);
+                Ok::<_, anyhow::Error>(update_id)
+            }
+        };
 
-    let mut updates_url = "https://api.schoology.com/v1/recent/?extended&options&start=0&limit=50&created_offset=0&with_attachments=TRUE&richtext=1".to_string();
-    let mut updates_cnt = 0;
-    loop {
-        info!("exporting updates ({})", updates_cnt);
-        let update_info = get_raw(&client, &token_info, &updates_url)
+        ctx.summary.phase("resources");
+        export_collections(export_dir.join("resources"), &client, &token_info, &ctx)
             .await
-            .context("failed to request update info")?;
+            .context("failed to export resources")?;
 
-        for update in update_info
-            .get_array("update")
-            .context("failed to get update info")?
-        {
-            let update_id = update.get_int("id").context("failed to get update id")?;
-
-            let update_user_id = update
-                .get_int("uid")
-                .context("failed to get update user id")?;
-            export_user!(update_user_id);
+        ctx.summary.phase("updates");
+        let updates_url = format!("{}recent/?extended&options&start=0&limit=50&created_offset=0&with_attachments=TRUE&richtext=1", token_info.api_base);
+        let mut updates_cnt = 0;
+        let mut next_page = Some(
+            get_raw(&client, &token_info, &updates_url)
+                .await
+                .context("failed to request update info")?,
+        );
+        while let Some(mut update_info) = next_page.take() {
+            info!("exporting updates ({})", updates_cnt);
+            let updates_path = export_updates_dir.join(format!("updates_{updates_cnt}.json"));
+            let updates = update_info
+                .get_array("update")
+                .context("failed to get update info")?;
+            prefetch_users!(updates.iter().flat_map(update_user_ids));
+
+            // the next page is requested while this one is exported
+            let next_link = update_info.get("links").and_then(|x| x.get_string("next"));
+            let request_next_page = async {
+                match next_link {
+                    Some(next_link) => get_raw(&client, &token_info, &next_link)
+                        .await
+                        .context("failed to request update info")
+                        .map(Some),
+                    None => Ok(None),
+                }
+            };
+            let export_updates = stream::iter(updates)
+                .map(|x| export_update(x, updates_path.clone()))
+                .buffered(ctx.config.page_parallelism.max(1))
+                .try_collect::<Vec<_>>();
+            let (update_ids, next) = tokio::try_join!(export_updates, request_next_page)?;
+            exported_updates.extend(update_ids);
+            next_page = next;
+
+            export_update_files(&export_updates_dir, &client, &ctx, &mut update_info).await?;
+            ctx.redact(&mut update_info);
+            export_updates_markdown(&export_updates_dir, &ctx, &update_info).await?;
+            export_updates_pdf(&export_updates_dir, &ctx, &update_info).await?;
+            ctx.storage
+                .write(
+                    &updates_path,
+                    serde_json::to_string_pretty(&update_info)?.into(),
+                )
+                .await?;
 
-            for comment in update
-                .get_array("comments")
-                .context("failed to get update comments")?
-            {
-                let comment_user_id = comment
-                    .get_int("uid")
-                    .context("failed to get update comment user id")?;
-                export_user!(comment_user_id);
-            }
+            updates_cnt += 1;
+        }
 
-            export_attachments(
-                &|file_name| export_updates_dir.join(format!("update_{update_id}_{file_name}")),
+        // the recent feed stops at a server side cutoff, so older updates are only reachable per realm
+        let mut update_realms = vec![
+            format!("users/{uid}"),
+            format!("schools/{school_id}"),
+            format!("schools/{building_id}"),
+        ];
+        update_realms.extend(
+            courses_list
+                .iter()
+                .filter_map(|x| x.get_string("id"))
+                .map(|x| format!("sections/{x}")),
+        );
+        let groups = get_all_pages(
+            &client,
+            &token_info,
+            &format!("users/{uid}/groups"),
+            "group",
+        )
+        .await
+        .context("failed to request groups")?;
+        for group in &groups {
+            let Some(group_id) = group.get_string_lossy("id") else {
+                continue;
+            };
+            update_realms.push(format!("groups/{group_id}"));
+            export_group_resources(
+                export_dir.join("groups").join(&group_id).join("resources"),
                 &client,
                 &token_info,
-                &update,
+                &ctx,
+                &group_id,
             )
-            .await?;
+            .await
+            .context("failed to export group resources")?;
+            // the groups themselves are only exported for their join settings
+            if group.get_string_lossy("admin").as_deref() == Some("1") {
+                let group_dir = export_dir.join("groups").join(&group_id);
+                ctx.storage.create_dir_all(&group_dir).await?;
+                ctx.storage
+                    .write(
+                        &group_dir.join("info.json"),
+                        serde_json::to_string_pretty(group)?.into(),
+                    )
+                    .await?;
+                export_access(
+                    &group_dir,
+                    &client,
+                    &token_info,
+                    &ctx,
+                    &format!("groups/{group_id}"),
+                    group,
+                )
+                .await
+                .context("failed to export group access settings")?;
+            }
         }
-        tokio::fs::write(
-            export_updates_dir.join(format!("updates_{updates_cnt}.json")),
-            serde_json::to_string_pretty(&update_info)?,
-        )
-        .await?;
-
-        updates_cnt += 1;
-        if let Some(next_link) = update_info.get("links").and_then(|x| x.get_string("next")) {
-            updates_url = next_link
-        } else {
-            break;
+        for realm in &update_realms {
+            let mut realm_updates_cnt = 0;
+            loop {
+                let Some(mut update_info) = get_optional(
+                    &client,
+                    &token_info,
+                    &format!(
+                        "{realm}/updates?start={}&limit=50&with_attachments=TRUE&richtext=1",
+                        realm_updates_cnt * 50
+                    ),
+                )
+                .await
+                .context("failed to request realm update info")?
+                else {
+                    break;
+                };
+                let updates = update_info.get_array("update").unwrap_or_default();
+                if updates.is_empty() {
+                    break;
+                }
+                let last_page = updates.len() < 50;
+
+                let new_updates = updates
+                    .into_iter()
+                    .filter(|x| {
+                        x.get_int("id")
+                            .is_some_and(|x| !exported_updates.contains(&x))
+                    })
+                    .collect::<Vec<_>>();
+                if !new_updates.is_empty() {
+                    info!(
+                        "exporting {} older updates from {}",
+                        new_updates.len(),
+                        realm
+                    );
+                    let updates_path = export_updates_dir.join(format!(
+                        "realm_updates_{}_{realm_updates_cnt}.json",
+                        realm.replace('/', "_")
+                    ));
+                    prefetch_users!(new_updates.iter().flat_map(update_user_ids));
+                    let update_ids = stream::iter(new_updates.clone())
+                        .map(|x| export_update(x, updates_path.clone()))
+                        .buffered(ctx.config.page_parallelism.max(1))
+                        .try_collect::<Vec<_>>()
+                        .await?;
+                    exported_updates.extend(update_ids);
+                    update_info["update"] = Value::Array(new_updates);
+                    export_update_files(&export_updates_dir, &client, &ctx, &mut update_info)
+                        .await?;
+                    ctx.redact(&mut update_info);
+                    export_updates_markdown(&export_updates_dir, &ctx, &update_info).await?;
+                    export_updates_pdf(&export_updates_dir, &ctx, &update_info).await?;
+                    ctx.storage
+                        .write(
+                            &updates_path,
+                            serde_json::to_string_pretty(&update_info)?.into(),
+                        )
+                        .await?;
+                }
+
+                realm_updates_cnt += 1;
+                if last_page {
+                    break;
+                }
+            }
         }
-    }
-
-    let mut messages_url = "https://api.schoology.com/v1/messages/inbox?extended&options&start=0&limit=50&created_offset=0&with_attachments=TRUE&richtext=1".to_string();
-    let mut parsed_sent_messages = false;
-    let mut messages_cnt = 0;
-    loop {
-        info!("exporting messages ({})", messages_cnt);
-        let messages_info = get_raw(&client, &token_info, &messages_url)
-            .await
-            .context("failed to request messages info")?;
 
-        for message in messages_info
-            .get_array("message")
-            .context("failed to get messages info")?
-        {
-            let message_id = message.get_int("id").context("failed to get message id")?;
+        ctx.summary.phase("posted updates");
+        export_posted_updates(
+            &export_updates_dir,
+            &client,
+            &token_info,
+            &ctx,
+            posted_updates.into_inner().unwrap(),
+        )
+        .await
+        .context("failed to export posted updates")?;
 
-            let message_url = message
-                .get("links")
-                .and_then(|x| x.get_string("self"))
-                .context("failed to get message url")?;
+        ctx.summary.phase("events");
+        for realm in &update_realms {
+            export_events(
+                export_dir.join("events").join(realm.replace('/', "_")),
+                &client,
+                &token_info,
+                &ctx,
+                realm,
+            )
+            .await
+            .context("failed to export events")?;
+        }
 
-            let message_info = client
-                .execute(Request::get_raw(&message_url)?.into_schoology(&token_info)?)
+        ctx.summary.phase("messages");
+        let messages_url = |folder: &str| {
+            format!("{}messages/{folder}?extended&options&start=0&limit=50&created_offset=0&with_attachments=TRUE&richtext=1", token_info.api_base)
+        };
+        let mut messages_cnt = 0;
+        let mut mailbox = Vec::new();
+        let mut next_page = Some((
+            "inbox",
+            get_raw(&client, &token_info, &messages_url("inbox"))
                 .await
-                .context("failed to request message info")?
-                .json::<Value>()
+                .context("failed to request messages info")?,
+        ));
+        while let Some((folder, mut messages_info)) = next_page.take() {
+            info!("exporting messages ({})", messages_cnt);
+            let messages = messages_info
+                .get_array("message")
+                .context("failed to get messages info")?;
+            prefetch_users!(messages.iter().filter_map(|x| x.get_int("author_id")));
+
+            // the next page is requested while this one is exported
+            let next_url = match messages_info
+                .get("links")
+                .and_then(|x| x.get_string("next"))
+            {
+                Some(next_link) => Some((folder, next_link)),
+                None if folder == "inbox" => Some(("sent", messages_url("sent"))),
+                None => None,
+            };
+            let request_next_page = async {
+                match next_url {
+                    Some((folder, url)) => get_raw(&client, &token_info, &url)
+                        .await
+                        .context("failed to request messages info")
+                        .map(|x| Some((folder, x))),
+                    None => Ok(None),
+                }
+            };
+            let export_messages = stream::iter(messages)
+                .map(|message| {
+                    let (client, token_info, ctx) = (&client, &token_info, &ctx);
+                    let export_messages_dir = &export_messages_dir;
+                    async move {
+                        let message_started = Instant::now();
+                        let message_id =
+                            message.get_int("id").context("failed to get message id")?;
+
+                        let message_url = message
+                            .get("links")
+                            .and_then(|x| x.get_string("self"))
+                            .context("failed to get message url")?;
+
+                        let mut message_info = get_raw(client, token_info, &message_url)
+                            .await
+                            .context("failed to request message info")?;
+
+                        let message_path =
+                            export_messages_dir.join(format!("message_{message_id}.json"));
+                        ctx.redact(&mut message_info);
+                        let message_json = serde_json::to_string_pretty(&message_info)?;
+                        let message_bytes = message_json.len() as u64;
+                        ctx.storage
+                            .write(&message_path, message_json.into())
+                            .await?;
+                        export_message_markdown(&message_path, ctx, &message_info).await?;
+
+                        // replies deep in the thread only carry their attachments in the detail
+                        let thread = message_info.get_array("message").unwrap_or_default();
+                        let mut file_ids = HashSet::new();
+                        let files = [&message, &message_info]
+                            .into_iter()
+                            .chain(&thread)
+                            .filter_map(|x| x.get("attachments")?.get("files")?.get_array("file"))
+                            .flatten()
+                            .filter(|x| file_ids.insert(x.get_string_lossy("id")))
+                            .collect::<Vec<_>>();
+                        export_attachments(
+                            &|file_name| {
+                                export_messages_dir
+                                    .join(format!("message_{message_id}_{file_name}"))
+                            },
+                            client,
+                            token_info,
+                            ctx,
+                            &json!({ "attachments": { "files": { "file": files } } }),
+                        )
+                        .await?;
+
+                        if let Some(update_user_id) = message.get_int("author_id") {
+                            ctx.queue_user(update_user_id)?;
+                            ctx.people.record(
+                                update_user_id,
+                                &message_path,
+                                "message",
+                                message.get_int("last_updated"),
+                            );
+                        }
+
+                        let mut recipient_ids = message
+                            .get_string_lossy("recipient_ids")
+                            .unwrap_or_default()
+                            .split(',')
+                            .filter_map(|x| x.trim().parse::<i64>().ok())
+                            .collect::<Vec<_>>();
+                        for recipient_id in recipient_ids.iter().copied() {
+                            ctx.queue_user(recipient_id)?;
+                            ctx.people.record(
+                                recipient_id,
+                                &message_path,
+                                "message recipient",
+                                message.get_int("last_updated"),
+                            );
+                        }
+                        if ctx.config.redact_classmates() {
+                            recipient_ids.retain(|x| !ctx.is_classmate(*x));
+                        }
+                        ctx.summary.entity(
+                            "message",
+                            message_id,
+                            &message_path,
+                            Some(message_bytes),
+                            message_started,
+                        );
+                        Ok::<_, anyhow::Error>(json!({
+                            "id": message_id,
+                            "folder": folder,
+                            "status": message.get_string("message_status"),
+                            "subject": message.get_string("subject"),
+                            "author_id": message.get_int("author_id"),
+                            "recipient_ids": recipient_ids,
+                            "last_updated": message.get_int("last_updated"),
+                            "path": format!("message_{message_id}.json"),
+                        }))
+                    }
+                })
+                .buffered(ctx.config.page_parallelism.max(1))
+                .try_collect::<Vec<_>>();
+            let (mailbox_entries, next) = tokio::try_join!(export_messages, request_next_page)?;
+            mailbox.extend(mailbox_entries);
+            next_page = next;
+
+            ctx.redact(&mut messages_info);
+            ctx.storage
+                .write(
+                    &export_messages_dir.join(format!("messages_{messages_cnt}.json")),
+                    serde_json::to_string_pretty(&messages_info)?.into(),
+                )
                 .await?;
 
-            tokio::fs::write(
-                export_messages_dir.join(format!("message_{message_id}.json")),
-                serde_json::to_string_pretty(&message_info)?,
+            messages_cnt += 1;
+        }
+        let mut mailbox = Value::Array(mailbox);
+        ctx.redact(&mut mailbox);
+        ctx.storage
+            .write(
+                &export_messages_dir.join("mailbox.json"),
+                serde_json::to_string_pretty(&mailbox)?.into(),
             )
             .await?;
 
-            export_attachments(
-                &|file_name| export_messages_dir.join(format!("message_{message_id}_{file_name}")),
+        ctx.summary.phase("notifications");
+        export_notifications(
+            export_dir.join("notifications"),
+            &client,
+            &token_info,
+            &ctx,
+            uid,
+        )
+        .await
+        .context("failed to export notifications")?;
+
+        debug!(
+            "courses to export: {:?}",
+            courses_list
+                .iter()
+                .map(|x| x.get_string("id").unwrap_or_default())
+                .collect::<Vec<_>>()
+        );
+
+        ctx.summary.phase("courses");
+        ctx.prefetched
+            .prefetch(
                 &client,
                 &token_info,
-                &message,
+                courses_list
+                    .iter()
+                    .filter_map(|x| x.get_string("id"))
+                    .map(|x| format!("users/{uid}/grades/?section_id={x}"))
+                    .collect::<Vec<_>>(),
             )
+            .await;
+        let grading_periods = stream::iter(courses_list.iter())
+            .map(|course| {
+                export_course(
+                    export_courses_dir.clone(),
+                    &client,
+                    &token_info,
+                    &ctx,
+                    course,
+                )
+                .inspect_err(|_| {
+                    progress::course_failed(&course.get_string("id").unwrap_or_default())
+                })
+            })
+            .buffer_unordered(ctx.config.parallelism.max(1))
+            .try_fold(BTreeSet::new(), |mut grading_periods, x| async move {
+                grading_periods.extend(x);
+                Ok(grading_periods)
+            })
             .await?;
 
-            if let Some(update_user_id) = message.get_int("author_id") {
-                export_user!(update_user_id);
+        ctx.summary.phase("enrollments");
+        for (course_id, mut course_enrollments) in enrollments {
+            let enrollments_path = export_courses_dir.join(&course_id).join("enrollments.json");
+            prefetch_users!(course_enrollments.iter().filter_map(enrollment_user_id));
+            for enrollment in &mut course_enrollments {
+                let Some(enrollment_user_id) = enrollment_user_id(enrollment) else {
+                    continue;
+                };
+                ctx.queue_user(enrollment_user_id)?;
+                ctx.people.record_enrollment(
+                    enrollment_user_id,
+                    &enrollments_path,
+                    &course_id,
+                    enrollment,
+                );
+                if ctx.should_export_user(enrollment_user_id) {
+                    enrollment["profile"] = format!("../../users/{enrollment_user_id}").into();
+                }
             }
+            ctx.storage
+                .write(
+                    &export_courses_dir.join(&course_id).join("members.csv"),
+                    members_csv(&course_enrollments, &ctx).into(),
+                )
+                .await?;
+            let mut enrollments_info = json!({ "enrollment": course_enrollments });
+            ctx.redact(&mut enrollments_info);
+            ctx.storage
+                .write(
+                    &enrollments_path,
+                    serde_json::to_string_pretty(&enrollments_info)?.into(),
+                )
+                .await?;
         }
-        tokio::fs::write(
-            export_messages_dir.join(format!("messages_{messages_cnt}.json")),
-            serde_json::to_string_pretty(&messages_info)?,
+
+        // closing the queue lets the user exports finish
+        ctx.user_queue.close_channel();
+        Ok::<_, anyhow::Error>(grading_periods)
+    };
+    let (grading_periods, ()) = tokio::try_join!(
+        export_walk,
+        ctx.summary.concurrent_phase("users", export_users)
+    )?;
+
+    ctx.summary.phase("report cards");
+    export_report_cards(
+        export_dir.join("reports"),
+        &client,
+        &token_info,
+        &ctx,
+        uid,
+        &grading_periods,
+    )
+    .await
+    .context("failed to export report cards")?;
+    if let Some(web_session) = &ctx.web_session {
+        ctx.summary.phase("grade reports");
+        export_grade_reports(
+            export_dir.join("grades").join("reports"),
+            &client,
+            &ctx,
+            web_session,
+            &grading_periods,
         )
+        .await
+        .context("failed to export grade reports")?;
+    }
+
+    ctx.summary.phase("people");
+    ctx.people
+        .write(&export_dir, &ctx)
+        .await
+        .context("failed to write people directory")?;
+    ctx.timeline
+        .write(ctx.storage.as_ref(), &export_dir)
         .await?;
 
-        messages_cnt += 1;
-        if let Some(next_link) = messages_info
-            .get("links")
-            .and_then(|x| x.get_string("next"))
-        {
-            messages_url = next_link
-        } else if !parsed_sent_messages {
-            messages_url = "https://api.schoology.com/v1/messages/sent?extended&options&start=0&limit=50&created_offset=0&with_attachments=TRUE&richtext=1".to_string();
-            parsed_sent_messages = true;
-        } else {
-            break;
+    ctx.manifest
+        .write(ctx.storage.as_ref(), &export_dir)
+        .await?;
+
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    // the interface is only shown for exports to a terminal
+    #[cfg(feature = "tui")]
+    if args.tui && args.command.is_none() && std::io::IsTerminal::is_terminal(&std::io::stdout()) {
+        tui::init_logging(args.log_format);
+        let tui = tui::Tui::start()?;
+        let result = export(args, false).await;
+        tui.stop().await;
+        return result;
+    }
+    init_logging(args.log_format);
+
+    match &args.command {
+        Some(Command::Verify { config, export_dir }) => {
+            let config = load_config(config, &args).await?;
+            let client = build_client(&config, None, None)?;
+            let token_info = authenticate(&client, &config).await?;
+            return verify_export(
+                export_dir.clone(),
+                &client,
+                &token_info,
+                args.max_bandwidth.map(RateLimiter::new).as_ref(),
+                false,
+            )
+            .await;
         }
+        Some(Command::FetchAttachments { config, export_dir }) => {
+            let config = load_config(config, &args).await?;
+            let client = build_client(&config, None, None)?;
+            let token_info = authenticate(&client, &config).await?;
+            return verify_export(
+                export_dir.clone(),
+                &client,
+                &token_info,
+                args.max_bandwidth.map(RateLimiter::new).as_ref(),
+                true,
+            )
+            .await;
+        }
+        Some(Command::List { kind, config }) => {
+            let config = load_config(config, &args).await?;
+            let client = build_client(&config, None, None)?;
+            let token_info = authenticate(&client, &config).await?;
+            return list(&client, &token_info, *kind).await;
+        }
+        Some(Command::Inspect { export_dir, query }) => {
+            return inspect(export_dir, query);
+        }
+        #[cfg(feature = "search")]
+        Some(Command::Search {
+            export_dir,
+            query,
+            limit,
+        }) => {
+            return search::search(export_dir, query, *limit);
+        }
+        Some(Command::Login {
+            config,
+            save_keychain: save,
+        }) => {
+            let config = load_config(config, &args).await?;
+            let client = build_client(&config, None, None)?;
+            let (user_token, user_secret) = match config.admin {
+                true => (None, None),
+                false => {
+                    let (user_token, user_secret) =
+                        login(&client, &config.domain, &app_token_info(&config)).await?;
+                    (Some(user_token), Some(user_secret))
+                }
+            };
+            if !save {
+                println!("user_token = {:?}", user_token.unwrap_or_default());
+                println!("user_secret = {:?}", user_secret.unwrap_or_default());
+                return Ok(());
+            }
+            save_keychain(
+                &config.domain,
+                &Credentials {
+                    app_token: config.app_token,
+                    app_secret: config.app_secret,
+                    user_token,
+                    user_secret,
+                },
+            )?;
+            info!(
+                "saved the credentials for {} to the keychain, they can be removed from the config",
+                config.domain
+            );
+            return Ok(());
+        }
+        Some(Command::Sync {
+            config,
+            every,
+            output,
+        }) => {
+            return sync(args.clone(), config.clone(), *every, output.clone()).await;
+        }
+        None => {}
     }
 
-    let courses = get(
-        &client,
-        &token_info,
-        &format!("users/{uid}/sections?include_past=1"),
-    )
-    .await
-    .context("failed to request courses")?;
+    let resume = args.resume;
+    export(args, resume).await
+}
 
-    tokio::fs::write(
-        export_courses_dir.join("info.json"),
-        serde_json::to_string_pretty(&courses)?,
-    )
-    .await?;
+// merge exports into an existing directory without asking
+async fn export(args: Args, merge: bool) -> anyhow::Result<()> {
+    let config = load_config(args.config.as_ref().context("no config given")?, &args).await?;
+    let client = build_client(&config, args.record_api.as_deref(), args.warc.as_deref())?;
+    let limiter = args.max_bandwidth.map(RateLimiter::new);
+    if !config.accounts.is_empty() {
+        return export_accounts(args, config, client, limiter, merge).await;
+    }
+    export_account(args, config, client, limiter, merge).await
+}
 
-    let courses_list = courses
-        .get_array("section")
-        .context("failed to get courses")?;
-
-    debug!(
-        "courses to export: {:?}",
-        courses_list
-            .iter()
-            .map(|x| x.get_string("id").unwrap_or_default())
-            .collect::<Vec<_>>()
-    );
+// every account of the config is exported to its own directory below the output
+async fn export_accounts(
+    args: Args,
+    config: Config,
+    client: Arc<ClientWithMiddleware>,
+    limiter: Option<RateLimiter>,
+    merge: bool,
+) -> anyhow::Result<()> {
+    if config.admin {
+        return Err(anyhow!("accounts can't be combined with admin credentials"));
+    }
+    let output = match args.output.clone().or(config.output.clone()) {
+        Some(output) => output,
+        None => PathBuf::from(expand_name_template(
+            &args.name_template,
+            &config.domain,
+            "accounts",
+        )?),
+    };
 
-    for course in courses_list {
-        let course_id = course.get_string("id").context("failed to get course id")?; // ???
-        let course_dir = export_courses_dir.join(&course_id);
-        tokio::fs::create_dir(&course_dir).await?;
+    let mut names = UniqueNames::default();
+    let exports = config
+        .accounts
+        .iter()
+        .map(|account| {
+            let name = names.claim(&account.name);
+            let mut args = args.clone();
+            args.output = Some(output.join(&name));
+            args.dest = args
+                .dest
+                .take()
+                .map(|x| format!("{}/{name}", x.trim_end_matches('/')));
+            let mut config = config.clone();
+            config.accounts.clear();
+            config.user_token = account.user_token.clone();
+            config.user_secret = account.user_secret.clone();
+            config.session_cookie = account.session_cookie.clone();
+            let (client, limiter) = (client.clone(), limiter.clone());
+            async move {
+                info!("exporting account {}", name);
+                let result = export_account(args, config, client, limiter, merge).await;
+                if let Err(err) = &result {
+                    error!("failed to export account {}: {:#}", name, err);
+                }
+                result
+            }
+        })
+        .collect::<Vec<_>>();
+    let results = match config.parallel_accounts {
+        true => futures::future::join_all(exports).await,
+        false => {
+            let mut results = Vec::new();
+            for export in exports {
+                results.push(export.await);
+            }
+            results
+        }
+    };
 
-        info!("exporting course {}", course_id);
+    match results.iter().filter(|x| x.is_err()).count() {
+        0 => Ok(()),
+        failed => Err(anyhow!(
+            "failed to export {} of {} accounts",
+            failed,
+            results.len()
+        )),
+    }
+}
 
-        let course_info_url = course
-            .get("links")
-            .and_then(|x| x.get_string("self"))
-            .context("failed to get course url")?;
-        let course_info = client
-            .execute(Request::get_raw(&course_info_url)?.into_schoology(&token_info)?)
-            .await
-            .context("failed to get course info")?
-            .json::<Value>()
-            .await?;
-        tokio::fs::write(
-            course_dir.join("info.json"),
-            serde_json::to_string_pretty(&course_info)?,
-        )
-        .await?;
+async fn export_account(
+    args: Args,
+    config: Config,
+    client: Arc<ClientWithMiddleware>,
+    limiter: Option<RateLimiter>,
+    merge: bool,
+) -> anyhow::Result<()> {
+    let start = Instant::now();
 
-        let course_banner_url = course_info
-            .get_string("profile_url")
-            .context("failed to get course banner url")?;
-        tokio::fs::write(
-            course_dir.join("banner.png"),
-            client
-                .execute(Request::get_raw(&course_banner_url)?.into_schoology(&token_info)?)
-                .await
-                .context("failed to request course banner")?
-                .bytes()
-                .await?,
-        )
-        .await?;
+    let token_info = authenticate(&client, &config).await?;
+    let storage = open_storage(build_storage_client(&config)?, args.dest.as_deref())?;
 
-        let course_grades_info = client
-            .execute(
-                Request::get(&format!("users/{uid}/grades/?section_id={course_id}"))?
-                    .into_schoology(&token_info)?,
-            )
-            .await
-            .context("failed to get course grades")?
-            .json::<Value>()
-            .await?;
-        tokio::fs::write(
-            course_dir.join("grades.json"),
-            serde_json::to_string_pretty(&course_grades_info)?,
-        )
-        .await?;
+    if config.admin {
+        return export_school_users(args, config, client, token_info, storage, limiter, merge)
+            .await;
+    }
+
+    let uid = get(&client, &token_info, "app-user-info")
+        .await
+        .context("failed to request uid")?
+        .get_int("api_uid")
+        .context("failed to get uid")?;
+    info!("logged in as user {}", uid);
 
-        let course_files_root = course_dir.join("files");
+    let export_dir = match args.output.clone().or(config.output.clone()) {
+        Some(output) => output,
+        None => PathBuf::from(expand_name_template(
+            &args.name_template,
+            &config.domain,
+            &uid.to_string(),
+        )?),
+    };
+    let remote = args.dest.is_some();
+    let anonymize_export = args.anonymize;
+    let split_by = args.split_by.map(|x| (x, args.zip));
+    let report = args.grade_report.then_some(args.grade_report_csv);
+    #[cfg(feature = "search")]
+    let search_index = args.search_index;
+    if !remote && !merge && !confirm_merge(&export_dir).await? {
+        return Err(anyhow!("{:?} already exists", export_dir));
+    }
 
-        let course_files_info = client
-            .execute(
-                Request::get(&format!("courses/{course_id}/folder/0"))?
-                    .into_schoology(&token_info)?,
-            )
+    let summary = Arc::new(Summary::new(args.log_format, storage.clone()));
+    let storage = export_storage(&summary, storage, args.compress_json);
+    let result = run_export(
+        args,
+        config,
+        client,
+        token_info,
+        uid,
+        export_dir.clone(),
+        summary.clone(),
+        storage,
+        limiter,
+    )
+    .await;
+    if remote || export_dir.exists() {
+        summary
+            .write(&export_dir, result.as_ref().err())
             .await
-            .context("failed to request course files")?
-            .json::<Value>()
-            .await?;
-
-        export_directory(course_files_root, &client, &token_info, &course_files_info)
+            .context("failed to write summary")?;
+    }
+    result?;
+    if anonymize_export {
+        anonymize(export_dir.clone())
+            .await
+            .context("failed to anonymize export")?;
+    }
+    if !remote {
+        write_indexes(export_dir.clone())
+            .await
+            .context("failed to write directory indexes")?;
+    }
+    if let Some(write_csv) = report {
+        grade_report(export_dir.clone(), write_csv)
+            .await
+            .context("failed to write grade report")?;
+    }
+    #[cfg(feature = "search")]
+    if search_index {
+        search::build_index(export_dir.clone())
+            .await
+            .context("failed to build search index")?;
+    }
+    if let Some((split_by, zip)) = split_by {
+        split(export_dir, split_by, zip)
             .await
-            .context("failed to export course files")?;
+            .context("failed to split export")?;
     }
 
     let end = Instant::now();
@@ -0,0 +1,268 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::Context;
+use log::{info, warn};
+use reqwest::Request;
+use reqwest_middleware::ClientWithMiddleware;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    api_helpers::SchoologyRequestHelper,
+    export::download,
+    storage::{LocalStorage, Storage},
+    throttle::RateLimiter,
+    TokenInfo,
+};
+
+#[derive(Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub path: PathBuf,
+    pub url: String,
+    pub filesize: Option<u64>,
+    pub bytes: u64,
+    pub sha256: String,
+    // listed by a metadata-only export, not downloaded yet
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pending: bool,
+    // the name schoology gave the attachment, when it was saved under a corrected one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub original_name: Option<String>,
+}
+
+impl ManifestEntry {
+    pub fn is_truncated(&self) -> bool {
+        self.filesize.is_some_and(|x| x != self.bytes)
+    }
+}
+
+#[derive(Default)]
+pub struct Manifest {
+    entries: Mutex<Vec<ManifestEntry>>,
+    // entries of the export that is merged into, by absolute path
+    previous: Mutex<HashMap<PathBuf, ManifestEntry>>,
+}
+
+impl Manifest {
+    pub async fn load(export_dir: &Path) -> Self {
+        let previous = tokio::fs::read_to_string(export_dir.join("manifest.json"))
+            .await
+            .ok()
+            .and_then(|x| serde_json::from_str::<Vec<ManifestEntry>>(&x).ok())
+            .unwrap_or_default()
+            .into_iter()
+            .map(|x| (export_dir.join(&x.path), x))
+            .collect();
+        Self {
+            entries: Mutex::default(),
+            previous: Mutex::new(previous),
+        }
+    }
+
+    pub fn add(&self, entry: ManifestEntry) {
+        self.entries.lock().unwrap().push(entry);
+    }
+
+    // keeps the previous download of an attachment if its source, size and hash haven't changed,
+    // or a complete file of an interrupted export that never got to write its manifest. without a
+    // filesize in the metadata, the size on disk and the hash are all there is to compare
+    pub async fn reuse(&self, path: &Path, url: &str, filesize: Option<u64>) -> bool {
+        let previous = {
+            let mut previous = self.previous.lock().unwrap();
+            // attachments saved under a corrected name are found by their url
+            let path = match previous.contains_key(path) {
+                true => Some(path.to_path_buf()),
+                false => previous
+                    .iter()
+                    .find(|(x, entry)| {
+                        entry.original_name.is_some()
+                            && entry.url == url
+                            && x.parent() == path.parent()
+                    })
+                    .map(|(x, _)| x.clone()),
+            };
+            path.and_then(|x| Some((x.clone(), previous.remove(&x)?)))
+        };
+        let Some((path, entry)) = previous else {
+            return self.reuse_complete(path, url, filesize).await;
+        };
+        let intact = !entry.pending
+            && entry.url == url
+            && filesize.is_none_or(|x| x == entry.bytes)
+            && tokio::fs::metadata(&path)
+                .await
+                .is_ok_and(|x| x.len() == entry.bytes)
+            && hash_file(&path).await.is_ok_and(|x| x == entry.sha256);
+        if intact {
+            self.add(ManifestEntry { path, ..entry });
+        }
+        intact
+    }
+
+    // files only get their final name once completely written
+    async fn reuse_complete(&self, path: &Path, url: &str, filesize: Option<u64>) -> bool {
+        let Ok(metadata) = tokio::fs::metadata(path).await else {
+            return false;
+        };
+        let bytes = metadata.len();
+        if filesize.is_some_and(|x| x != bytes) {
+            return false;
+        }
+        let Ok(sha256) = hash_file(path).await else {
+            return false;
+        };
+        self.add(ManifestEntry {
+            path: path.to_path_buf(),
+            url: url.to_string(),
+            filesize,
+            bytes,
+            sha256,
+            pending: false,
+            original_name: None,
+        });
+        true
+    }
+
+    pub async fn write(&self, storage: &dyn Storage, export_dir: &Path) -> anyhow::Result<()> {
+        let entries = std::mem::take(&mut *self.entries.lock().unwrap())
+            .into_iter()
+            .map(|mut x| {
+                if let Ok(relative) = x.path.strip_prefix(export_dir) {
+                    x.path = relative.to_path_buf();
+                }
+                x
+            })
+            .collect::<Vec<_>>();
+        write_entries(storage, export_dir, &entries).await
+    }
+}
+
+async fn write_entries(
+    storage: &dyn Storage,
+    export_dir: &Path,
+    entries: &[ManifestEntry],
+) -> anyhow::Result<()> {
+    storage
+        .write(
+            &export_dir.join("manifest.json"),
+            serde_json::to_string_pretty(entries)?.into(),
+        )
+        .await
+        .context("failed to write manifest")
+}
+
+async fn hash_file(path: &Path) -> anyhow::Result<String> {
+    let contents = tokio::fs::read(path).await?;
+    Ok(format!("{:x}", Sha256::digest(contents)))
+}
+
+async fn redownload(
+    path: &Path,
+    url: &str,
+    client: &ClientWithMiddleware,
+    token_info: &TokenInfo,
+    limiter: Option<&RateLimiter>,
+) -> anyhow::Result<(u64, String)> {
+    if let Some(parent) = path.parent() {
+        LocalStorage.create_dir_all(parent).await?;
+    }
+    download(
+        &LocalStorage,
+        path,
+        client
+            .execute(Request::get_raw(url)?.into_schoology(token_info)?)
+            .await
+            .context("failed to request file attachment")?
+            .error_for_status()?,
+        limiter,
+    )
+    .await
+    .context("failed to save file attachment")
+}
+
+pub async fn verify_export(
+    export_dir: PathBuf,
+    client: &ClientWithMiddleware,
+    token_info: &TokenInfo,
+    limiter: Option<&RateLimiter>,
+    pending_only: bool,
+) -> anyhow::Result<()> {
+    let mut entries: Vec<ManifestEntry> = serde_json::from_str(
+        &tokio::fs::read_to_string(export_dir.join("manifest.json"))
+            .await
+            .context("failed to read manifest")?,
+    )
+    .context("failed to parse manifest")?;
+
+    let mut redownloaded = 0;
+    let mut failed = 0;
+    for entry in &mut entries {
+        if pending_only && !entry.pending {
+            continue;
+        }
+        let path = export_dir.join(&entry.path);
+        let intact = match tokio::fs::metadata(&path).await {
+            Ok(metadata) if metadata.len() == entry.bytes && !entry.is_truncated() => {
+                match hash_file(&path).await {
+                    Ok(sha256) => sha256 == entry.sha256,
+                    Err(err) => {
+                        warn!("failed to hash {:?}: {:#}", entry.path, err);
+                        failed += 1;
+                        continue;
+                    }
+                }
+            }
+            _ => false,
+        };
+        if intact {
+            continue;
+        }
+
+        info!("re-downloading {:?}", entry.path);
+        // a file that can't be fetched again keeps its entry, the others are still written
+        let (bytes, sha256) = match redownload(&path, &entry.url, client, token_info, limiter).await
+        {
+            Ok(x) => x,
+            Err(err) => {
+                warn!("failed to re-download {:?}: {:#}", entry.path, err);
+                failed += 1;
+                continue;
+            }
+        };
+        entry.bytes = bytes;
+        entry.sha256 = sha256;
+        entry.pending = false;
+        redownloaded += 1;
+
+        if entry.is_truncated() {
+            warn!(
+                "{:?} is still {} bytes instead of {} bytes",
+                entry.path,
+                entry.bytes,
+                entry.filesize.unwrap_or_default()
+            );
+            failed += 1;
+        }
+    }
+
+    // an existing export is always verified on disk
+    write_entries(&LocalStorage, &export_dir, &entries).await?;
+    match pending_only {
+        true => info!(
+            "fetched {} attachments, {} mismatched",
+            redownloaded, failed
+        ),
+        false => info!(
+            "verified {} files, re-downloaded {}, {} still mismatched",
+            entries.len(),
+            redownloaded,
+            failed
+        ),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,41 @@
+use std::time::{Duration, SystemTime};
+
+use clap::ValueEnum;
+use serde_json::Value;
+
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    #[default]
+    Json,
+    // also writes the rich text of updates, messages and pages as markdown
+    Markdown,
+}
+
+pub fn date(timestamp: Option<i64>) -> Value {
+    match timestamp.and_then(|x| u64::try_from(x).ok()) {
+        Some(timestamp) => humantime::format_rfc3339_seconds(
+            SystemTime::UNIX_EPOCH + Duration::from_secs(timestamp),
+        )
+        .to_string()
+        .into(),
+        None => Value::Null,
+    }
+}
+
+pub fn html_to_markdown(html: &str) -> String {
+    html2md::parse_html(html).trim().to_string()
+}
+
+// json scalars are valid yaml, so the values don't need their own escaping
+pub fn markdown_document(front_matter: &[(&str, Value)], body: &str) -> String {
+    let mut document = String::from("---\n");
+    for (key, value) in front_matter {
+        if !value.is_null() {
+            document += &format!("{key}: {value}\n");
+        }
+    }
+    document += "---\n\n";
+    document += body;
+    document.push('\n');
+    document
+}
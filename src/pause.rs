@@ -0,0 +1,140 @@
+use std::{
+    collections::VecDeque,
+    future::Future,
+    io::IsTerminal,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, Once,
+    },
+};
+
+use http::Extensions;
+use log::info;
+use reqwest::{Request, Response};
+use reqwest_middleware::{Middleware, Next};
+use tokio::sync::{oneshot, Notify};
+
+static PAUSED: AtomicBool = AtomicBool::new(false);
+static RESUMED: Notify = Notify::const_new();
+static LISTENING: AtomicBool = AtomicBool::new(false);
+static READER: Once = Once::new();
+static INPUT: Mutex<Input> = Mutex::new(Input {
+    prompts: VecDeque::new(),
+    lines: VecDeque::new(),
+    closed: false,
+});
+
+// stdin is only read by one thread, the prompts get their lines from it before the pause commands do
+struct Input {
+    prompts: VecDeque<oneshot::Sender<String>>,
+    // lines typed ahead of a prompt while nothing listens for commands
+    lines: VecDeque<String>,
+    closed: bool,
+}
+
+// requests wait here while the export is paused, the ones already sent finish normally
+pub struct PauseMiddleware;
+
+#[async_trait::async_trait]
+impl Middleware for PauseMiddleware {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        loop {
+            let resumed = RESUMED.notified();
+            if !PAUSED.load(Ordering::SeqCst) {
+                break;
+            }
+            resumed.await;
+        }
+        next.run(req, extensions).await
+    }
+}
+
+// SCHOOLOGY_INTERACTIVE answers the prompts from a pipe, e.g. when a script drives the export
+pub fn interactive() -> bool {
+    std::io::stdin().is_terminal() || std::env::var_os("SCHOOLOGY_INTERACTIVE").is_some()
+}
+
+fn command(line: &str) {
+    match line.trim() {
+        "p" if !PAUSED.swap(true, Ordering::SeqCst) => {
+            info!(
+                "paused, the requests already sent are finished, type r and press ENTER to resume"
+            );
+        }
+        "r" if PAUSED.swap(false, Ordering::SeqCst) => {
+            RESUMED.notify_waiters();
+            info!("resumed");
+        }
+        _ => {}
+    }
+}
+
+fn start_reader() {
+    // a thread of its own, a pending read on the runtime would keep the process alive after the export
+    READER.call_once(|| {
+        std::thread::spawn(|| {
+            for line in std::io::stdin().lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                let mut input = INPUT.lock().unwrap();
+                let mut line = Some(line);
+                // a prompt that was given up on doesn't take the line
+                while let Some(prompt) = input.prompts.pop_front() {
+                    match prompt.send(line.take().unwrap_or_default()) {
+                        Ok(()) => break,
+                        Err(unread) => line = Some(unread),
+                    }
+                }
+                let Some(line) = line else {
+                    continue;
+                };
+                if LISTENING.load(Ordering::SeqCst) {
+                    drop(input);
+                    command(&line);
+                } else {
+                    input.lines.push_back(line);
+                }
+            }
+            let mut input = INPUT.lock().unwrap();
+            input.closed = true;
+            input.prompts.clear();
+        });
+    });
+}
+
+// the next line typed by the user, empty once stdin is closed. the prompt is registered right away,
+// so it has to be created before the user is asked, the pause commands would take the line otherwise
+pub fn read_line() -> impl Future<Output = String> {
+    start_reader();
+    let mut input = INPUT.lock().unwrap();
+    let answer = match input.lines.pop_front() {
+        Some(line) => Ok(line),
+        None if input.closed => Ok(String::new()),
+        None => {
+            let (sender, receiver) = oneshot::channel();
+            input.prompts.push_back(sender);
+            Err(receiver)
+        }
+    };
+    async move {
+        match answer {
+            Ok(line) => line,
+            Err(receiver) => receiver.await.unwrap_or_default(),
+        }
+    }
+}
+
+// "p" and ENTER pauses the export, "r" and ENTER resumes it
+pub fn listen() {
+    if !interactive() || LISTENING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    info!("type p and press ENTER to pause the export, r to resume it");
+    start_reader();
+}
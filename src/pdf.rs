@@ -0,0 +1,73 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Context};
+use tokio::process::Command;
+use uuid::Uuid;
+
+use crate::storage::Storage;
+
+// renders through a headless chromium, so the pdfs look like the page in a browser
+pub struct PdfRenderer {
+    program: String,
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+impl PdfRenderer {
+    pub fn new(program: String) -> Self {
+        Self { program }
+    }
+
+    // relative links to embedded files are resolved against the directory of the pdf
+    pub async fn render(
+        &self,
+        storage: &dyn Storage,
+        path: &Path,
+        title: &str,
+        body: &str,
+    ) -> anyhow::Result<()> {
+        let base = std::path::absolute(path.parent().unwrap_or(Path::new(".")))?;
+        let document = format!(
+            "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><base href=\"file://{}/\"><title>{}</title></head><body><h1>{}</h1>{}</body></html>",
+            base.display(),
+            escape_html(title),
+            escape_html(title),
+            body
+        );
+
+        let name = Uuid::new_v4();
+        let html_path = std::env::temp_dir().join(format!("export-schoology-{name}.html"));
+        let pdf_path = std::env::temp_dir().join(format!("export-schoology-{name}.pdf"));
+        tokio::fs::write(&html_path, document).await?;
+        let output = Command::new(&self.program)
+            .arg("--headless")
+            .arg("--disable-gpu")
+            .arg("--no-pdf-header-footer")
+            .arg(format!("--print-to-pdf={}", pdf_path.display()))
+            .arg(format!("file://{}", html_path.display()))
+            .output()
+            .await
+            .with_context(|| format!("failed to run {:?}", self.program));
+        let _ = tokio::fs::remove_file(&html_path).await;
+        let output = output?;
+        if !output.status.success() {
+            let _ = tokio::fs::remove_file(&pdf_path).await;
+            return Err(anyhow!(
+                "{:?} failed to render {:?}: {}",
+                self.program,
+                path,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+
+        let pdf = tokio::fs::read(&pdf_path)
+            .await
+            .context("failed to read rendered pdf")?;
+        let _ = tokio::fs::remove_file(&pdf_path).await;
+        storage.write(path, pdf).await
+    }
+}
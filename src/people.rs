@@ -0,0 +1,275 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{context::ExportContext, richtext::escape_html, ValueHelper};
+
+pub fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+// course admins are the teachers unless schoology names the role, e.g. for teaching assistants
+fn member_role(enrollment: &Value) -> String {
+    match enrollment
+        .get_string("role_title")
+        .filter(|x| !x.is_empty())
+    {
+        Some(role) => role.to_lowercase(),
+        None if enrollment.get_string_lossy("admin").as_deref() == Some("1") => {
+            "teacher".to_string()
+        }
+        None => "student".to_string(),
+    }
+}
+
+pub fn members_csv(enrollments: &[Value], ctx: &ExportContext) -> String {
+    let mut csv = String::from("uid,name,role,status,profile\n");
+    for enrollment in enrollments {
+        let uid = enrollment
+            .get_string_lossy("uid")
+            .and_then(|x| x.parse::<i64>().ok());
+        if uid.is_some_and(|x| ctx.config.redact_classmates() && ctx.is_classmate(x)) {
+            continue;
+        }
+        let fields = [
+            uid.map(|x| x.to_string()).unwrap_or_default(),
+            enrollment.get_string("name_display").unwrap_or_default(),
+            member_role(enrollment),
+            enrollment.get_string_lossy("status").unwrap_or_default(),
+            enrollment.get_string("profile").unwrap_or_default(),
+        ];
+        csv += &fields.map(|x| csv_field(&x)).join(",");
+        csv.push('\n');
+    }
+    csv
+}
+
+#[derive(Serialize)]
+struct Appearance {
+    path: PathBuf,
+    kind: &'static str,
+    timestamp: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct Enrollment {
+    course: String,
+    enrollment_id: Option<String>,
+    role: String,
+}
+
+#[derive(Serialize)]
+struct IndexEntry<'a> {
+    name: Option<&'a str>,
+    roles: BTreeSet<&'a str>,
+    profile: Option<&'a Path>,
+    enrollments: &'a [Enrollment],
+    courses: &'a BTreeSet<String>,
+    // files referencing the user by kind, e.g. message or update comment
+    references: BTreeMap<&'static str, BTreeSet<&'a Path>>,
+}
+
+#[derive(Serialize, Default)]
+struct Person {
+    uid: i64,
+    name: Option<String>,
+    role: Option<String>,
+    profile: Option<PathBuf>,
+    courses: BTreeSet<String>,
+    enrollments: Vec<Enrollment>,
+    first_seen: Option<i64>,
+    last_seen: Option<i64>,
+    appearances: Vec<Appearance>,
+}
+
+#[derive(Default)]
+pub struct People(Mutex<BTreeMap<i64, Person>>);
+
+impl People {
+    pub fn record(&self, uid: i64, path: &Path, kind: &'static str, timestamp: Option<i64>) {
+        let mut people = self.0.lock().unwrap();
+        let person = people.entry(uid).or_insert_with(|| Person {
+            uid,
+            ..Default::default()
+        });
+        if let Some(timestamp) = timestamp {
+            person.first_seen = Some(person.first_seen.map_or(timestamp, |x| x.min(timestamp)));
+            person.last_seen = Some(person.last_seen.map_or(timestamp, |x| x.max(timestamp)));
+        }
+        person.appearances.push(Appearance {
+            path: path.to_path_buf(),
+            kind,
+            timestamp,
+        });
+    }
+
+    // grades and attendance reference enrollment ids instead of users
+    pub fn record_enrollment(&self, uid: i64, path: &Path, course_id: &str, enrollment: &Value) {
+        self.record(uid, path, "enrollment", None);
+        let mut people = self.0.lock().unwrap();
+        if let Some(person) = people.get_mut(&uid) {
+            person.enrollments.push(Enrollment {
+                course: course_id.to_string(),
+                enrollment_id: enrollment.get_string_lossy("id"),
+                role: member_role(enrollment),
+            });
+        }
+    }
+
+    pub fn set_profile(&self, uid: i64, path: &Path, user_info: &Value) {
+        let mut people = self.0.lock().unwrap();
+        let person = people.entry(uid).or_insert_with(|| Person {
+            uid,
+            ..Default::default()
+        });
+        person.name = user_info.get_string("name_display");
+        person.profile = Some(path.to_path_buf());
+    }
+
+    pub async fn write(&self, export_dir: &Path, ctx: &ExportContext) -> anyhow::Result<()> {
+        let people = {
+            let mut people = std::mem::take(&mut *self.0.lock().unwrap());
+            people.retain(|uid, _| !ctx.config.redact_classmates() || !ctx.is_classmate(*uid));
+            for person in people.values_mut() {
+                person.role = if person.uid == ctx.uid {
+                    Some("self".to_string())
+                } else if ctx.teachers.contains(&person.uid) {
+                    Some("teacher".to_string())
+                } else {
+                    None
+                };
+                for path in person
+                    .profile
+                    .iter_mut()
+                    .chain(person.appearances.iter_mut().map(|x| &mut x.path))
+                {
+                    if let Ok(relative) = path.strip_prefix(export_dir) {
+                        *path = relative.to_path_buf();
+                    }
+                }
+                person.courses = person
+                    .appearances
+                    .iter()
+                    .filter_map(|x| {
+                        let mut components = x.path.components();
+                        (components.next()?.as_os_str() == "courses")
+                            .then(|| components.next())
+                            .flatten()
+                            .map(|x| x.as_os_str().to_string_lossy().to_string())
+                    })
+                    .filter(|x| x != "info.json")
+                    .collect();
+            }
+            people
+        };
+
+        ctx.storage
+            .write(
+                &export_dir.join("people.json"),
+                serde_json::to_string_pretty(&people.values().collect::<Vec<_>>())?.into(),
+            )
+            .await?;
+
+        // keyed by uid, so references found anywhere in the export can be resolved to a person
+        let index = people
+            .values()
+            .map(|person| {
+                let mut references = BTreeMap::<_, BTreeSet<_>>::new();
+                for appearance in &person.appearances {
+                    references
+                        .entry(appearance.kind)
+                        .or_default()
+                        .insert(appearance.path.as_path());
+                }
+                let entry = IndexEntry {
+                    name: person.name.as_deref(),
+                    roles: person
+                        .role
+                        .iter()
+                        .chain(person.enrollments.iter().map(|x| &x.role))
+                        .map(|x| x.as_str())
+                        .collect(),
+                    profile: person.profile.as_deref(),
+                    enrollments: &person.enrollments,
+                    courses: &person.courses,
+                    references,
+                };
+                (person.uid, entry)
+            })
+            .collect::<BTreeMap<_, _>>();
+        ctx.storage
+            .create_dir_all(&export_dir.join("users"))
+            .await?;
+        ctx.storage
+            .write(
+                &export_dir.join("users").join("index.json"),
+                serde_json::to_string_pretty(&index)?.into(),
+            )
+            .await?;
+
+        let format_timestamp = |x: Option<i64>| {
+            x.map(|x| {
+                humantime::format_rfc3339_seconds(
+                    SystemTime::UNIX_EPOCH + Duration::from_secs(x.max(0) as u64),
+                )
+                .to_string()
+            })
+            .unwrap_or_default()
+        };
+        let link = |path: &Path, text: &str| {
+            format!(
+                "<a href=\"{}\">{}</a>",
+                escape_html(&path.to_string_lossy()),
+                escape_html(text)
+            )
+        };
+
+        let mut html = String::from(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>People</title>\n</head>\n<body>\n<table>\n<tr><th>Name</th><th>Role</th><th>Courses</th><th>First seen</th><th>Last seen</th><th>Appears in</th></tr>\n",
+        );
+        for person in people.values() {
+            let name = person
+                .name
+                .clone()
+                .unwrap_or_else(|| person.uid.to_string());
+            html += &format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                match &person.profile {
+                    Some(profile) => link(profile, &name),
+                    None => escape_html(&name),
+                },
+                escape_html(person.role.as_deref().unwrap_or_default()),
+                person
+                    .courses
+                    .iter()
+                    .map(|x| link(&Path::new("courses").join(x), x))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                format_timestamp(person.first_seen),
+                format_timestamp(person.last_seen),
+                person
+                    .appearances
+                    .iter()
+                    .map(|x| link(&x.path, x.kind))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            );
+        }
+        html += "</table>\n</body>\n</html>\n";
+        ctx.storage
+            .write(&export_dir.join("people.html"), html.into())
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,158 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Mutex, MutexGuard,
+    },
+};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Running,
+    Done,
+    Failed,
+}
+
+pub struct PhaseProgress {
+    pub name: String,
+    pub status: Status,
+    pub items: u64,
+}
+
+pub struct CourseProgress {
+    pub id: String,
+    pub title: String,
+    pub dir: PathBuf,
+    pub status: Status,
+    pub items: BTreeMap<String, u64>,
+    pub current: Option<String>,
+}
+
+pub struct Progress {
+    pub phases: Vec<PhaseProgress>,
+    pub courses: Vec<CourseProgress>,
+    pub errors: Vec<String>,
+    // the last info line of the log
+    pub status: String,
+}
+
+// only tracked while something shows it, the export doesn't pay for it otherwise
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static DOWNLOADED: AtomicU64 = AtomicU64::new(0);
+static PROGRESS: Mutex<Progress> = Mutex::new(Progress {
+    phases: Vec::new(),
+    courses: Vec::new(),
+    errors: Vec::new(),
+    status: String::new(),
+});
+
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+fn tracked() -> Option<MutexGuard<'static, Progress>> {
+    ENABLED
+        .load(Ordering::Relaxed)
+        .then(|| PROGRESS.lock().unwrap_or_else(|x| x.into_inner()))
+}
+
+pub fn progress() -> MutexGuard<'static, Progress> {
+    PROGRESS.lock().unwrap_or_else(|x| x.into_inner())
+}
+
+// bytes of attachments and images downloaded so far
+pub fn downloaded() -> u64 {
+    DOWNLOADED.load(Ordering::Relaxed)
+}
+
+pub fn download(bytes: usize) {
+    DOWNLOADED.fetch_add(bytes as u64, Ordering::Relaxed);
+}
+
+fn finish_phase(progress: &mut Progress) {
+    for phase in &mut progress.phases {
+        if phase.status == Status::Running {
+            phase.status = Status::Done;
+        }
+    }
+}
+
+pub fn phase(name: &str) {
+    let Some(mut progress) = tracked() else {
+        return;
+    };
+    finish_phase(&mut progress);
+    progress.phases.push(PhaseProgress {
+        name: name.to_string(),
+        status: Status::Running,
+        items: 0,
+    });
+}
+
+pub fn finish() {
+    if let Some(mut progress) = tracked() {
+        finish_phase(&mut progress);
+    }
+}
+
+pub fn course_started(id: &str, title: &str, dir: &Path) {
+    let Some(mut progress) = tracked() else {
+        return;
+    };
+    progress.courses.push(CourseProgress {
+        id: id.to_string(),
+        title: title.to_string(),
+        dir: dir.to_path_buf(),
+        status: Status::Running,
+        items: BTreeMap::new(),
+        current: None,
+    });
+}
+
+pub fn course_failed(id: &str) {
+    let Some(mut progress) = tracked() else {
+        return;
+    };
+    if let Some(course) = progress.courses.iter_mut().rev().find(|x| x.id == id) {
+        course.status = Status::Failed;
+    }
+}
+
+pub fn entity(kind: &str, path: &Path) {
+    let Some(mut progress) = tracked() else {
+        return;
+    };
+    if let Some(phase) = progress.phases.last_mut() {
+        phase.items += 1;
+    }
+    let Some(course) = progress
+        .courses
+        .iter_mut()
+        .rev()
+        .find(|x| path.starts_with(&x.dir))
+    else {
+        return;
+    };
+    match kind {
+        "course" => {
+            course.status = Status::Done;
+            course.current = None;
+        }
+        _ => {
+            *course.items.entry(kind.to_string()).or_default() += 1;
+            let name = path.strip_prefix(&course.dir).unwrap_or(path);
+            course.current = Some(format!("{} {}", kind, name.display()));
+        }
+    }
+}
+
+pub fn log(error: bool, message: String) {
+    let Some(mut progress) = tracked() else {
+        return;
+    };
+    match error {
+        true => progress.errors.push(message),
+        false => progress.status = message,
+    }
+}
@@ -0,0 +1,91 @@
+use std::path::PathBuf;
+
+use http::Extensions;
+use log::warn;
+use reqwest::{Request, Response, ResponseBuilderExt};
+use reqwest_middleware::{Middleware, Next};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+// saves the raw api responses of an export, file downloads are left out
+pub struct RecordingMiddleware {
+    dir: PathBuf,
+    api_base: String,
+}
+
+impl RecordingMiddleware {
+    pub fn new(dir: PathBuf, api_base: String) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self { dir, api_base })
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for RecordingMiddleware {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        if !req.url().as_str().starts_with(&self.api_base) {
+            return next.run(req, extensions).await;
+        }
+        let method = req.method().clone();
+        let url = req.url().to_string();
+        let request_body = req
+            .body()
+            .and_then(|x| x.as_bytes())
+            .map(|x| x.to_vec())
+            .unwrap_or_default();
+
+        let response = next.run(req, extensions).await?;
+        let response_url = response.url().clone();
+        let status = response.status();
+        let version = response.version();
+        let headers = response.headers().clone();
+        let body = response.bytes().await?;
+
+        // the oauth parameters are in the headers, so the url and body identify a request
+        let key = format!(
+            "{:x}",
+            Sha256::new()
+                .chain_update(method.as_str())
+                .chain_update(&url)
+                .chain_update(&request_body)
+                .finalize()
+        );
+        let recording = json!({
+            "method": method.as_str(),
+            "url": url,
+            "request": (!request_body.is_empty())
+                .then(|| String::from_utf8_lossy(&request_body).to_string()),
+            "status": status.as_u16(),
+            "recorded": humantime::format_rfc3339_seconds(std::time::SystemTime::now()).to_string(),
+            "body": serde_json::from_slice::<Value>(&body)
+                .unwrap_or_else(|_| String::from_utf8_lossy(&body).into()),
+        });
+        let path = self.dir.join(format!("{key}.json"));
+        match serde_json::to_vec_pretty(&recording) {
+            Ok(recording) => {
+                if let Err(err) = tokio::fs::write(&path, recording).await {
+                    warn!("failed to record {:?}: {}", url, err);
+                }
+            }
+            Err(err) => warn!("failed to record {:?}: {}", url, err),
+        }
+
+        // the url of the response is kept, redirects to the login page are detected by it
+        let mut rebuilt = http::Response::builder()
+            .status(status)
+            .version(version)
+            .url(response_url);
+        if let Some(rebuilt_headers) = rebuilt.headers_mut() {
+            *rebuilt_headers = headers;
+        }
+        let rebuilt = rebuilt
+            .body(body)
+            .map_err(|x| reqwest_middleware::Error::Middleware(x.into()))?;
+        Ok(Response::from(rebuilt))
+    }
+}
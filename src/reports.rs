@@ -0,0 +1,224 @@
+use std::collections::{BTreeSet, HashMap};
+
+use serde_json::{json, Value};
+
+use crate::{markdown::date, people::csv_field, ValueHelper};
+
+// the grades of the section joined with the assignment titles and due dates for spreadsheets
+pub fn grades_csv(grades_info: &Value, assignments: &[Value]) -> String {
+    let assignments = assignments
+        .iter()
+        .filter_map(|x| Some((x.get_string_lossy("id")?, x)))
+        .collect::<HashMap<_, _>>();
+
+    let mut csv = String::from(
+        "grading_period,assignment_id,assignment,category,due,points_earned,points_possible,graded\n",
+    );
+    for section in grades_info.get_array("section").unwrap_or_default() {
+        let categories = section
+            .get_array("grading_category")
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|x| Some((x.get_string_lossy("id")?, x.get_string("title")?)))
+            .collect::<HashMap<_, _>>();
+        for period in section.get_array("period").unwrap_or_default() {
+            let period_title = period
+                .get_string("period_title")
+                .or_else(|| period.get_string_lossy("period_id"))
+                .unwrap_or_default();
+            for grade in period.get_array("assignment").unwrap_or_default() {
+                let assignment_id = grade.get_string_lossy("assignment_id").unwrap_or_default();
+                let assignment = assignments.get(&assignment_id);
+                let category = grade
+                    .get_string_lossy("category_id")
+                    .or_else(|| assignment.and_then(|x| x.get_string_lossy("grading_category")))
+                    .map(|x| categories.get(&x).cloned().unwrap_or(x))
+                    .unwrap_or_default();
+                let fields = [
+                    period_title.clone(),
+                    assignment_id,
+                    assignment
+                        .and_then(|x| x.get_string("title"))
+                        .unwrap_or_default(),
+                    category,
+                    assignment
+                        .and_then(|x| x.get_string("due"))
+                        .unwrap_or_default(),
+                    grade.get_string_lossy("grade").unwrap_or_default(),
+                    grade
+                        .get_string_lossy("max_points")
+                        .or_else(|| assignment.and_then(|x| x.get_string_lossy("max_points")))
+                        .unwrap_or_default(),
+                    date(grade.get_int("timestamp"))
+                        .as_str()
+                        .unwrap_or_default()
+                        .to_string(),
+                ];
+                csv += &fields.map(|x| csv_field(&x)).join(",");
+                csv.push('\n');
+            }
+        }
+    }
+    csv
+}
+
+// fields that tell when and how a grade was changed, history fields only show up for teachers and admins
+const GRADE_AUDIT_KEYS: &[&str] = &[
+    "enrollment_id",
+    "grade",
+    "max_points",
+    "exception",
+    "is_final",
+    "override",
+    "pending",
+    "comment",
+    "comment_status",
+    "timestamp",
+    "last_updated",
+    "graded_by",
+];
+
+pub fn grade_history(assignment_grade: &Value) -> Value {
+    let grades = assignment_grade
+        .get("grades")
+        .and_then(|x| x.get_array("grade"))
+        .unwrap_or_default()
+        .into_iter()
+        .map(|grade| {
+            let mut entry = grade.as_object().cloned().unwrap_or_default();
+            entry.retain(|key, _| {
+                GRADE_AUDIT_KEYS.contains(&key.as_str())
+                    || key.contains("history")
+                    || key.contains("revision")
+            });
+            entry.insert("graded".to_string(), date(grade.get_int("timestamp")));
+            Value::Object(entry)
+        })
+        .collect::<Vec<_>>();
+    json!({ "grade": grades })
+}
+
+// how the class did, only included when the teacher shows the statistics to students
+const STATISTICS_KEYS: &[&str] = &[
+    "statistics",
+    "stats",
+    "average",
+    "class_average",
+    "mean",
+    "median",
+    "high",
+    "low",
+    "highest",
+    "lowest",
+    "standard_deviation",
+];
+
+pub fn grade_statistics(assignment_grade: &Value) -> Option<Value> {
+    let statistics = std::iter::once(assignment_grade.clone())
+        .chain(
+            assignment_grade
+                .get("grades")
+                .and_then(|x| x.get_array("grade"))
+                .unwrap_or_default(),
+        )
+        .filter_map(|x| x.as_object().cloned())
+        .flat_map(|x| x.into_iter())
+        .filter(|(key, value)| STATISTICS_KEYS.contains(&key.as_str()) && !value.is_null())
+        .collect::<serde_json::Map<_, _>>();
+    (!statistics.is_empty()).then_some(Value::Object(statistics))
+}
+
+// overall comments per grading period, they are only shown on the grades page
+pub fn grade_comments(grades_info: &Value) -> Vec<Value> {
+    let mut comments = Vec::new();
+    for section in grades_info.get_array("section").unwrap_or_default() {
+        let periods = section
+            .get_array("period")
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|x| {
+                Some((
+                    x.get_string_lossy("period_id")?,
+                    x.get_string("period_title")?,
+                ))
+            })
+            .collect::<HashMap<_, _>>();
+        for final_grade in section.get_array("final_grade").unwrap_or_default() {
+            let Some(comment) = final_grade
+                .get_string("comment")
+                .filter(|x| !x.trim().is_empty())
+            else {
+                continue;
+            };
+            let period_id = final_grade.get_string_lossy("period_id");
+            comments.push(json!({
+                "period_id": period_id,
+                "period_title": period_id.and_then(|x| periods.get(&x)),
+                "grade": final_grade.get("grade"),
+                "comment": comment,
+                "comment_status": final_grade.get("comment_status"),
+            }));
+        }
+    }
+    comments
+}
+
+// how the gradebook of a section is set up, enough to rebuild it elsewhere
+pub fn grade_setup(section_info: &Value, categories: &Value, scales: Option<&Value>) -> Value {
+    let options = section_info.get("options").cloned().unwrap_or_default();
+    let rounding = options
+        .as_object()
+        .into_iter()
+        .flatten()
+        .filter(|(key, _)| key.contains("round"))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect::<serde_json::Map<_, _>>();
+    let categories = categories
+        .get_array("grading_category")
+        .unwrap_or_default()
+        .into_iter()
+        .map(|category| {
+            json!({
+                "id": category.get("id"),
+                "title": category.get("title"),
+                "weight": category.get("weight"),
+                "calculation_type": category.get("calculation_type"),
+                "drop_lowest": category.get("delete_lowest"),
+                "rounding": category.get("rounding"),
+            })
+        })
+        .collect::<Vec<_>>();
+    json!({
+        "weighted_categories": options.get("weighted_grading_categories"),
+        "default_grading_scale_id": options.get("default_grading_scale_id"),
+        "rounding": rounding,
+        "grading_category": categories,
+        "grading_scale": scales.and_then(|x| x.get_array("grading_scale")),
+    })
+}
+
+// one row per user with every scalar field of their usage, the fields differ between deployments
+pub fn analytics_csv(users: &[Value]) -> String {
+    let columns = users
+        .iter()
+        .filter_map(|x| x.as_object())
+        .flat_map(|x| x.iter())
+        .filter(|(key, value)| *key != "uid" && !value.is_object() && !value.is_array())
+        .map(|(key, _)| key.as_str())
+        .collect::<BTreeSet<_>>();
+    let mut csv = std::iter::once("uid")
+        .chain(columns.iter().copied())
+        .map(csv_field)
+        .collect::<Vec<_>>()
+        .join(",");
+    csv.push('\n');
+    for user in users {
+        let fields = std::iter::once("uid")
+            .chain(columns.iter().copied())
+            .map(|key| csv_field(&user.get_string_lossy(key).unwrap_or_default()))
+            .collect::<Vec<_>>();
+        csv += &fields.join(",");
+        csv.push('\n');
+    }
+    csv
+}
@@ -0,0 +1,89 @@
+const EMBED_TAGS: &[&str] = &["img", "iframe"];
+
+// iframes elsewhere are usually video players that don't work as a local file
+fn is_mirrored(tag_name: &str, url: &str) -> bool {
+    tag_name != "iframe"
+        || url
+            .split("//")
+            .nth(1)
+            .and_then(|x| x.split(['/', '?', '#']).next())
+            .is_some_and(|x| x == "schoology.com" || x.ends_with(".schoology.com"))
+}
+
+pub fn attribute_value(tag: &str, attribute: &str) -> Option<String> {
+    let lower = tag.to_ascii_lowercase();
+    let mut search_from = 0;
+    while let Some(idx) = lower[search_from..].find(attribute) {
+        let start = search_from + idx;
+        search_from = start + attribute.len();
+
+        let preceded_by_space = lower[..start]
+            .chars()
+            .next_back()
+            .is_some_and(|x| x.is_whitespace());
+        let rest = lower[search_from..].trim_start();
+        if !preceded_by_space || !rest.starts_with('=') {
+            continue;
+        }
+
+        let value_start = tag.len() - rest[1..].trim_start().len();
+        let value = &tag[value_start..];
+        return match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => value[1..].split(quote).next().map(|x| x.to_string()),
+            Some(_) => value
+                .split(|x: char| x.is_whitespace() || x == '>')
+                .next()
+                .map(|x| x.to_string()),
+            None => None,
+        };
+    }
+    None
+}
+
+// returns the src attributes exactly as they appear in the html so they can be replaced later
+pub fn embedded_urls(html: &str) -> Vec<String> {
+    let lower = html.to_ascii_lowercase();
+    let mut urls = Vec::new();
+    let mut search_from = 0;
+    while let Some(idx) = lower[search_from..].find('<') {
+        let start = search_from + idx;
+        let end = lower[start..]
+            .find('>')
+            .map(|x| start + x)
+            .unwrap_or(lower.len());
+        search_from = end;
+
+        let tag_name = lower[start + 1..end]
+            .split(|x: char| x.is_whitespace() || x == '/')
+            .next()
+            .unwrap_or_default();
+        if !EMBED_TAGS.contains(&tag_name) {
+            continue;
+        }
+
+        if let Some(url) = attribute_value(&html[start..end], "src") {
+            if !url.is_empty()
+                && !url.starts_with("data:")
+                && is_mirrored(tag_name, &url)
+                && !urls.contains(&url)
+            {
+                urls.push(url);
+            }
+        }
+    }
+    urls
+}
+
+pub fn decode_entities(url: &str) -> String {
+    url.replace("&amp;", "&")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+}
+
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
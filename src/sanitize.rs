@@ -0,0 +1,96 @@
+use std::collections::HashSet;
+
+use clap::ValueEnum;
+
+// leaves headroom under the usual 255 byte limit for prefixes and collision suffixes
+const MAX_NAME_LEN: usize = 200;
+const MAX_EXTENSION_LEN: usize = 16;
+
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+fn split_extension(name: &str) -> (&str, &str) {
+    match name.rfind('.') {
+        Some(idx) if idx > 0 && name.len() - idx <= MAX_EXTENSION_LEN => name.split_at(idx),
+        _ => (name, ""),
+    }
+}
+
+fn truncate_to(name: &str, max_len: usize) -> &str {
+    if name.len() <= max_len {
+        return name;
+    }
+    let mut end = max_len;
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    &name[..end]
+}
+
+pub fn sanitize_filename(name: &str) -> String {
+    let mut sanitized: String = name
+        .chars()
+        .map(|x| match x {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+            x if x.is_control() => '_',
+            x => x,
+        })
+        .collect();
+
+    let trimmed = sanitized.trim_end_matches(['.', ' ']).trim_start();
+    sanitized = if trimmed.is_empty() {
+        "_".to_string()
+    } else {
+        trimmed.to_string()
+    };
+
+    let (stem, extension) = split_extension(&sanitized);
+    if RESERVED_NAMES
+        .iter()
+        .any(|x| x.eq_ignore_ascii_case(stem.trim_end()))
+    {
+        sanitized = format!("{stem}_{extension}");
+    }
+
+    if sanitized.len() > MAX_NAME_LEN {
+        let (stem, extension) = split_extension(&sanitized);
+        let stem = truncate_to(stem, MAX_NAME_LEN - extension.len()).trim_end_matches(['.', ' ']);
+        sanitized = format!("{stem}{extension}");
+    }
+
+    sanitized
+}
+
+// windows filesystems are case insensitive, so collisions are checked case insensitively
+#[derive(Default)]
+pub struct UniqueNames(HashSet<String>);
+
+impl UniqueNames {
+    pub fn claim(&mut self, name: &str) -> String {
+        let name = sanitize_filename(name);
+        if self.0.insert(name.to_lowercase()) {
+            return name;
+        }
+
+        let (stem, extension) = split_extension(&name);
+        let mut suffix = 2;
+        loop {
+            let candidate = format!("{stem}_{suffix}{extension}");
+            if self.0.insert(candidate.to_lowercase()) {
+                return candidate;
+            }
+            suffix += 1;
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Layout {
+    // folder items are named after their title
+    #[default]
+    Title,
+    // folder items are named after their id, so renames don't move them between runs
+    Id,
+}
@@ -0,0 +1,200 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context};
+use log::info;
+use serde_json::Value;
+use tantivy::{
+    collector::TopDocs,
+    doc,
+    query::QueryParser,
+    schema::{Field, Schema, Value as _, STORED, STRING, TEXT},
+    Index, IndexWriter, SnippetGenerator, TantivyDocument,
+};
+
+use crate::{
+    compress::read_json,
+    inspect::export_files,
+    markdown::{date, html_to_markdown},
+    ValueHelper,
+};
+
+const INDEX_DIR: &str = "search_index";
+
+struct Fields {
+    kind: Field,
+    title: Field,
+    body: Field,
+    date: Field,
+    path: Field,
+}
+
+fn schema() -> (Schema, Fields) {
+    let mut schema = Schema::builder();
+    let fields = Fields {
+        kind: schema.add_text_field("kind", STRING | STORED),
+        title: schema.add_text_field("title", TEXT | STORED),
+        body: schema.add_text_field("body", TEXT | STORED),
+        date: schema.add_text_field("date", STORED),
+        path: schema.add_text_field("path", STRING | STORED),
+    };
+    (schema.build(), fields)
+}
+
+struct Entry {
+    kind: &'static str,
+    title: String,
+    // html, indexed as text
+    body: String,
+    date: Value,
+}
+
+// updates, messages, pages and assignment descriptions of the export, by the file they are saved in
+fn entries(export_dir: &Path, path: &Path) -> Vec<Entry> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let top = path
+        .components()
+        .next()
+        .map(|x| x.as_os_str().to_string_lossy().to_string())
+        .unwrap_or_default();
+    let full_path = export_dir.join(path);
+    match (top.as_str(), name.as_ref()) {
+        ("updates", name) if name.starts_with("updates_") || name.starts_with("realm_updates_") => {
+            read_json(&full_path)
+                .and_then(|x| x.get_array("update"))
+                .unwrap_or_default()
+                .into_iter()
+                .map(|update| Entry {
+                    kind: "update",
+                    title: format!(
+                        "update {}",
+                        update.get_string_lossy("id").unwrap_or_default()
+                    ),
+                    body: update.get_string("body").unwrap_or_default(),
+                    date: date(update.get_int("created")),
+                })
+                .collect()
+        }
+        ("messages", name) if name.starts_with("message_") && name.ends_with(".json") => {
+            read_json(&full_path)
+                .and_then(|x| x.get_array("message"))
+                .unwrap_or_default()
+                .into_iter()
+                .map(|message| Entry {
+                    kind: "message",
+                    title: message.get_string("subject").unwrap_or_default(),
+                    body: message.get_string("message").unwrap_or_default(),
+                    date: date(message.get_int("last_updated")),
+                })
+                .collect()
+        }
+        ("courses", "info.json") => {
+            let Some(info) = read_json(&full_path) else {
+                return Vec::new();
+            };
+            let title = info.get_string("title").unwrap_or_default();
+            let page = full_path.with_file_name("page.html");
+            if page.exists() {
+                return vec![Entry {
+                    kind: "page",
+                    title,
+                    body: std::fs::read_to_string(page).unwrap_or_default(),
+                    date: date(info.get_int("created")),
+                }];
+            }
+            match info.get_string("description") {
+                Some(description) if info.get("due").is_some() => vec![Entry {
+                    kind: "assignment",
+                    title,
+                    body: description,
+                    date: info.get("due").cloned().unwrap_or_default(),
+                }],
+                _ => Vec::new(),
+            }
+        }
+        _ => Vec::new(),
+    }
+}
+
+fn write_index(export_dir: &Path) -> anyhow::Result<()> {
+    let index_dir = export_dir.join(INDEX_DIR);
+    // rebuilt from scratch, the export may have changed since the last index
+    if index_dir.exists() {
+        std::fs::remove_dir_all(&index_dir)
+            .with_context(|| format!("failed to remove {:?}", index_dir))?;
+    }
+    std::fs::create_dir_all(&index_dir)
+        .with_context(|| format!("failed to create {:?}", index_dir))?;
+    let (schema, fields) = schema();
+    let index = Index::create_in_dir(&index_dir, schema).context("failed to create index")?;
+    let mut writer: IndexWriter = index
+        .writer(50_000_000)
+        .context("failed to open index writer")?;
+
+    let mut indexed = 0;
+    for path in export_files(export_dir) {
+        if path.starts_with(INDEX_DIR) {
+            continue;
+        }
+        for entry in entries(export_dir, &path) {
+            writer.add_document(doc!(
+                fields.kind => entry.kind,
+                fields.title => entry.title,
+                fields.body => html_to_markdown(&entry.body),
+                fields.date => entry.date.as_str().unwrap_or_default(),
+                fields.path => path.to_string_lossy().to_string(),
+            ))?;
+            indexed += 1;
+        }
+    }
+    writer.commit().context("failed to write index")?;
+    info!(
+        "indexed {} updates, messages, pages and assignments",
+        indexed
+    );
+    Ok(())
+}
+
+// a full-text index of a finished export in search_index/, for the search command
+pub async fn build_index(export_dir: PathBuf) -> anyhow::Result<()> {
+    tokio::task::spawn_blocking(move || write_index(&export_dir)).await?
+}
+
+fn text(document: &TantivyDocument, field: Field) -> String {
+    document
+        .get_first(field)
+        .and_then(|x| x.as_str())
+        .unwrap_or_default()
+        .to_string()
+}
+
+pub fn search(export_dir: &Path, query: &str, limit: usize) -> anyhow::Result<()> {
+    let index_dir = export_dir.join(INDEX_DIR);
+    // exports made without --search-index are indexed on the first search
+    if !index_dir.exists() {
+        write_index(export_dir)?;
+    }
+    let (_, fields) = schema();
+    let index = Index::open_in_dir(&index_dir).context("failed to open index")?;
+    let searcher = index.reader().context("failed to read index")?.searcher();
+    let query = QueryParser::for_index(&index, vec![fields.title, fields.body])
+        .parse_query(query)
+        .map_err(|x| anyhow!("invalid query: {}", x))?;
+    let mut snippets = SnippetGenerator::create(&searcher, &*query, fields.body)?;
+    snippets.set_max_num_chars(120);
+
+    for (_, address) in searcher.search(&query, &TopDocs::with_limit(limit))? {
+        let document = searcher.doc::<TantivyDocument>(address)?;
+        println!(
+            "{} {} {:?} {}",
+            text(&document, fields.date),
+            text(&document, fields.kind),
+            text(&document, fields.title),
+            text(&document, fields.path)
+        );
+        let snippet = snippets.snippet_from_doc(&document);
+        if !snippet.is_empty() {
+            println!("    {}", snippet.fragment().replace('\n', " "));
+        }
+    }
+    Ok(())
+}
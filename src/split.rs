@@ -0,0 +1,260 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::File,
+    io,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use clap::ValueEnum;
+use log::info;
+use serde_json::{json, Value};
+use zip::{write::SimpleFileOptions, ZipWriter};
+
+use crate::{
+    compress::{self, read_json},
+    manifest::ManifestEntry,
+    sanitize::sanitize_filename,
+    ValueHelper,
+};
+
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq)]
+pub enum SplitBy {
+    // one archive per course with its members and course updates and events
+    Course,
+}
+
+enum Archive {
+    Dir(PathBuf),
+    Zip(Box<ZipWriter<File>>),
+}
+
+impl Archive {
+    fn create(path: PathBuf, zip: bool) -> anyhow::Result<Self> {
+        // an earlier split of the same export is replaced, not merged into
+        if path.is_dir() {
+            std::fs::remove_dir_all(&path)
+                .with_context(|| format!("failed to remove {:?}", path))?;
+        }
+        if !zip {
+            return Ok(Self::Dir(path));
+        }
+        let mut zip_path = path.into_os_string();
+        zip_path.push(".zip");
+        let file =
+            File::create(&zip_path).with_context(|| format!("failed to create {:?}", zip_path))?;
+        Ok(Self::Zip(Box::new(ZipWriter::new(file))))
+    }
+
+    fn add(&mut self, path: &Path, contents: impl io::Read) -> anyhow::Result<()> {
+        let mut contents = contents;
+        match self {
+            Self::Dir(dir) => {
+                let target = dir.join(path);
+                if let Some(parent) = target.parent() {
+                    std::fs::create_dir_all(parent)
+                        .with_context(|| format!("failed to create {:?}", parent))?;
+                }
+                io::copy(
+                    &mut contents,
+                    &mut File::create(&target)
+                        .with_context(|| format!("failed to create {:?}", target))?,
+                )?;
+            }
+            Self::Zip(zip) => {
+                let name = path
+                    .components()
+                    .map(|x| x.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/");
+                zip.start_file(name, SimpleFileOptions::default())?;
+                io::copy(&mut contents, zip)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn add_json(&mut self, path: &Path, value: &impl serde::Serialize) -> anyhow::Result<()> {
+        self.add(path, serde_json::to_vec_pretty(value)?.as_slice())
+    }
+
+    fn finish(self) -> anyhow::Result<()> {
+        if let Self::Zip(zip) = self {
+            zip.finish().context("failed to finish zip")?;
+        }
+        Ok(())
+    }
+}
+
+// relative to the export, so they can be placed in the archive as is
+fn collect_files(export_dir: &Path, dir: &Path, files: &mut BTreeSet<PathBuf>) -> io::Result<()> {
+    let Ok(entries) = std::fs::read_dir(export_dir.join(dir)) else {
+        return Ok(());
+    };
+    for entry in entries {
+        let entry = entry?;
+        let path = dir.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            collect_files(export_dir, &path, files)?;
+        } else {
+            files.insert(path);
+        }
+    }
+    Ok(())
+}
+
+// the updates posted in the course and their attachments, markdown and pdfs
+fn course_updates(
+    export_dir: &Path,
+    course_id: &str,
+    files: &mut BTreeSet<PathBuf>,
+) -> io::Result<()> {
+    let mut all_updates = BTreeSet::new();
+    collect_files(export_dir, Path::new("updates"), &mut all_updates)?;
+    let prefix = format!("realm_updates_sections_{course_id}_");
+    let pages = all_updates
+        .iter()
+        .filter(|x| {
+            x.file_name()
+                .is_some_and(|x| x.to_string_lossy().starts_with(&prefix))
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+    let update_ids = pages
+        .iter()
+        .filter_map(|x| read_json(&export_dir.join(x)))
+        .flat_map(|x| x.get_array("update").unwrap_or_default())
+        .filter_map(|x| x.get_int("id"))
+        .collect::<Vec<_>>();
+    for path in all_updates {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let is_update_file = update_ids.iter().any(|id| {
+            name.strip_prefix(&format!("update_{id}"))
+                .is_some_and(|x| x.starts_with(['_', '.']))
+        });
+        if is_update_file {
+            files.insert(path);
+        }
+    }
+    files.extend(pages);
+    Ok(())
+}
+
+fn split_course(
+    export_dir: &Path,
+    split_dir: &Path,
+    zip: bool,
+    course: &Value,
+    people: &[Value],
+    index: &BTreeMap<String, Value>,
+    manifest: &[ManifestEntry],
+) -> anyhow::Result<()> {
+    let Some(course_id) = course.get_string_lossy("id") else {
+        return Ok(());
+    };
+    let course_dir = Path::new("courses").join(&course_id);
+    if !export_dir.join(&course_dir).is_dir() {
+        return Ok(());
+    }
+
+    let mut files = BTreeSet::new();
+    collect_files(export_dir, &course_dir, &mut files)?;
+    collect_files(
+        export_dir,
+        &Path::new("events").join(format!("sections_{course_id}")),
+        &mut files,
+    )?;
+    course_updates(export_dir, &course_id, &mut files)?;
+
+    // the members and everyone else who shows up in the course
+    let members = index
+        .iter()
+        .filter(|(_, x)| {
+            x.get_array("courses")
+                .unwrap_or_default()
+                .iter()
+                .any(|x| x.as_str() == Some(&course_id))
+        })
+        .collect::<BTreeMap<_, _>>();
+    for uid in members.keys() {
+        collect_files(export_dir, &Path::new("users").join(uid), &mut files)?;
+    }
+    // the index of every user, whether or not it was compressed
+    files.retain(|x| compress::uncompressed_path(x) != Path::new("users/index.json"));
+
+    let name = sanitize_filename(&format!(
+        "{course_id} {}",
+        course.get_string("course_title").unwrap_or_default()
+    ));
+    info!(
+        "writing {} files of course {} to {}",
+        files.len(),
+        course_id,
+        name
+    );
+    let mut archive = Archive::create(split_dir.join(&name), zip)?;
+    for path in &files {
+        let source = export_dir.join(path);
+        archive.add(
+            path,
+            File::open(&source).with_context(|| format!("failed to read {:?}", source))?,
+        )?;
+    }
+    archive.add_json(
+        &Path::new("courses").join("info.json"),
+        &json!({ "section": [course] }),
+    )?;
+    archive.add_json(&Path::new("users").join("index.json"), &members)?;
+    archive.add_json(
+        Path::new("people.json"),
+        &people
+            .iter()
+            .filter(|x| {
+                x.get_string_lossy("uid")
+                    .is_some_and(|x| members.contains_key(&x))
+            })
+            .collect::<Vec<_>>(),
+    )?;
+    archive.add_json(
+        Path::new("manifest.json"),
+        &manifest
+            .iter()
+            .filter(|x| files.contains(&x.path))
+            .collect::<Vec<_>>(),
+    )?;
+    archive.finish()
+}
+
+fn split_courses(export_dir: &Path, zip: bool) -> anyhow::Result<()> {
+    let courses = read_json(&export_dir.join("courses").join("info.json"))
+        .and_then(|x| x.get_array("section"))
+        .unwrap_or_default();
+    let people = read_json(&export_dir.join("people.json"))
+        .and_then(|x| x.as_array().cloned())
+        .unwrap_or_default();
+    let index = read_json(&export_dir.join("users").join("index.json"))
+        .and_then(|x| serde_json::from_value::<BTreeMap<String, Value>>(x).ok())
+        .unwrap_or_default();
+    let manifest = std::fs::read_to_string(export_dir.join("manifest.json"))
+        .ok()
+        .and_then(|x| serde_json::from_str::<Vec<ManifestEntry>>(&x).ok())
+        .unwrap_or_default();
+
+    let split_dir = export_dir.join("split");
+    std::fs::create_dir_all(&split_dir).context("failed to create split dir")?;
+    for course in &courses {
+        split_course(
+            export_dir, &split_dir, zip, course, &people, &index, &manifest,
+        )?;
+    }
+    Ok(())
+}
+
+// copies every course of a finished export, with what it references, into its own directory or zip
+pub async fn split(export_dir: PathBuf, split_by: SplitBy, zip: bool) -> anyhow::Result<()> {
+    match split_by {
+        SplitBy::Course => {
+            tokio::task::spawn_blocking(move || split_courses(&export_dir, zip)).await?
+        }
+    }
+}
@@ -0,0 +1,529 @@
+use std::{
+    collections::HashMap,
+    io,
+    path::{Component, Path, PathBuf},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{ready, Context as TaskContext, Poll},
+    time::SystemTime,
+};
+
+use anyhow::{anyhow, Context};
+use async_trait::async_trait;
+use futures::{future::BoxFuture, FutureExt};
+use log::warn;
+use reqwest::{header::HeaderValue, Method, Request, Response, Url};
+use reqwest_middleware::ClientWithMiddleware;
+use sha2::{Digest, Sha256};
+use tokio::{fs::File, io::AsyncWrite};
+
+use crate::error::ExportError;
+
+pub type StorageWriter = Pin<Box<dyn AsyncWrite + Send>>;
+
+// everything the export writes goes through a storage, so it can be sent somewhere other than the disk
+#[async_trait]
+pub trait Storage: Send + Sync {
+    async fn create_dir_all(&self, path: &Path) -> anyhow::Result<()>;
+    async fn write(&self, path: &Path, contents: Vec<u8>) -> anyhow::Result<()>;
+    // the file is complete once the writer is shut down
+    async fn create(&self, path: &Path) -> anyhow::Result<StorageWriter>;
+    // total size of everything stored below the path
+    fn size(&self, path: &Path) -> u64;
+}
+
+pub struct LocalStorage;
+
+fn dir_size(path: &Path) -> u64 {
+    let Ok(entries) = std::fs::read_dir(path) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .map(|x| match x.metadata() {
+            Ok(metadata) if metadata.is_dir() => dir_size(&x.path()),
+            Ok(metadata) => metadata.len(),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+// files are written next to their final path and renamed once complete, an interrupted export
+// never leaves a truncated file behind that looks like a good one
+fn temp_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tmp");
+    path.with_file_name(name)
+}
+
+struct LocalWriter {
+    file: File,
+    temp_path: PathBuf,
+    path: PathBuf,
+}
+
+impl AsyncWrite for LocalWriter {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().file).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().file).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(Pin::new(&mut this.file).poll_shutdown(cx))?;
+        Poll::Ready(std::fs::rename(&this.temp_path, &this.path))
+    }
+}
+
+#[async_trait]
+impl Storage for LocalStorage {
+    async fn create_dir_all(&self, path: &Path) -> anyhow::Result<()> {
+        Ok(tokio::fs::create_dir_all(path)
+            .await
+            .map_err(ExportError::filesystem("create directory", path))?)
+    }
+
+    async fn write(&self, path: &Path, contents: Vec<u8>) -> anyhow::Result<()> {
+        let temp_path = temp_path(path);
+        tokio::fs::write(&temp_path, contents)
+            .await
+            .map_err(ExportError::filesystem("write", path))?;
+        Ok(tokio::fs::rename(&temp_path, path)
+            .await
+            .map_err(ExportError::filesystem("write", path))?)
+    }
+
+    async fn create(&self, path: &Path) -> anyhow::Result<StorageWriter> {
+        let temp_path = temp_path(path);
+        Ok(Box::pin(LocalWriter {
+            file: File::create(&temp_path)
+                .await
+                .map_err(ExportError::filesystem("create", path))?,
+            temp_path,
+            path: path.to_path_buf(),
+        }))
+    }
+
+    fn size(&self, path: &Path) -> u64 {
+        dir_size(path)
+    }
+}
+
+// any S3-compatible object storage, the export dir becomes a key prefix
+#[derive(Clone)]
+pub struct S3Storage {
+    client: Arc<ClientWithMiddleware>,
+    endpoint: Url,
+    region: String,
+    bucket: String,
+    prefix: String,
+    access_key: String,
+    secret_key: String,
+    // object sizes by key, objects can't be listed cheaply after the fact
+    sizes: Arc<Mutex<HashMap<String, u64>>>,
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut key = match key.len() > 64 {
+        true => Sha256::digest(key).to_vec(),
+        false => key.to_vec(),
+    };
+    key.resize(64, 0);
+    let inner = Sha256::new()
+        .chain_update(key.iter().map(|x| x ^ 0x36).collect::<Vec<_>>())
+        .chain_update(data)
+        .finalize();
+    Sha256::new()
+        .chain_update(key.iter().map(|x| x ^ 0x5c).collect::<Vec<_>>())
+        .chain_update(inner)
+        .finalize()
+        .to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|x| format!("{x:02x}")).collect()
+}
+
+fn uri_encode(key: &str) -> String {
+    key.bytes()
+        .map(|x| match x {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                (x as char).to_string()
+            }
+            _ => format!("%{x:02X}"),
+        })
+        .collect()
+}
+
+impl S3Storage {
+    // s3://bucket/prefix, credentials and endpoint come from the usual AWS_* variables
+    pub fn new(client: Arc<ClientWithMiddleware>, dest: &str) -> anyhow::Result<Self> {
+        let location = dest
+            .strip_prefix("s3://")
+            .context("only s3:// destinations are supported")?;
+        let (bucket, prefix) = location.split_once('/').unwrap_or((location, ""));
+        if bucket.is_empty() {
+            return Err(anyhow!("no bucket in {:?}", dest));
+        }
+        let region = std::env::var("AWS_REGION").unwrap_or("us-east-1".to_string());
+        let endpoint = std::env::var("AWS_ENDPOINT_URL")
+            .unwrap_or(format!("https://s3.{region}.amazonaws.com"));
+        Ok(Self {
+            client,
+            endpoint: Url::parse(&endpoint).context("failed to parse AWS_ENDPOINT_URL")?,
+            region,
+            bucket: bucket.to_string(),
+            prefix: prefix.trim_matches('/').to_string(),
+            access_key: std::env::var("AWS_ACCESS_KEY_ID").context("AWS_ACCESS_KEY_ID not set")?,
+            secret_key: std::env::var("AWS_SECRET_ACCESS_KEY")
+                .context("AWS_SECRET_ACCESS_KEY not set")?,
+            sizes: Arc::default(),
+        })
+    }
+
+    fn key(&self, path: &Path) -> String {
+        self.prefix
+            .split('/')
+            .filter(|x| !x.is_empty())
+            .map(|x| x.to_string())
+            .chain(path.components().filter_map(|x| match x {
+                Component::Normal(x) => Some(x.to_string_lossy().into_owned()),
+                _ => None,
+            }))
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    // a signed request for the object, the query is given in its canonical order
+    async fn send(
+        &self,
+        method: Method,
+        key: &str,
+        query: &[(&str, &str)],
+        contents: Vec<u8>,
+    ) -> anyhow::Result<Response> {
+        let amz_date = humantime::format_rfc3339_seconds(SystemTime::now())
+            .to_string()
+            .replace(['-', ':'], "");
+        let date = &amz_date[..8];
+        let payload_hash = hex(&Sha256::digest(&contents));
+
+        let path = format!(
+            "{}/{}/{}",
+            self.endpoint.path().trim_end_matches('/'),
+            self.bucket,
+            uri_encode(key)
+        );
+        let query = query
+            .iter()
+            .map(|(name, value)| format!("{name}={}", uri_encode(value).replace('/', "%2F")))
+            .collect::<Vec<_>>()
+            .join("&");
+        let mut url = self.endpoint.clone();
+        url.set_path(&path);
+        url.set_query(Some(&query).filter(|x| !x.is_empty()).map(|x| x.as_str()));
+        let host = match url.port() {
+            Some(port) => format!("{}:{port}", url.host_str().unwrap_or_default()),
+            None => url.host_str().unwrap_or_default().to_string(),
+        };
+
+        let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+        let canonical_request = format!(
+            "{method}\n{path}\n{query}\nhost:{host}\nx-amz-content-sha256:{payload_hash}\nx-amz-date:{amz_date}\n\n{signed_headers}\n{payload_hash}"
+        );
+        let scope = format!("{date}/{}/s3/aws4_request", self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}",
+            hex(&Sha256::digest(canonical_request))
+        );
+        let signing_key = [self.region.as_str(), "s3", "aws4_request"].iter().fold(
+            hmac(
+                format!("AWS4{}", self.secret_key).as_bytes(),
+                date.as_bytes(),
+            ),
+            |key, x| hmac(&key, x.as_bytes()),
+        );
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={}",
+            self.access_key,
+            hex(&hmac(&signing_key, string_to_sign.as_bytes()))
+        );
+
+        let mut request = Request::new(method, url);
+        let headers = request.headers_mut();
+        headers.insert("Authorization", HeaderValue::from_str(&authorization)?);
+        headers.insert(
+            "x-amz-content-sha256",
+            HeaderValue::from_str(&payload_hash)?,
+        );
+        headers.insert("x-amz-date", HeaderValue::from_str(&amz_date)?);
+        *request.body_mut() = Some(contents.into());
+        self.client
+            .execute(request)
+            .await
+            .with_context(|| format!("failed to upload {key:?}"))?
+            .error_for_status()
+            .with_context(|| format!("failed to upload {key:?}"))
+    }
+
+    async fn put(&self, key: String, contents: Vec<u8>) -> anyhow::Result<()> {
+        let bytes = contents.len() as u64;
+        self.send(Method::PUT, &key, &[], contents).await?;
+        self.sizes.lock().unwrap().insert(key, bytes);
+        Ok(())
+    }
+
+    async fn create_multipart(&self, key: &str) -> anyhow::Result<String> {
+        let response = self
+            .send(Method::POST, key, &[("uploads", "")], Vec::new())
+            .await?
+            .text()
+            .await
+            .with_context(|| format!("failed to upload {key:?}"))?;
+        xml_value(&response, "UploadId")
+            .map(|x| x.to_string())
+            .with_context(|| format!("no upload id for {key:?}"))
+    }
+
+    async fn upload_part(
+        &self,
+        key: &str,
+        upload_id: &str,
+        part_number: usize,
+        contents: Vec<u8>,
+    ) -> anyhow::Result<String> {
+        let response = self
+            .send(
+                Method::PUT,
+                key,
+                &[
+                    ("partNumber", &part_number.to_string()),
+                    ("uploadId", upload_id),
+                ],
+                contents,
+            )
+            .await?;
+        response
+            .headers()
+            .get("ETag")
+            .and_then(|x| x.to_str().ok())
+            .map(|x| x.to_string())
+            .with_context(|| format!("no etag for part {part_number} of {key:?}"))
+    }
+
+    async fn complete_multipart(
+        &self,
+        key: String,
+        upload_id: &str,
+        etags: &[String],
+        bytes: u64,
+    ) -> anyhow::Result<()> {
+        let parts = etags
+            .iter()
+            .enumerate()
+            .map(|(idx, etag)| {
+                format!(
+                    "<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>",
+                    idx + 1,
+                    etag.replace('"', "&quot;")
+                )
+            })
+            .collect::<String>();
+        let body = format!("<CompleteMultipartUpload>{parts}</CompleteMultipartUpload>");
+        let response = self
+            .send(Method::POST, &key, &[("uploadId", upload_id)], body.into())
+            .await?
+            .text()
+            .await
+            .with_context(|| format!("failed to upload {key:?}"))?;
+        // a failed completion can still be answered with 200
+        if let Some(error) = xml_value(&response, "Message") {
+            return Err(anyhow!("failed to upload {key:?}: {error}"));
+        }
+        self.sizes.lock().unwrap().insert(key, bytes);
+        Ok(())
+    }
+
+    // the parts already stored are deleted, an unfinished upload would be kept and billed otherwise
+    async fn abort_multipart(&self, key: &str, upload_id: &str) {
+        if let Err(err) = self
+            .send(Method::DELETE, key, &[("uploadId", upload_id)], Vec::new())
+            .await
+        {
+            warn!("failed to abort the upload of {key:?}: {err:#}");
+        }
+    }
+}
+
+fn xml_value<'a>(xml: &'a str, tag: &str) -> Option<&'a str> {
+    let start = xml.find(&format!("<{tag}>"))? + tag.len() + 2;
+    let end = xml[start..].find(&format!("</{tag}>"))?;
+    Some(&xml[start..start + end])
+}
+
+// parts are at least 5 MiB, except the last one
+const PART_SIZE: usize = 16 * 1024 * 1024;
+
+// objects can't be appended to, so the file is buffered and uploaded a part at a time once the
+// buffer is full. files smaller than a part are uploaded on shutdown with a single request
+struct S3Writer {
+    storage: S3Storage,
+    key: String,
+    buffer: Vec<u8>,
+    bytes: u64,
+    upload_id: Option<String>,
+    etags: Vec<String>,
+    // the part being uploaded, with the upload id it was created with
+    part: Option<BoxFuture<'static, anyhow::Result<(String, String)>>>,
+    upload: Option<BoxFuture<'static, anyhow::Result<()>>>,
+}
+
+impl S3Writer {
+    fn start_part(&mut self) {
+        let storage = self.storage.clone();
+        let key = self.key.clone();
+        let upload_id = self.upload_id.clone();
+        let part_number = self.etags.len() + 1;
+        let contents = std::mem::take(&mut self.buffer);
+        self.part = Some(Box::pin(async move {
+            let upload_id = match upload_id {
+                Some(upload_id) => upload_id,
+                None => storage.create_multipart(&key).await?,
+            };
+            match storage
+                .upload_part(&key, &upload_id, part_number, contents)
+                .await
+            {
+                Ok(etag) => Ok((upload_id, etag)),
+                Err(err) => {
+                    storage.abort_multipart(&key, &upload_id).await;
+                    Err(err)
+                }
+            }
+        }));
+    }
+
+    fn poll_part(&mut self, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        let Some(part) = &mut self.part else {
+            return Poll::Ready(Ok(()));
+        };
+        let result = ready!(part.poll_unpin(cx));
+        self.part = None;
+        let (upload_id, etag) = result.map_err(|err| io::Error::other(format!("{err:#}")))?;
+        self.upload_id = Some(upload_id);
+        self.etags.push(etag);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for S3Writer {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        ready!(this.poll_part(cx))?;
+        if this.buffer.len() >= PART_SIZE {
+            this.start_part();
+            ready!(this.poll_part(cx))?;
+        }
+        this.buffer.extend_from_slice(buf);
+        this.bytes += buf.len() as u64;
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_part(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_part(cx))?;
+        let upload = this.upload.get_or_insert_with(|| {
+            let storage = this.storage.clone();
+            let key = std::mem::take(&mut this.key);
+            let buffer = std::mem::take(&mut this.buffer);
+            let Some(upload_id) = this.upload_id.clone() else {
+                return Box::pin(async move { storage.put(key, buffer).await });
+            };
+            let mut etags = std::mem::take(&mut this.etags);
+            let bytes = this.bytes;
+            Box::pin(async move {
+                let result = async {
+                    if !buffer.is_empty() {
+                        let part_number = etags.len() + 1;
+                        etags.push(
+                            storage
+                                .upload_part(&key, &upload_id, part_number, buffer)
+                                .await?,
+                        );
+                    }
+                    storage
+                        .complete_multipart(key.clone(), &upload_id, &etags, bytes)
+                        .await
+                }
+                .await;
+                if result.is_err() {
+                    storage.abort_multipart(&key, &upload_id).await;
+                }
+                result
+            })
+        });
+        upload
+            .poll_unpin(cx)
+            .map_err(|err| io::Error::other(format!("{err:#}")))
+    }
+}
+
+#[async_trait]
+impl Storage for S3Storage {
+    async fn create_dir_all(&self, _: &Path) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    async fn write(&self, path: &Path, contents: Vec<u8>) -> anyhow::Result<()> {
+        self.put(self.key(path), contents).await
+    }
+
+    async fn create(&self, path: &Path) -> anyhow::Result<StorageWriter> {
+        Ok(Box::pin(S3Writer {
+            storage: self.clone(),
+            key: self.key(path),
+            buffer: Vec::new(),
+            bytes: 0,
+            upload_id: None,
+            etags: Vec::new(),
+            part: None,
+            upload: None,
+        }))
+    }
+
+    fn size(&self, path: &Path) -> u64 {
+        let prefix = format!("{}/", self.key(path));
+        self.sizes
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(key, _)| key.starts_with(&prefix))
+            .map(|(_, size)| size)
+            .sum()
+    }
+}
+
+pub fn open_storage(
+    client: Arc<ClientWithMiddleware>,
+    dest: Option<&str>,
+) -> anyhow::Result<Arc<dyn Storage>> {
+    Ok(match dest {
+        Some(dest) => Arc::new(S3Storage::new(client, dest)?),
+        None => Arc::new(LocalStorage),
+    })
+}
@@ -0,0 +1,308 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    future::Future,
+    io::{self, Write},
+    path::Path,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context as TaskContext, Poll},
+    time::{Instant, SystemTime},
+};
+
+use async_trait::async_trait;
+use clap::ValueEnum;
+use log::{
+    kv::{Error, Key, Value as KvValue, VisitSource},
+    Level,
+};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+use tokio::io::AsyncWrite;
+
+use crate::{
+    progress,
+    storage::{Storage, StorageWriter},
+};
+
+tokio::task_local! {
+    // the phase of work that runs alongside the others, e.g. the exports of other users
+    static CONCURRENT_PHASE: usize;
+}
+
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+struct JsonVisitor<'a>(&'a mut Map<String, Value>);
+
+impl<'kvs> VisitSource<'kvs> for JsonVisitor<'_> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: KvValue<'kvs>) -> Result<(), Error> {
+        self.0.insert(
+            key.to_string(),
+            serde_json::to_value(value).unwrap_or(Value::Null),
+        );
+        Ok(())
+    }
+}
+
+pub fn log_builder(format: LogFormat) -> env_logger::Builder {
+    let mut builder = env_logger::Builder::new();
+    builder
+        .filter_level(log::LevelFilter::Info)
+        .parse_default_env();
+    if format == LogFormat::Json {
+        builder.format(|buf, record| {
+            let mut event = Map::new();
+            event.insert(
+                "timestamp".to_string(),
+                humantime::format_rfc3339_millis(SystemTime::now())
+                    .to_string()
+                    .into(),
+            );
+            event.insert("level".to_string(), record.level().as_str().into());
+            event.insert("target".to_string(), record.target().into());
+            event.insert("message".to_string(), record.args().to_string().into());
+            let _ = record.key_values().visit(&mut JsonVisitor(&mut event));
+            writeln!(buf, "{}", Value::Object(event))
+        });
+    }
+    builder
+}
+
+pub fn init_logging(format: LogFormat) {
+    log_builder(format).init();
+}
+
+#[derive(Serialize, Default)]
+struct Phase {
+    name: String,
+    duration_secs: f64,
+    entities: BTreeMap<String, u64>,
+    bytes: u64,
+    // everything written to the storage by the phase, including the api responses
+    bytes_written: u64,
+    #[serde(skip)]
+    started: Option<Instant>,
+}
+
+impl Phase {
+    fn start(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            started: Some(Instant::now()),
+            ..Default::default()
+        }
+    }
+
+    fn finish(&mut self) {
+        if let Some(started) = self.started.take() {
+            self.duration_secs = started.elapsed().as_secs_f64();
+        }
+    }
+}
+
+#[derive(Default)]
+struct SummaryState {
+    phases: Vec<Phase>,
+    // the phase started last by the export itself
+    current: Option<usize>,
+    failures: Vec<String>,
+}
+
+impl SummaryState {
+    fn phase(&mut self) -> Option<&mut Phase> {
+        let index = CONCURRENT_PHASE.try_with(|x| *x).ok().or(self.current)?;
+        self.phases.get_mut(index)
+    }
+}
+
+pub struct Summary {
+    format: LogFormat,
+    storage: Arc<dyn Storage>,
+    start: Instant,
+    state: Mutex<SummaryState>,
+}
+
+impl Summary {
+    pub fn new(format: LogFormat, storage: Arc<dyn Storage>) -> Self {
+        Self {
+            format,
+            storage,
+            start: Instant::now(),
+            state: Mutex::new(SummaryState::default()),
+        }
+    }
+
+    // the storage for the export, what it writes is counted in the phase that wrote it
+    pub fn counting(self: &Arc<Self>, storage: Arc<dyn Storage>) -> Arc<dyn Storage> {
+        Arc::new(CountingStorage {
+            inner: storage,
+            summary: self.clone(),
+        })
+    }
+
+    fn written(&self, bytes: u64) {
+        if let Some(phase) = self.state.lock().unwrap().phase() {
+            phase.bytes_written += bytes;
+        }
+    }
+
+    pub fn phase(&self, name: &str) {
+        progress::phase(name);
+        let mut state = self.state.lock().unwrap();
+        if let Some(current) = state.current.and_then(|x| state.phases.get_mut(x)) {
+            current.finish();
+        }
+        state.phases.push(Phase::start(name));
+        state.current = Some(state.phases.len() - 1);
+    }
+
+    // work running alongside the phases started meanwhile is counted in a phase of its own
+    pub async fn concurrent_phase<F: Future>(&self, name: &str, work: F) -> F::Output {
+        let index = {
+            let mut state = self.state.lock().unwrap();
+            state.phases.push(Phase::start(name));
+            state.phases.len() - 1
+        };
+        let output = CONCURRENT_PHASE.scope(index, work).await;
+        self.state.lock().unwrap().phases[index].finish();
+        output
+    }
+
+    pub fn entity(
+        &self,
+        kind: &str,
+        id: impl Display,
+        path: &Path,
+        bytes: Option<u64>,
+        started: Instant,
+    ) {
+        let duration_ms = started.elapsed().as_millis() as u64;
+        progress::entity(kind, path);
+        {
+            let mut state = self.state.lock().unwrap();
+            if let Some(phase) = state.phase() {
+                *phase.entities.entry(kind.to_string()).or_default() += 1;
+                phase.bytes += bytes.unwrap_or_default();
+            }
+        }
+
+        // the events are only interesting to scripts, so they stay out of the text log
+        let level = match self.format {
+            LogFormat::Text => Level::Debug,
+            LogFormat::Json => Level::Info,
+        };
+        log::log!(
+            level,
+            entity = kind,
+            id:% = id,
+            path:% = path.display(),
+            bytes = bytes.unwrap_or_default(),
+            duration_ms = duration_ms;
+            "exported {}", kind
+        );
+    }
+
+    pub fn failure(&self, message: String) {
+        self.state.lock().unwrap().failures.push(message);
+    }
+
+    pub async fn write(
+        &self,
+        export_dir: &Path,
+        error: Option<&anyhow::Error>,
+    ) -> anyhow::Result<()> {
+        let summary = {
+            let mut state = self.state.lock().unwrap();
+            for phase in &mut state.phases {
+                phase.finish();
+            }
+            json!({
+                "success": error.is_none(),
+                "error": error.map(|x| format!("{:#}", x)),
+                "duration_secs": self.start.elapsed().as_secs_f64(),
+                "total_bytes": state.phases.iter().map(|x| x.bytes).sum::<u64>(),
+                "total_bytes_written": state.phases.iter().map(|x| x.bytes_written).sum::<u64>(),
+                "entities": state.phases.iter().flat_map(|x| x.entities.iter()).fold(
+                    BTreeMap::<&str, u64>::new(),
+                    |mut entities, (kind, count)| {
+                        *entities.entry(kind).or_default() += count;
+                        entities
+                    },
+                ),
+                "failures": state.failures,
+                "phases": state.phases,
+            })
+        };
+        self.storage
+            .write(
+                &export_dir.join("summary.json"),
+                serde_json::to_string_pretty(&summary)?.into(),
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+struct CountingStorage {
+    inner: Arc<dyn Storage>,
+    summary: Arc<Summary>,
+}
+
+#[async_trait]
+impl Storage for CountingStorage {
+    async fn create_dir_all(&self, path: &Path) -> anyhow::Result<()> {
+        self.inner.create_dir_all(path).await
+    }
+
+    async fn write(&self, path: &Path, contents: Vec<u8>) -> anyhow::Result<()> {
+        let bytes = contents.len() as u64;
+        self.inner.write(path, contents).await?;
+        self.summary.written(bytes);
+        Ok(())
+    }
+
+    async fn create(&self, path: &Path) -> anyhow::Result<StorageWriter> {
+        Ok(Box::pin(CountingWriter {
+            inner: self.inner.create(path).await?,
+            summary: self.summary.clone(),
+        }))
+    }
+
+    fn size(&self, path: &Path) -> u64 {
+        self.inner.size(path)
+    }
+}
+
+struct CountingWriter {
+    inner: StorageWriter,
+    summary: Arc<Summary>,
+}
+
+impl AsyncWrite for CountingWriter {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let written = this.inner.as_mut().poll_write(cx, buf);
+        if let Poll::Ready(Ok(bytes)) = written {
+            this.summary.written(bytes as u64);
+        }
+        written
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().inner.as_mut().poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().inner.as_mut().poll_shutdown(cx)
+    }
+}
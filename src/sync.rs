@@ -0,0 +1,126 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::Context;
+use log::{error, info};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt;
+
+use crate::{export, sanitize::Layout, Args};
+
+// rewritten by every export, they would always show up as changed
+const IGNORED_FILES: &[&str] = &["summary.json", "manifest.json", "changelog.jsonl"];
+
+fn hash_files(
+    dir: &Path,
+    export_dir: &Path,
+    hashes: &mut HashMap<PathBuf, String>,
+) -> io::Result<()> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Ok(());
+    };
+    for entry in entries {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            hash_files(&path, export_dir, hashes)?;
+            continue;
+        }
+        if IGNORED_FILES.iter().any(|x| entry.file_name() == *x) {
+            continue;
+        }
+        let mut hasher = Sha256::new();
+        io::copy(&mut File::open(&path)?, &mut hasher)?;
+        hashes.insert(
+            path.strip_prefix(export_dir).unwrap_or(&path).to_path_buf(),
+            format!("{:x}", hasher.finalize()),
+        );
+    }
+    Ok(())
+}
+
+async fn hash_export(export_dir: PathBuf) -> anyhow::Result<HashMap<PathBuf, String>> {
+    tokio::task::spawn_blocking(move || {
+        let mut hashes = HashMap::new();
+        hash_files(&export_dir, &export_dir, &mut hashes).context("failed to hash export")?;
+        Ok(hashes)
+    })
+    .await?
+}
+
+// exports into the same tree over and over, a failed export is logged and retried at the next interval
+pub async fn sync(
+    args: Args,
+    config: PathBuf,
+    every: Duration,
+    output: PathBuf,
+) -> anyhow::Result<()> {
+    // item directories have to keep their names for the tree to be updated in place
+    let args = Args {
+        command: None,
+        config: Some(config),
+        output: Some(output.clone()),
+        dest: None,
+        layout: Layout::Id,
+        ..args
+    };
+    tokio::fs::create_dir_all(&output)
+        .await
+        .context("failed to create export dir")?;
+
+    loop {
+        let started = Instant::now();
+        let started_at = SystemTime::now();
+        let before = hash_export(output.clone()).await?;
+        let result = export(args.clone(), true).await;
+        if let Err(err) = &result {
+            error!("sync failed: {:#}", err);
+        }
+        let after = hash_export(output.clone()).await?;
+
+        let mut added = after
+            .keys()
+            .filter(|x| !before.contains_key(*x))
+            .collect::<Vec<_>>();
+        added.sort();
+        let mut changed = after
+            .iter()
+            .filter(|(path, hash)| before.get(*path).is_some_and(|x| x != *hash))
+            .map(|(path, _)| path)
+            .collect::<Vec<_>>();
+        changed.sort();
+        info!(
+            "synced {} new and {} changed files",
+            added.len(),
+            changed.len()
+        );
+
+        let entry = json!({
+            "started": humantime::format_rfc3339_seconds(started_at).to_string(),
+            "duration": started.elapsed().as_secs(),
+            "error": result.err().map(|x| format!("{x:#}")),
+            "added": added,
+            "changed": changed,
+        });
+        let mut changelog = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(output.join("changelog.jsonl"))
+            .await
+            .context("failed to open changelog")?;
+        changelog
+            .write_all(format!("{entry}\n").as_bytes())
+            .await
+            .context("failed to write changelog")?;
+
+        let next = Duration::from_secs(every.saturating_sub(started.elapsed()).as_secs());
+        info!("next sync in {}", humantime::format_duration(next));
+        tokio::time::sleep(next).await;
+    }
+}
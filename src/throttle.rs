@@ -0,0 +1,155 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::{anyhow, Context};
+use http::Extensions;
+use log::warn;
+use reqwest::{Request, Response, StatusCode};
+use reqwest_middleware::{Middleware, Next};
+use tokio::sync::Mutex;
+
+// parses sizes like "5MB/s", "500KiB/s" or "1G" into bytes per second
+pub fn parse_bandwidth(bandwidth: &str) -> anyhow::Result<u64> {
+    let bandwidth = bandwidth.trim();
+    let bandwidth = bandwidth.strip_suffix("/s").unwrap_or(bandwidth);
+    let split = bandwidth
+        .find(|x: char| !x.is_ascii_digit() && x != '.')
+        .unwrap_or(bandwidth.len());
+    let (amount, unit) = bandwidth.split_at(split);
+    let amount: f64 = amount.parse().context("invalid bandwidth amount")?;
+
+    let multiplier = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1.0,
+        "k" | "kb" => 1e3,
+        "m" | "mb" => 1e6,
+        "g" | "gb" => 1e9,
+        "ki" | "kib" => 1024.0,
+        "mi" | "mib" => 1024.0 * 1024.0,
+        "gi" | "gib" => 1024.0 * 1024.0 * 1024.0,
+        x => return Err(anyhow!("unknown bandwidth unit {:?}", x)),
+    };
+
+    let bytes = (amount * multiplier) as u64;
+    if bytes == 0 {
+        return Err(anyhow!("bandwidth must be greater than zero"));
+    }
+    Ok(bytes)
+}
+
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+// token bucket holding up to one second worth of bytes, clones share the bucket
+#[derive(Clone)]
+pub struct RateLimiter {
+    bytes_per_sec: f64,
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(bytes_per_sec: u64) -> Self {
+        Self {
+            bytes_per_sec: bytes_per_sec as f64,
+            bucket: Arc::new(Mutex::new(Bucket {
+                tokens: bytes_per_sec as f64,
+                last_refill: Instant::now(),
+            })),
+        }
+    }
+
+    pub async fn acquire(&self, bytes: usize) {
+        // the lock is held while sleeping so waiting downloads queue up behind each other
+        let mut bucket = self.bucket.lock().await;
+
+        let now = Instant::now();
+        bucket.tokens = (bucket.tokens
+            + now.duration_since(bucket.last_refill).as_secs_f64() * self.bytes_per_sec)
+            .min(self.bytes_per_sec);
+        bucket.last_refill = now;
+
+        bucket.tokens -= bytes as f64;
+        if bucket.tokens < 0.0 {
+            tokio::time::sleep(Duration::from_secs_f64(-bucket.tokens / self.bytes_per_sec)).await;
+            bucket.tokens = 0.0;
+            bucket.last_refill = Instant::now();
+        }
+    }
+}
+
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+#[derive(Default)]
+struct Backoff {
+    until: Option<Instant>,
+    // rate limit windows in a row, for the exponential backoff without a Retry-After
+    consecutive: u32,
+}
+
+// seconds or an http date
+fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get("Retry-After")?.to_str().ok()?.trim();
+    match value.parse::<u64>() {
+        Ok(seconds) => Some(Duration::from_secs(seconds)),
+        Err(_) => Some(
+            httpdate::parse_http_date(value)
+                .ok()?
+                .duration_since(SystemTime::now())
+                .unwrap_or_default(),
+        ),
+    }
+}
+
+// a 429 holds back every request of the export until the rate limit window resets, not just the
+// one that hit it, so the retries don't keep the account banned. sits below the retry middleware
+// so every attempt waits too
+#[derive(Clone, Default)]
+pub struct BackoffMiddleware {
+    backoff: Arc<Mutex<Backoff>>,
+}
+
+#[async_trait::async_trait]
+impl Middleware for BackoffMiddleware {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        let until = self.backoff.lock().await.until;
+        if let Some(until) = until {
+            tokio::time::sleep_until(until.into()).await;
+        }
+
+        let sent = Instant::now();
+        let response = next.run(req, extensions).await?;
+        let mut backoff = self.backoff.lock().await;
+        // requests already in flight when a pause started answer for the window that was paused,
+        // only the ones sent after it ended tell whether the rate limit was lifted
+        let after_pause = backoff.until.is_none_or(|x| sent >= x);
+        if response.status() != StatusCode::TOO_MANY_REQUESTS {
+            if after_pause {
+                backoff.consecutive = 0;
+            }
+            return Ok(response);
+        }
+        let delay = retry_after(&response)
+            .unwrap_or(Duration::from_secs(1 << backoff.consecutive.min(16)).min(MAX_BACKOFF));
+        if after_pause {
+            backoff.consecutive += 1;
+        }
+        let until = Instant::now() + delay;
+        if backoff.until.is_none_or(|x| x < until) {
+            warn!(
+                "rate limited by {:?}, pausing all requests for {}",
+                response.url().host_str().unwrap_or_default(),
+                humantime::format_duration(Duration::from_secs(delay.as_secs()))
+            );
+            backoff.until = Some(until);
+        }
+        Ok(response)
+    }
+}
@@ -0,0 +1,163 @@
+use std::{
+    path::{Component, Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::Context;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{storage::Storage, ValueHelper};
+
+#[derive(Serialize)]
+struct TimelineEntry {
+    // as schoology gives it, in the timezone of the account
+    date: String,
+    all_day: bool,
+    kind: String,
+    id: i64,
+    title: String,
+    course: Option<String>,
+    path: PathBuf,
+}
+
+// due dates and events of the whole export, in the order they happen
+#[derive(Default)]
+pub struct Timeline(Mutex<Vec<TimelineEntry>>);
+
+// the section an item or event belongs to, from where it is saved
+fn course_of(path: &Path) -> Option<String> {
+    let mut components = path.components().filter_map(|x| match x {
+        Component::Normal(x) => Some(x.to_string_lossy()),
+        _ => None,
+    });
+    match components.next()?.as_ref() {
+        "courses" => Some(components.next()?.to_string()),
+        "events" => components
+            .next()?
+            .strip_prefix("sections_")
+            .map(|x| x.to_string()),
+        _ => None,
+    }
+}
+
+fn escape_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+        .replace('\r', "")
+}
+
+// lines longer than 75 bytes are continued on the next line after a space
+fn fold_line(line: &str) -> String {
+    let mut folded = String::new();
+    let mut length = 0;
+    for x in line.chars() {
+        if length + x.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            length = 1;
+        }
+        folded.push(x);
+        length += x.len_utf8();
+    }
+    folded.push_str("\r\n");
+    folded
+}
+
+// "2024-01-10 23:59:00" to a floating local time, schoology doesn't say which timezone
+fn ics_date(date: &str, all_day: bool) -> Option<String> {
+    let digits = date
+        .chars()
+        .filter(|x| x.is_ascii_digit())
+        .collect::<String>();
+    match (all_day, digits.len()) {
+        (true, 8..) => Some(format!(";VALUE=DATE:{}", &digits[..8])),
+        (false, 14..) => Some(format!(":{}T{}", &digits[..8], &digits[8..14])),
+        (false, 12..) => Some(format!(":{}T{}00", &digits[..8], &digits[8..12])),
+        (false, 8..) => Some(format!(";VALUE=DATE:{}", &digits[..8])),
+        _ => None,
+    }
+}
+
+fn calendar(entries: &[TimelineEntry]) -> String {
+    let stamp = humantime::format_rfc3339_seconds(std::time::SystemTime::now())
+        .to_string()
+        .replace(['-', ':'], "");
+    let mut ics = String::from(
+        "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nPRODID:-//export-schoology//timeline//EN\r\n",
+    );
+    for entry in entries {
+        let Some(start) = ics_date(&entry.date, entry.all_day) else {
+            continue;
+        };
+        ics.push_str("BEGIN:VEVENT\r\n");
+        ics.push_str(&format!(
+            "UID:{}-{}@export-schoology\r\n",
+            entry.kind, entry.id
+        ));
+        ics.push_str(&format!("DTSTAMP:{stamp}\r\n"));
+        ics.push_str(&format!("DTSTART{start}\r\n"));
+        ics.push_str(&fold_line(&format!(
+            "SUMMARY:{}",
+            escape_text(&match entry.kind.as_str() {
+                "event" => entry.title.clone(),
+                _ => format!("{} due", entry.title),
+            })
+        )));
+        ics.push_str(&fold_line(&format!(
+            "DESCRIPTION:{}",
+            escape_text(&entry.path.to_string_lossy())
+        )));
+        ics.push_str("END:VEVENT\r\n");
+    }
+    ics.push_str("END:VCALENDAR\r\n");
+    ics
+}
+
+impl Timeline {
+    // items without a date are left out
+    pub fn record(&self, kind: &str, id: i64, info: &Value, path: &Path) {
+        let (date, all_day) = match kind {
+            "event" => (
+                info.get_string("start"),
+                info.get_string_lossy("all_day").as_deref() == Some("1"),
+            ),
+            _ => (info.get_string("due"), false),
+        };
+        let Some(date) = date.filter(|x| !x.trim().is_empty()) else {
+            return;
+        };
+        self.0.lock().unwrap().push(TimelineEntry {
+            date,
+            all_day,
+            kind: kind.to_string(),
+            id,
+            title: info.get_string("title").unwrap_or_default(),
+            course: None,
+            path: path.to_path_buf(),
+        });
+    }
+
+    pub async fn write(&self, storage: &dyn Storage, export_dir: &Path) -> anyhow::Result<()> {
+        let mut entries = std::mem::take(&mut *self.0.lock().unwrap());
+        for entry in &mut entries {
+            if let Ok(relative) = entry.path.strip_prefix(export_dir) {
+                entry.path = relative.to_path_buf();
+            }
+            entry.course = course_of(&entry.path);
+        }
+        entries.sort_by(|a, b| a.date.cmp(&b.date).then(a.id.cmp(&b.id)));
+        storage
+            .write(
+                &export_dir.join("timeline.json"),
+                serde_json::to_string_pretty(&entries)?.into(),
+            )
+            .await
+            .context("failed to write timeline")?;
+        storage
+            .write(&export_dir.join("timeline.ics"), calendar(&entries).into())
+            .await
+            .context("failed to write timeline calendar")
+    }
+}
@@ -0,0 +1,229 @@
+use std::{
+    collections::VecDeque,
+    io::{stdout, Stdout},
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use log::{warn, Level, Log, Metadata, Record};
+use ratatui::{
+    backend::CrosstermBackend,
+    crossterm::{
+        cursor::{Hide, Show},
+        execute,
+        terminal::{EnterAlternateScreen, LeaveAlternateScreen},
+    },
+    layout::{Constraint, Layout},
+    style::{Color, Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, List, ListItem, Paragraph},
+    Frame, Terminal,
+};
+use tokio::{sync::oneshot, task::JoinHandle};
+
+use crate::{
+    inspect::format_bytes,
+    progress::{self, progress, Progress, Status},
+    summary::{log_builder, LogFormat},
+};
+
+// log lines go to the interface while it is shown and to stderr before and after
+static SHOWN: AtomicBool = AtomicBool::new(false);
+
+struct TuiLogger(env_logger::Logger);
+
+impl Log for TuiLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.0.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.0.matches(record) {
+            return;
+        }
+        match SHOWN.load(Ordering::Relaxed) {
+            true => progress::log(record.level() <= Level::Warn, record.args().to_string()),
+            false => self.0.log(record),
+        }
+    }
+
+    fn flush(&self) {
+        self.0.flush();
+    }
+}
+
+pub fn init_logging(format: LogFormat) {
+    let logger = log_builder(format).build();
+    log::set_max_level(logger.filter());
+    let _ = log::set_boxed_logger(Box::new(TuiLogger(logger)));
+}
+
+// download throughput over the last few seconds
+struct Throughput(VecDeque<(Instant, u64)>);
+
+impl Throughput {
+    fn sample(&mut self) -> f64 {
+        let now = Instant::now();
+        self.0.push_back((now, progress::downloaded()));
+        while self
+            .0
+            .front()
+            .is_some_and(|(at, _)| now.duration_since(*at) > Duration::from_secs(3))
+        {
+            self.0.pop_front();
+        }
+        match (self.0.front(), self.0.back()) {
+            (Some((first_at, first)), Some((last_at, last))) if last_at > first_at => {
+                (last - first) as f64 / last_at.duration_since(*first_at).as_secs_f64()
+            }
+            _ => 0.0,
+        }
+    }
+}
+
+fn status_span(status: Status) -> Span<'static> {
+    match status {
+        Status::Running => "● ".yellow(),
+        Status::Done => "✓ ".green(),
+        Status::Failed => "✗ ".red(),
+    }
+}
+
+fn tree(progress: &Progress) -> Vec<ListItem<'static>> {
+    let mut items = Vec::new();
+    for phase in &progress.phases {
+        items.push(ListItem::new(Line::from(vec![
+            status_span(phase.status),
+            Span::raw(format!("{} ({})", phase.name, phase.items)),
+        ])));
+        if phase.name != "courses" {
+            continue;
+        }
+        for course in &progress.courses {
+            let counts = course
+                .items
+                .iter()
+                .map(|(kind, count)| format!("{count} {kind}"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let mut line = vec![
+                Span::raw("  "),
+                status_span(course.status),
+                Span::raw(format!("{} {}", course.id, course.title)).bold(),
+                Span::raw(format!("  {counts}")),
+            ];
+            if let (Status::Running, Some(current)) = (course.status, &course.current) {
+                line.push(Span::raw(format!("  {current}")).dark_gray());
+            }
+            items.push(ListItem::new(Line::from(line)));
+        }
+    }
+    items
+}
+
+fn draw(frame: &mut Frame, started: Instant, rate: f64) {
+    let progress = progress();
+    let [header, body, errors, status] = Layout::vertical([
+        Constraint::Length(1),
+        Constraint::Min(3),
+        Constraint::Length(8),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+
+    frame.render_widget(
+        Paragraph::new(format!(
+            "export-schoology  {}  {} downloaded  {}/s",
+            humantime::format_duration(Duration::from_secs(started.elapsed().as_secs())),
+            format_bytes(progress::downloaded()),
+            format_bytes(rate as u64)
+        ))
+        .bold(),
+        header,
+    );
+
+    // the newest lines are kept in view
+    let items = tree(&progress);
+    let skipped = items
+        .len()
+        .saturating_sub(body.height.saturating_sub(2) as usize);
+    frame.render_widget(
+        List::new(items.into_iter().skip(skipped)).block(Block::bordered().title("progress")),
+        body,
+    );
+
+    let shown = errors.height.saturating_sub(2) as usize;
+    let lines = progress
+        .errors
+        .iter()
+        .skip(progress.errors.len().saturating_sub(shown))
+        .map(|x| Line::raw(x.clone()))
+        .collect::<Vec<_>>();
+    frame.render_widget(
+        Paragraph::new(lines)
+            .style(Style::new().fg(Color::Red))
+            .block(Block::bordered().title(format!("errors ({})", progress.errors.len()))),
+        errors,
+    );
+
+    frame.render_widget(Paragraph::new(progress.status.clone()).dark_gray(), status);
+}
+
+async fn render(
+    mut terminal: Terminal<CrosstermBackend<Stdout>>,
+    mut stop: oneshot::Receiver<()>,
+) -> anyhow::Result<()> {
+    let started = Instant::now();
+    let mut throughput = Throughput(VecDeque::new());
+    let mut interval = tokio::time::interval(Duration::from_millis(250));
+    loop {
+        let stopping = tokio::select! {
+            _ = interval.tick() => false,
+            _ = &mut stop => true,
+        };
+        let rate = throughput.sample();
+        terminal.draw(|frame| draw(frame, started, rate))?;
+        if stopping {
+            return Ok(());
+        }
+    }
+}
+
+// the live interface of --tui, shown in the alternate screen until stopped
+pub struct Tui {
+    stop: oneshot::Sender<()>,
+    handle: JoinHandle<anyhow::Result<()>>,
+}
+
+impl Tui {
+    pub fn start() -> anyhow::Result<Self> {
+        progress::enable();
+        // no raw mode, ctrl-c and the prompts of the export keep working
+        execute!(stdout(), EnterAlternateScreen, Hide).context("failed to set up the terminal")?;
+        let terminal = Terminal::new(CrosstermBackend::new(stdout()))
+            .context("failed to set up the terminal")?;
+        SHOWN.store(true, Ordering::Relaxed);
+        let (stop, receiver) = oneshot::channel();
+        Ok(Self {
+            stop,
+            handle: tokio::spawn(render(terminal, receiver)),
+        })
+    }
+
+    pub async fn stop(self) {
+        progress::finish();
+        let _ = self.stop.send(());
+        let result = self.handle.await;
+        let _ = execute!(stdout(), LeaveAlternateScreen, Show);
+        SHOWN.store(false, Ordering::Relaxed);
+        if let Ok(Err(err)) | Err(err) = result.map_err(anyhow::Error::from) {
+            warn!("failed to draw the interface: {:#}", err);
+        }
+
+        // the error pane is gone with the alternate screen
+        for error in &progress().errors {
+            warn!("{}", error);
+        }
+    }
+}
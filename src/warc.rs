@@ -0,0 +1,315 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::Context;
+use futures::{SinkExt, StreamExt};
+use http::{Extensions, HeaderMap, Method, StatusCode, Version};
+use log::warn;
+use reqwest::{Body, Request, Response, ResponseBuilderExt, Url};
+use reqwest_middleware::{Middleware, Next};
+use tokio::{fs::File, io::AsyncWriteExt, sync::Mutex};
+use uuid::Uuid;
+
+// credentials and headers that don't describe the archived payload, the body is stored decoded and unchunked
+const SKIPPED_HEADERS: [&str; 6] = [
+    "authorization",
+    "cookie",
+    "set-cookie",
+    "content-encoding",
+    "transfer-encoding",
+    "content-length",
+];
+
+// the lines of a record up to its block
+fn record_head(
+    warc_type: &str,
+    record_id: &str,
+    headers: &[(&str, String)],
+    content_type: &str,
+    block_len: u64,
+) -> Vec<u8> {
+    let mut record = format!(
+        "WARC/1.1\r\nWARC-Type: {}\r\nWARC-Record-ID: {}\r\nWARC-Date: {}\r\n",
+        warc_type,
+        record_id,
+        humantime::format_rfc3339_seconds(std::time::SystemTime::now())
+    );
+    for (name, value) in headers {
+        record.push_str(&format!("{name}: {value}\r\n"));
+    }
+    record.push_str(&format!(
+        "Content-Type: {}\r\nContent-Length: {}\r\n\r\n",
+        content_type, block_len
+    ));
+    record.into_bytes()
+}
+
+fn record(
+    warc_type: &str,
+    record_id: &str,
+    headers: &[(&str, String)],
+    content_type: &str,
+    block: &[u8],
+) -> Vec<u8> {
+    let mut record = record_head(
+        warc_type,
+        record_id,
+        headers,
+        content_type,
+        block.len() as u64,
+    );
+    record.extend_from_slice(block);
+    record.extend_from_slice(b"\r\n\r\n");
+    record
+}
+
+fn record_id() -> String {
+    format!("<urn:uuid:{}>", Uuid::new_v4())
+}
+
+// the header lines of an http message, up to its body
+fn http_head(start_line: String, headers: &HeaderMap, body_len: u64) -> Vec<u8> {
+    let mut message = start_line;
+    message.push_str("\r\n");
+    for (name, value) in headers {
+        if SKIPPED_HEADERS.contains(&name.as_str()) {
+            continue;
+        }
+        message.push_str(&format!(
+            "{}: {}\r\n",
+            name,
+            String::from_utf8_lossy(value.as_bytes())
+        ));
+    }
+    if body_len > 0 {
+        message.push_str(&format!("Content-Length: {}\r\n", body_len));
+    }
+    message.push_str("\r\n");
+    message.into_bytes()
+}
+
+// a response while its body is read by the export, the body is spooled to a file of its own
+// so downloads of any size are archived without holding them in memory
+struct Exchange {
+    warc: Arc<Mutex<File>>,
+    url: Url,
+    request: Vec<u8>,
+    status: StatusCode,
+    version: Version,
+    headers: HeaderMap,
+    spool_path: PathBuf,
+    spool: File,
+    body_len: u64,
+    failed: bool,
+}
+
+impl Exchange {
+    async fn spool(&mut self, chunk: &[u8]) {
+        if self.failed {
+            return;
+        }
+        match self.spool.write_all(chunk).await {
+            Ok(()) => self.body_len += chunk.len() as u64,
+            Err(err) => {
+                warn!(
+                    "failed to spool {:?} for the warc: {}",
+                    self.url.as_str(),
+                    err
+                );
+                self.failed = true;
+            }
+        }
+    }
+
+    async fn write(&mut self) -> std::io::Result<()> {
+        self.spool.flush().await?;
+        let response_id = record_id();
+        let target = [("WARC-Target-URI", self.url.to_string())];
+        let head = http_head(
+            format!(
+                "{:?} {} {}",
+                self.version,
+                self.status.as_u16(),
+                self.status.canonical_reason().unwrap_or_default()
+            ),
+            &self.headers,
+            self.body_len,
+        );
+        let mut response = record_head(
+            "response",
+            &response_id,
+            &target,
+            "application/http;msgtype=response",
+            head.len() as u64 + self.body_len,
+        );
+        response.extend(head);
+        let request = record(
+            "request",
+            &record_id(),
+            &[target[0].clone(), ("WARC-Concurrent-To", response_id)],
+            "application/http;msgtype=request",
+            &self.request,
+        );
+
+        // both records of an exchange are written together, requests run concurrently
+        let mut warc = self.warc.lock().await;
+        warc.write_all(&response).await?;
+        let mut body = File::open(&self.spool_path).await?;
+        tokio::io::copy(&mut body, &mut *warc).await?;
+        warc.write_all(b"\r\n\r\n").await?;
+        warc.write_all(&request).await?;
+        warc.flush().await
+    }
+
+    async fn finish(mut self) {
+        if self.failed {
+            return;
+        }
+        if let Err(err) = self.write().await {
+            warn!(
+                "failed to write {:?} to the warc: {}",
+                self.url.as_str(),
+                err
+            );
+        }
+    }
+}
+
+// a body that isn't read to the end isn't archived
+impl Drop for Exchange {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.spool_path);
+    }
+}
+
+// writes every request of the export and its response to a single WARC file, credentials left out
+pub struct WarcMiddleware {
+    file: Arc<Mutex<File>>,
+}
+
+impl WarcMiddleware {
+    pub fn new(path: &Path) -> anyhow::Result<Self> {
+        let mut file =
+            std::fs::File::create(path).with_context(|| format!("failed to create {:?}", path))?;
+        let info = format!(
+            "software: export-schoology/{}\r\nformat: WARC File Format 1.1\r\n",
+            env!("CARGO_PKG_VERSION")
+        );
+        let filename = path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        file.write_all(&record(
+            "warcinfo",
+            &record_id(),
+            &[("WARC-Filename", filename)],
+            "application/warc-fields",
+            info.as_bytes(),
+        ))
+        .with_context(|| format!("failed to write {:?}", path))?;
+        Ok(Self {
+            file: Arc::new(Mutex::new(File::from_std(file))),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for WarcMiddleware {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        // uploads to the destination aren't part of what was exported
+        if req.method() != Method::GET {
+            return next.run(req, extensions).await;
+        }
+        let url = req.url().clone();
+        let mut request_headers = req.headers().clone();
+        if let Some(host) = url.host_str() {
+            let host = match url.port() {
+                Some(port) => format!("{host}:{port}"),
+                None => host.to_string(),
+            };
+            if let Ok(host) = host.parse() {
+                request_headers.insert(http::header::HOST, host);
+            }
+        }
+        let path = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        };
+        let request = http_head(
+            format!("{} {} HTTP/1.1", req.method(), path),
+            &request_headers,
+            0,
+        );
+
+        let response = next.run(req, extensions).await?;
+        let spool_path =
+            std::env::temp_dir().join(format!("export-schoology-{}.warc.tmp", Uuid::new_v4()));
+        let spool = match File::create(&spool_path).await {
+            Ok(spool) => spool,
+            Err(err) => {
+                warn!("failed to spool {:?} for the warc: {}", url.as_str(), err);
+                return Ok(response);
+            }
+        };
+        let response_url = response.url().clone();
+        let status = response.status();
+        let version = response.version();
+        let headers = response.headers().clone();
+        let exchange = Exchange {
+            warc: self.file.clone(),
+            url,
+            request,
+            status,
+            version,
+            headers: headers.clone(),
+            spool_path,
+            spool,
+            body_len: 0,
+            failed: false,
+        };
+
+        // the body is passed on chunk by chunk and archived once it was read to the end, the channel
+        // only takes the next chunk once the export read the last one
+        let (mut sender, receiver) = futures::channel::mpsc::channel(0);
+        tokio::spawn(async move {
+            let mut exchange = exchange;
+            let mut body = response.bytes_stream();
+            while let Some(chunk) = body.next().await {
+                let chunk = match chunk {
+                    Ok(chunk) => chunk,
+                    Err(err) => {
+                        let _ = sender.send(Err(err)).await;
+                        return;
+                    }
+                };
+                exchange.spool(&chunk).await;
+                if sender.send(Ok(chunk)).await.is_err() {
+                    return;
+                }
+            }
+            exchange.finish().await;
+        });
+
+        // the url of the response is kept, redirects to the login page are detected by it
+        let mut rebuilt = http::Response::builder()
+            .status(status)
+            .version(version)
+            .url(response_url);
+        if let Some(rebuilt_headers) = rebuilt.headers_mut() {
+            *rebuilt_headers = headers;
+        }
+        let rebuilt = rebuilt
+            .body(Body::wrap_stream(receiver))
+            .map_err(|x| reqwest_middleware::Error::Middleware(x.into()))?;
+        Ok(Response::from(rebuilt))
+    }
+}
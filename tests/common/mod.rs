@@ -0,0 +1,232 @@
+// each test binary only uses some of the helpers
+#![allow(dead_code)]
+
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+    process::Output,
+};
+
+use serde_json::{json, Value};
+use tokio::process::Command;
+use wiremock::{
+    matchers::{any, method, path_regex},
+    Mock, MockServer, Request, ResponseTemplate,
+};
+
+const FIXTURES_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/api");
+
+// fixtures/api/<path>.json answers GET <api base>/<path>, whatever the query string, and
+// <path>.<status>.json answers it with that status
+fn fixtures(dir: &Path, prefix: &str, routes: &mut Vec<(String, u16, String)>) {
+    let mut entries = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|x| x.unwrap().path())
+        .collect::<Vec<_>>();
+    entries.sort();
+    for path in entries {
+        let name = path.file_name().unwrap().to_string_lossy().to_string();
+        if path.is_dir() {
+            fixtures(&path, &format!("{prefix}/{name}"), routes);
+        } else if let Some(route) = name.strip_suffix(".json") {
+            let (route, status) = match route.rsplit_once('.') {
+                Some((route, status)) => (route, status.parse().unwrap()),
+                None => (route, 200),
+            };
+            routes.push((
+                format!("{prefix}/{route}"),
+                status,
+                std::fs::read_to_string(&path).unwrap(),
+            ));
+        }
+    }
+}
+
+// a Schoology api serving the recorded fixtures, everything else answers as an empty endpoint
+pub struct MockSchoology {
+    pub server: MockServer,
+}
+
+impl MockSchoology {
+    pub async fn start() -> Self {
+        let server = MockServer::start().await;
+        let mut routes = Vec::new();
+        fixtures(Path::new(FIXTURES_DIR), "", &mut routes);
+        // urls in the fixtures point back to the mock server
+        for (_, _, body) in &mut routes {
+            *body = body.replace("{{base}}", &server.uri());
+        }
+        for (route, status, body) in routes.clone() {
+            Mock::given(method("GET"))
+                .and(path_regex(format!("^{route}/?$")))
+                .respond_with(ResponseTemplate::new(status).set_body_raw(body, "application/json"))
+                .with_priority(2)
+                .mount(&server)
+                .await;
+        }
+        // multiget answers every bundled request from the fixtures as well
+        Mock::given(method("POST"))
+            .and(path_regex("^/multiget/?$"))
+            .respond_with(move |request: &Request| {
+                let requests = request.body_json::<Value>().unwrap()["request"]
+                    .as_array()
+                    .cloned()
+                    .unwrap_or_default();
+                let responses = requests
+                    .iter()
+                    .map(|x| {
+                        let path = x.as_str().unwrap_or_default();
+                        let path = path.split('?').next().unwrap().trim_end_matches('/');
+                        match routes.iter().find(|(route, _, _)| route == path) {
+                            Some((_, status, body)) => json!({
+                                "response_code": status,
+                                "body": serde_json::from_str::<Value>(body).unwrap(),
+                            }),
+                            None => json!({ "response_code": 200, "body": {} }),
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                ResponseTemplate::new(200).set_body_json(json!({ "response": responses }))
+            })
+            .with_priority(2)
+            .mount(&server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+            .with_priority(u8::MAX)
+            .mount(&server)
+            .await;
+        Self { server }
+    }
+
+    // user credentials are given, so no authorization is needed
+    pub fn write_config(&self, dir: &Path) -> PathBuf {
+        let config = dir.join("config.toml");
+        std::fs::write(
+            &config,
+            format!(
+                r#"domain = "{}"
+api_base = "{}/"
+app_token = "app-token"
+app_secret = "app-secret"
+user_token = "user-token"
+user_secret = "user-secret"
+
+[retry]
+max_retries = 0
+"#,
+                self.server.address(),
+                self.server.uri()
+            ),
+        )
+        .unwrap();
+        config
+    }
+}
+
+// every file below the directory
+pub fn walkdir(dir: &Path) -> Vec<std::path::PathBuf> {
+    std::fs::read_dir(dir)
+        .unwrap()
+        .map(|x| x.unwrap().path())
+        .flat_map(|x| match x.is_dir() {
+            true => walkdir(&x),
+            false => vec![x],
+        })
+        .collect()
+}
+
+// a run of the binary against the fixtures, with the config and the mock adjusted by the test
+pub struct Export {
+    pub schoology: MockSchoology,
+    pub dir: tempfile::TempDir,
+    pub config: PathBuf,
+    envs: Vec<(String, String)>,
+}
+
+impl Export {
+    pub async fn start() -> Self {
+        let schoology = MockSchoology::start().await;
+        let dir = tempfile::tempdir().unwrap();
+        let config = schoology.write_config(dir.path());
+        Self {
+            schoology,
+            dir,
+            config,
+            envs: Vec::new(),
+        }
+    }
+
+    // variables set for every run of the binary
+    pub fn env(mut self, key: &str, value: &str) -> Self {
+        self.envs.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn configure(self, edit: impl FnOnce(String) -> String) -> Self {
+        let contents = std::fs::read_to_string(&self.config).unwrap();
+        std::fs::write(&self.config, edit(contents)).unwrap();
+        self
+    }
+
+    // mocks with priority 1 take precedence over the fixtures, bundled /multiget requests are still
+    // answered from the fixtures
+    pub async fn mock(self, mock: Mock) -> Self {
+        mock.mount(&self.schoology.server).await;
+        self
+    }
+
+    pub fn export_dir(&self) -> PathBuf {
+        self.dir.path().join("export")
+    }
+
+    async fn output(&self, args: &[&OsStr]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_export-schoology"))
+            .args(args)
+            .envs(self.envs.iter().cloned())
+            .output()
+            .await
+            .unwrap()
+    }
+
+    // runs the binary with these arguments and fails the test if it fails
+    pub async fn command(&self, args: &[&OsStr]) -> Output {
+        let output = self.output(args).await;
+        assert!(
+            output.status.success(),
+            "command failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        output
+    }
+
+    fn export_args<'a>(&'a self, export_dir: &'a Path, args: &'a [&'a str]) -> Vec<&'a OsStr> {
+        let mut command_args = vec![
+            self.config.as_os_str(),
+            "--output".as_ref(),
+            export_dir.as_os_str(),
+        ];
+        command_args.extend(args.iter().map(OsStr::new));
+        command_args
+    }
+
+    pub async fn run(&self, args: &[&str]) -> Output {
+        let export_dir = self.export_dir();
+        self.command(&self.export_args(&export_dir, args)).await
+    }
+
+    // runs the export and fails the test if it succeeds
+    pub async fn run_failing(&self, args: &[&str]) -> Output {
+        let export_dir = self.export_dir();
+        let output = self.output(&self.export_args(&export_dir, args)).await;
+        assert!(!output.status.success(), "the export succeeded");
+        output
+    }
+}
+
+// exports the fixtures into export/ of a new temporary directory
+pub async fn export(args: &[&str]) -> tempfile::TempDir {
+    let export = Export::start().await;
+    export.run(args).await;
+    export.dir
+}
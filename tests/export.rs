@@ -0,0 +1,984 @@
+mod common;
+
+use std::{
+    path::Path,
+    process::Stdio,
+    time::{Duration, Instant},
+};
+
+use common::{export, walkdir, Export};
+use serde_json::Value;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    process::Command,
+};
+use wiremock::{
+    matchers::{method, path_regex, query_param},
+    Mock, ResponseTemplate,
+};
+
+fn read_json(path: &Path) -> Value {
+    serde_json::from_str(
+        &std::fs::read_to_string(path).unwrap_or_else(|_| panic!("{path:?} is missing")),
+    )
+    .unwrap()
+}
+
+#[tokio::test]
+async fn exports_updates() {
+    let dir = export(&[]).await;
+    let export_dir = dir.path().join("export");
+
+    let updates = read_json(&export_dir.join("updates").join("updates_0.json"));
+    assert_eq!(updates["update"][0]["body"], "Welcome to Chemistry!");
+    // the author of the update is exported too
+    let author = read_json(&export_dir.join("users").join("200").join("user_info.json"));
+    assert_eq!(author["name_display"], "Test Teacher");
+
+    // own posts get their whole comment thread
+    let posted = read_json(&export_dir.join("updates").join("posted.json"));
+    assert_eq!(posted[0]["id"], 51);
+    assert_eq!(posted[0]["realm"], "sections/5");
+    assert_eq!(posted[0]["comments"][0]["comment"], "They are attached.");
+    assert!(export_dir
+        .join("updates")
+        .join("update_51_comment_90_72_notes.txt")
+        .exists());
+}
+
+#[tokio::test]
+async fn exports_folders() {
+    let dir = export(&["--layout", "id"]).await;
+    let files_dir = dir
+        .path()
+        .join("export")
+        .join("courses")
+        .join("5")
+        .join("files");
+
+    let index = read_json(&files_dir.join("folder_index.json"));
+    let titles = index
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|x| x["title"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(titles, ["Unit 1", "Lab report", "Answer keys"]);
+    // a folder answering 403 is recorded instead of failing the export
+    let denied = read_json(&files_dir.join("4").join("denied.json"));
+    assert_eq!(denied["status"], 403);
+    let summary = read_json(&dir.path().join("export").join("summary.json"));
+    assert!(summary["failures"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|x| x.as_str().unwrap().starts_with("skipped folder 4")));
+
+    let folder = read_json(&files_dir.join("1").join("_folder.json"));
+    assert_eq!(folder["body"], "<p>Atoms and molecules</p>");
+    let page = read_json(&files_dir.join("1").join("3").join("info.json"));
+    assert_eq!(page["body"], "<p>Read chapter 1.</p>");
+    // the author of the page is exported with a reference to it
+    let index = read_json(&dir.path().join("export").join("users").join("index.json"));
+    assert_eq!(
+        index["200"]["references"]["page author"][0],
+        "courses/5/files/1/3/info.json"
+    );
+}
+
+#[tokio::test]
+async fn prefetches_folder_items() {
+    let export = Export::start().await;
+    let output = export.run(&["--layout", "id"]).await;
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("failed to prefetch"));
+
+    let requests = export.schoology.server.received_requests().await.unwrap();
+    let multiget = requests
+        .iter()
+        .find(|x| x.url.path() == "/multiget")
+        .expect("the folder items weren't prefetched");
+    let bundled = multiget.body_json::<Value>().unwrap()["request"].clone();
+    assert!(bundled
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|x| x == "/courses/5/folder/1"));
+    // the prefetched folder isn't requested again
+    assert!(!requests
+        .iter()
+        .any(|x| x.method.as_str() == "GET" && x.url.path() == "/courses/5/folder/1"));
+    let page = read_json(&export.export_dir().join("courses/5/files/1/3/info.json"));
+    assert_eq!(page["body"], "<p>Read chapter 1.</p>");
+}
+
+#[tokio::test]
+async fn writes_directory_indexes() {
+    let dir = export(&["--layout", "id"]).await;
+    let export_dir = dir.path().join("export");
+
+    let courses = read_json(&export_dir.join("courses").join("index.json"));
+    assert_eq!(courses[0]["title"], "Chemistry: Period 1");
+    let files_dir = export_dir.join("courses").join("5").join("files");
+    let files = read_json(&files_dir.join("index.json"));
+    assert_eq!(files[1]["title"], "Lab report");
+    assert_eq!(files[1]["due"], "2026-01-15 23:59:00");
+    let folder = read_json(&files_dir.join("1").join("index.json"));
+    assert_eq!(folder[0]["author"], "Test Teacher");
+    let md = std::fs::read_to_string(files_dir.join("index.md")).unwrap();
+    assert!(md.contains("| [2](2/) | Lab report | 2026-01-15 23:59:00 |"));
+    // the people index is left alone
+    assert!(read_json(&export_dir.join("users").join("index.json")).is_object());
+}
+
+#[tokio::test]
+async fn exports_assignments() {
+    let dir = export(&["--layout", "id"]).await;
+    let assignment_dir = dir
+        .path()
+        .join("export")
+        .join("courses")
+        .join("5")
+        .join("files")
+        .join("2");
+
+    let assignment = read_json(&assignment_dir.join("info.json"));
+    assert_eq!(assignment["title"], "Lab report");
+    assert!(assignment_dir.join("grade.json").exists());
+    assert!(assignment_dir.join("submission_status.json").exists());
+    let statistics = read_json(&assignment_dir.join("statistics.json"));
+    assert_eq!(statistics["statistics"]["median"], 8);
+    // a draft saved after the last submission is exported with its attachments
+    let status = read_json(&assignment_dir.join("submission_status.json"));
+    assert_eq!(status["draft"], true);
+    assert_eq!(status["drafts"][0]["id"], "4");
+    assert!(assignment_dir.join("draft_4").join("body.html").exists());
+    assert!(assignment_dir.join("draft_4").join("73_draft.txt").exists());
+    // feedback is kept with each revision that was turned in, its attachments are stored once
+    let revision_dir = assignment_dir.join("revision_1");
+    let feedback = read_json(&revision_dir.join("feedback.json"));
+    assert_eq!(feedback["comment"][0]["uid"], 200);
+    let attachment = &feedback["comment"][0]["attachments"]["files"]["file"][0];
+    assert_eq!(attachment["export_path"], "../feedback_91_75_marked.txt");
+    assert!(assignment_dir.join("feedback_91_75_marked.txt").exists());
+    assert!(!revision_dir.join("feedback_91_75_marked.txt").exists());
+    assert!(!assignment_dir.join("feedback.json").exists());
+
+    let timeline = read_json(&dir.path().join("export").join("timeline.json"));
+    assert_eq!(timeline[0]["date"], "2026-01-15 23:59:00");
+    assert_eq!(timeline[0]["course"], "5");
+    assert_eq!(timeline[0]["path"], "courses/5/files/2");
+    let calendar = std::fs::read_to_string(dir.path().join("export").join("timeline.ics")).unwrap();
+    assert!(calendar.contains("DTSTART:20260115T235900\r\nSUMMARY:Lab report due\r\n"));
+}
+
+#[tokio::test]
+async fn exports_grade_setup() {
+    let dir = export(&["--layout", "id"]).await;
+    let course_dir = dir.path().join("export").join("courses").join("5");
+
+    let setup = read_json(&course_dir.join("grade_setup.json"));
+    assert_eq!(setup["weighted_categories"], "1");
+    assert_eq!(setup["rounding"]["final_grade_rounding"], "2");
+    assert_eq!(setup["grading_category"][0]["title"], "Labs");
+    assert_eq!(setup["grading_category"][0]["weight"], "60");
+    assert_eq!(setup["grading_category"][0]["drop_lowest"], "1");
+}
+
+#[tokio::test]
+async fn exports_messages() {
+    let dir = export(&[]).await;
+    let messages_dir = dir.path().join("export").join("messages");
+
+    let message = read_json(&messages_dir.join("message_60.json"));
+    assert_eq!(
+        message["message"][0]["message"],
+        "Please pick a lab partner by Friday."
+    );
+    let mailbox = read_json(&messages_dir.join("mailbox.json"));
+    assert_eq!(mailbox[0]["folder"], "inbox");
+    // the attachment of the reply is only in the thread
+    assert!(messages_dir.join("message_60_71_partners.txt").exists());
+}
+
+#[tokio::test]
+async fn exports_notifications() {
+    let dir = export(&[]).await;
+    let notifications_dir = dir.path().join("export").join("notifications");
+
+    let history = read_json(&notifications_dir.join("history.json"));
+    let kinds = history
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|x| x["kind"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(kinds, ["grade", "due date", "comment"]);
+    assert_eq!(history[0]["date"], "2026-01-02T00:00:00Z");
+    assert!(notifications_dir.join("notifications.json").exists());
+}
+
+#[tokio::test]
+async fn exports_course_outline() {
+    let dir = export(&["--outline"]).await;
+    let export_dir = dir.path().join("export");
+    let course_dir = export_dir.join("courses").join("5");
+
+    let outline = read_json(&course_dir.join("outline.json"));
+    let unit = &outline["items"][0];
+    assert_eq!(unit["title"], "Unit 1");
+    assert_eq!(unit["children"][0]["title"], "Reading");
+    assert_eq!(outline["items"][1]["type"], "assignment");
+    // a folder answering 403 is outlined without its children
+    let locked = &outline["items"][2];
+    assert_eq!(locked["denied"], true);
+    assert!(locked.get("children").is_none());
+    let md = std::fs::read_to_string(course_dir.join("outline.md")).unwrap();
+    assert!(md.contains(
+        "- **Unit 1**\n  - Reading (page)\n- Lab report (assignment)\n- **Answer keys** (not accessible)\n"
+    ));
+    // nothing but the outline is exported
+    assert!(!course_dir.join("info.json").exists());
+    assert!(!export_dir.join("users").join("100").exists());
+}
+
+#[tokio::test]
+async fn exports_group_resources() {
+    let dir = export(&["--layout", "id"]).await;
+    let resources_dir = dir
+        .path()
+        .join("export")
+        .join("groups")
+        .join("8")
+        .join("resources");
+
+    let index = read_json(&resources_dir.join("folder_index.json"));
+    assert_eq!(index[0]["title"], "Opening repertoire");
+    let resource_dir = resources_dir.join("90");
+    assert_eq!(
+        read_json(&resource_dir.join("info.json"))["body"],
+        "<p>Openings we study this season.</p>"
+    );
+    assert!(resource_dir.join("body.html").exists());
+    assert!(resource_dir.join("attachment_74_openings.pdf").exists());
+}
+
+// comments disabled on a post don't stop the blog export
+#[tokio::test]
+async fn exports_blog_without_comments() {
+    let dir = export(&[]).await;
+    let blog_dir = dir
+        .path()
+        .join("export")
+        .join("users")
+        .join("100")
+        .join("blog");
+
+    let posts = read_json(&blog_dir.join("posts_0.json"));
+    assert_eq!(posts["blog"][0]["title"], "Field trip");
+    assert!(!blog_dir.join("post_1_comments.json").exists());
+}
+
+// a student denied the parent course still gets the syllabus of the section
+#[tokio::test]
+async fn exports_syllabus_without_course() {
+    let mut section = read_json(
+        &Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/api/sections/5.json"),
+    );
+    section["description"] = "<p>Bring goggles</p>".into();
+    let export = Export::start()
+        .await
+        .mock(
+            Mock::given(method("GET"))
+                .and(path_regex("^/courses/7/?$"))
+                .respond_with(ResponseTemplate::new(403))
+                .with_priority(1),
+        )
+        .await
+        .mock(
+            Mock::given(method("GET"))
+                .and(path_regex("^/sections/5/?$"))
+                .respond_with(ResponseTemplate::new(200).set_body_json(section))
+                .with_priority(1),
+        )
+        .await;
+    export.run(&["--layout", "id"]).await;
+    let course_dir = export.export_dir().join("courses").join("5");
+
+    let syllabus = std::fs::read_to_string(course_dir.join("syllabus.html")).unwrap();
+    assert_eq!(syllabus, "<p>Bring goggles</p>");
+    assert!(!course_dir.join("course.json").exists());
+}
+
+// an empty body means the content is still being prepared, whatever its length header says
+#[tokio::test]
+async fn waits_for_empty_response() {
+    let export = Export::start()
+        .await
+        .mock(
+            Mock::given(method("GET"))
+                .and(path_regex("^/users/100/blogs/?$"))
+                .respond_with(ResponseTemplate::new(200).set_body_string(" \n"))
+                .up_to_n_times(1)
+                .with_priority(1),
+        )
+        .await;
+    export.run(&[]).await;
+
+    let requests = export.schoology.server.received_requests().await.unwrap();
+    let blog_requests = requests
+        .iter()
+        .filter(|x| x.url.path() == "/users/100/blogs")
+        .count();
+    assert_eq!(blog_requests, 2);
+    let blog_dir = export.export_dir().join("users").join("100").join("blog");
+    assert!(blog_dir.join("posts_0.json").exists());
+}
+
+#[tokio::test]
+async fn anonymizes_export() {
+    let dir = export(&["--anonymize"]).await;
+    let export_dir = dir.path().join("export");
+
+    for file in walkdir(&export_dir) {
+        let Ok(contents) = std::fs::read_to_string(&file) else {
+            continue;
+        };
+        for name in ["Test Student", "Test Teacher", "Test Child"] {
+            assert!(!contents.contains(name), "{name} is left in {file:?}");
+        }
+        for uid in ["users/100", "users/200", "children/300"] {
+            assert!(!contents.contains(uid), "{uid} is left in {file:?}");
+        }
+    }
+    // the users are numbered instead, directories included
+    let users_dir = export_dir.join("users");
+    for uid in ["100", "200", "300"] {
+        assert!(!users_dir.join(uid).exists());
+    }
+    let own = std::fs::read_to_string(users_dir.join("self")).unwrap();
+    let user = read_json(&users_dir.join(&own).join("user_info.json"));
+    assert_eq!(user["id"], own.parse::<i64>().unwrap());
+    assert_eq!(user["name_display"], format!("User {own}"));
+    let index = read_json(&users_dir.join("index.json"));
+    assert!(index.get(&own).is_some());
+    assert!(index.get("100").is_none());
+    let members =
+        std::fs::read_to_string(export_dir.join("courses").join("5").join("members.csv")).unwrap();
+    assert!(!members.lines().skip(1).any(|x| x.starts_with("100,")));
+    let mailbox = read_json(&export_dir.join("messages").join("mailbox.json"));
+    assert_ne!(mailbox[0]["recipient_ids"][0], 100);
+}
+
+// the error page isn't saved as the attachment, which is left for fetch-attachments instead
+async fn assert_attachment_left_pending(status: u16) {
+    let export = Export::start()
+        .await
+        .mock(
+            Mock::given(method("GET"))
+                .and(path_regex("^/attachment/70/"))
+                .respond_with(
+                    ResponseTemplate::new(status).set_body_string("<html>unavailable</html>"),
+                )
+                .with_priority(1),
+        )
+        .await;
+    export.run(&["--layout", "id"]).await;
+    let export_dir = export.export_dir();
+
+    let page_dir = export_dir
+        .join("courses")
+        .join("5")
+        .join("files")
+        .join("1")
+        .join("3");
+    assert!(!page_dir.join("attachment_70_chapter1.pdf").exists());
+    let manifest = read_json(&export_dir.join("manifest.json"));
+    let entry = manifest
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|x| x["url"].as_str().unwrap().contains("/attachment/70/"))
+        .unwrap();
+    assert_eq!(entry["pending"], true);
+    let summary = read_json(&export_dir.join("summary.json"));
+    assert!(summary["failures"].as_array().unwrap().iter().any(|x| x
+        .as_str()
+        .unwrap()
+        .starts_with("attachment 70 failed to download")));
+}
+
+#[tokio::test]
+async fn skips_failed_attachment_downloads() {
+    assert_attachment_left_pending(404).await;
+}
+
+#[tokio::test]
+async fn skips_attachment_server_errors() {
+    assert_attachment_left_pending(503).await;
+}
+
+#[tokio::test]
+async fn fails_on_malformed_response() {
+    let export = Export::start()
+        .await
+        .mock(
+            Mock::given(method("GET"))
+                .and(path_regex("^/messages/inbox/60"))
+                .respond_with(
+                    ResponseTemplate::new(200).set_body_raw(r#"{"message": ["#, "application/json"),
+                )
+                .with_priority(1),
+        )
+        .await;
+    let output = export.run_failing(&[]).await;
+    // the error names the response that couldn't be parsed
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("/messages/inbox/60\" returned an unexpected response"));
+}
+
+#[tokio::test]
+async fn fails_on_missing_field() {
+    let export = Export::start()
+        .await
+        .mock(
+            Mock::given(method("GET"))
+                .and(path_regex("^/users/100/sections"))
+                .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                    "section": [{ "id": "5", "course_title": "Chemistry" }],
+                })))
+                .with_priority(1),
+        )
+        .await;
+    let output = export.run_failing(&[]).await;
+    assert!(String::from_utf8_lossy(&output.stderr).contains("course 5 has no links.self"));
+}
+
+#[tokio::test]
+async fn compresses_json() {
+    let dir = export(&["--layout", "id", "--compress-json", "zstd"]).await;
+    let export_dir = dir.path().join("export");
+    let course_dir = export_dir.join("courses").join("5");
+    assert!(course_dir.join("info.json.zst").exists());
+    assert!(!course_dir.join("info.json").exists());
+    // attachments and the manifest are left as they are
+    assert!(export_dir.join("manifest.json").exists());
+    assert!(course_dir
+        .join("files")
+        .join("1")
+        .join("3")
+        .join("attachment_70_chapter1.pdf")
+        .exists());
+    // the steps after the export read the compressed files
+    assert!(export_dir.join("courses").join("index.json").exists());
+
+    let output = Command::new(env!("CARGO_BIN_EXE_export-schoology"))
+        .arg("inspect")
+        .arg(&export_dir)
+        .arg("--courses")
+        .output()
+        .await
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Chemistry: Period 1"), "{stdout}");
+}
+
+#[tokio::test]
+async fn exports_warc() {
+    let export = Export::start()
+        .await
+        .mock(
+            Mock::given(method("GET"))
+                .and(path_regex("^/users/100/blogs"))
+                .respond_with(
+                    ResponseTemplate::new(200)
+                        .insert_header("Set-Cookie", "SESS=session-secret; HttpOnly")
+                        .set_body_string("{}"),
+                )
+                .with_priority(1),
+        )
+        .await;
+    let warc_path = export.dir.path().join("export.warc");
+    export.run(&["--warc", warc_path.to_str().unwrap()]).await;
+
+    let warc = std::fs::read_to_string(&warc_path).unwrap();
+    assert!(warc.starts_with("WARC/1.1\r\nWARC-Type: warcinfo\r\n"));
+    assert!(warc.contains("Welcome to Chemistry!"));
+    // the oauth tokens and the browser session of the server stay out of the archive
+    assert!(!warc.contains("user-token"));
+    assert!(warc.contains("/users/100/blogs"));
+    // downloads are streamed into the archive too
+    assert!(warc.contains(
+        "WARC-Target-URI: {{base}}/attachment/70/source/chapter1.pdf"
+            .replace("{{base}}", &export.schoology.server.uri())
+            .as_str()
+    ));
+    assert!(!warc.contains("session-secret"));
+    assert_eq!(
+        warc.matches("WARC-Type: response").count(),
+        warc.matches("WARC-Type: request").count()
+    );
+}
+
+#[tokio::test]
+async fn writes_grade_report() {
+    let dir = export(&["--grade-report", "--grade-report-csv"]).await;
+    let export_dir = dir.path().join("export");
+
+    let report = read_json(&export_dir.join("report.json"));
+    let course = &report["courses"][0];
+    assert_eq!(course["course_id"], "5");
+    // weighted by category, the missing lab counts as zero and the excused test not at all
+    let trend = course["trend"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|x| x["percentage"].as_f64().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(trend, [80.0, 86.0, 70.0]);
+    assert_eq!(course["periods"][0]["final_grade"], 70);
+    assert_eq!(report["gpa"], 2.0);
+
+    let csv = std::fs::read_to_string(export_dir.join("grade_trends.csv")).unwrap();
+    assert_eq!(csv.lines().count(), 4);
+}
+
+#[tokio::test]
+async fn fetches_attachments_later() {
+    let export = Export::start().await;
+    let export_dir = export.export_dir();
+
+    export.run(&["--no-attachments"]).await;
+    let manifest = read_json(&export_dir.join("manifest.json"));
+    assert_eq!(manifest[0]["pending"], true);
+    let attachment = export_dir.join(manifest[0]["path"].as_str().unwrap());
+    assert!(!attachment.exists());
+
+    export
+        .command(&[
+            "fetch-attachments".as_ref(),
+            export.config.as_os_str(),
+            export_dir.as_os_str(),
+        ])
+        .await;
+    let manifest = read_json(&export_dir.join("manifest.json"));
+    assert!(manifest[0].get("pending").is_none());
+    assert_eq!(std::fs::read_to_string(&attachment).unwrap(), "{}");
+}
+
+#[tokio::test]
+async fn keeps_fetching_attachments_after_a_failure() {
+    let export = Export::start()
+        .await
+        .mock(
+            Mock::given(method("GET"))
+                .and(path_regex("^/attachment/70/"))
+                .respond_with(ResponseTemplate::new(404))
+                .with_priority(1),
+        )
+        .await;
+    let export_dir = export.export_dir();
+
+    export.run(&["--no-attachments"]).await;
+    export
+        .command(&[
+            "fetch-attachments".as_ref(),
+            export.config.as_os_str(),
+            export_dir.as_os_str(),
+        ])
+        .await;
+    let manifest = read_json(&export_dir.join("manifest.json"));
+    let entries = manifest.as_array().unwrap();
+    assert!(entries.len() > 1);
+    for entry in entries {
+        let failed = entry["url"].as_str().unwrap().contains("/attachment/70/");
+        assert_eq!(entry.get("pending").is_some(), failed);
+        let path = export_dir.join(entry["path"].as_str().unwrap());
+        assert_eq!(path.exists(), !failed);
+    }
+}
+
+// large attachments are uploaded a part at a time, with a client of their own
+#[tokio::test]
+async fn uploads_large_files_in_parts() {
+    let export = Export::start()
+        .await
+        .mock(
+            Mock::given(method("GET"))
+                .and(path_regex("^/attachment/70/"))
+                .respond_with(ResponseTemplate::new(200).set_body_bytes(vec![b'a'; 20 << 20]))
+                .with_priority(1),
+        )
+        .await
+        .mock(
+            Mock::given(method("POST"))
+                .and(path_regex("^/s3/"))
+                .and(query_param("uploads", ""))
+                .respond_with(ResponseTemplate::new(200).set_body_string(
+                    "<InitiateMultipartUploadResult><UploadId>upload-1</UploadId></InitiateMultipartUploadResult>",
+                ))
+                .with_priority(1),
+        )
+        .await;
+    let mut export = export;
+    for part in ["1", "2"] {
+        export = export
+            .mock(
+                Mock::given(method("PUT"))
+                    .and(path_regex("^/s3/"))
+                    .and(query_param("partNumber", part))
+                    .and(query_param("uploadId", "upload-1"))
+                    .respond_with(
+                        ResponseTemplate::new(200)
+                            .insert_header("ETag", format!("\"etag-{part}\"")),
+                    )
+                    .with_priority(1),
+            )
+            .await;
+    }
+    let endpoint = format!("{}/s3", export.schoology.server.uri());
+    let export = export
+        .env("AWS_ENDPOINT_URL", &endpoint)
+        .env("AWS_ACCESS_KEY_ID", "access-key")
+        .env("AWS_SECRET_ACCESS_KEY", "secret-key");
+    export.run(&["--dest", "s3://bucket/exports"]).await;
+
+    let requests = export.schoology.server.received_requests().await.unwrap();
+    let uploads = requests
+        .iter()
+        .filter(|x| x.url.path().starts_with("/s3/"))
+        .collect::<Vec<_>>();
+    assert!(uploads.iter().all(|x| x.headers["authorization"]
+        .to_str()
+        .unwrap()
+        .starts_with("AWS4-HMAC-SHA256 ")));
+    let parts = uploads
+        .iter()
+        .filter(|x| x.url.query().is_some_and(|x| x.contains("partNumber")))
+        .collect::<Vec<_>>();
+    assert!(parts.iter().all(|x| x.url.path().ends_with("chapter1.pdf")));
+    // every upload of the file is split in a full part and the rest
+    let second_parts = parts
+        .iter()
+        .filter(|x| x.url.query().unwrap().starts_with("partNumber=2&"))
+        .count();
+    assert!(second_parts > 0);
+    assert_eq!(parts.len(), second_parts * 2);
+    let bytes = parts.iter().map(|x| x.body.len()).sum::<usize>();
+    assert_eq!(bytes, second_parts * (20 << 20));
+    let complete = uploads
+        .iter()
+        .find(|x| x.method.as_str() == "POST" && x.url.query() == Some("uploadId=upload-1"))
+        .unwrap();
+    let complete = String::from_utf8_lossy(&complete.body);
+    assert!(complete.contains("<PartNumber>2</PartNumber><ETag>&quot;etag-2&quot;</ETag>"));
+}
+
+// attachments without a filesize are checked by their size on disk and their hash
+#[tokio::test]
+async fn reuses_attachments_without_filesize() {
+    let export = Export::start().await;
+    export.run(&["--resume"]).await;
+    export.run(&["--resume"]).await;
+
+    let downloads = export
+        .schoology
+        .server
+        .received_requests()
+        .await
+        .unwrap()
+        .into_iter()
+        .filter(|x| x.url.path() == "/attachment/75/source/marked.txt")
+        .count();
+    assert_eq!(downloads, 1);
+    let manifest = read_json(&export.export_dir().join("manifest.json"));
+    let entry = manifest
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|x| x["url"].as_str().unwrap().contains("/attachment/75/"))
+        .unwrap();
+    assert_eq!(entry["filesize"], Value::Null);
+    assert_eq!(entry["bytes"], 2);
+}
+
+#[tokio::test]
+async fn keeps_complete_files_of_interrupted_export() {
+    let export = Export::start().await;
+    let export_dir = export.export_dir();
+    for _ in 0..2 {
+        export.run(&["--resume"]).await;
+        // an interrupted export never gets to write its manifest
+        std::fs::remove_file(export_dir.join("manifest.json")).unwrap();
+    }
+
+    let downloads = export
+        .schoology
+        .server
+        .received_requests()
+        .await
+        .unwrap()
+        .into_iter()
+        .filter(|x| x.url.path() == "/attachment/71/source/partners.txt")
+        .count();
+    assert_eq!(downloads, 1);
+    let temp_files = walkdir(&export_dir)
+        .into_iter()
+        .filter(|x| x.extension().is_some_and(|x| x == "tmp"))
+        .collect::<Vec<_>>();
+    assert!(temp_files.is_empty(), "{temp_files:?} are left behind");
+}
+
+#[tokio::test]
+async fn exports_course_analytics() {
+    let export = Export::start()
+        .await
+        .configure(|x| format!("{x}\n[content]\nanalytics = true\n"));
+    export.run(&[]).await;
+
+    let course_dir = export.export_dir().join("courses").join("5");
+    let csv = std::fs::read_to_string(course_dir.join("analytics.csv")).unwrap();
+    let lines = csv.lines().collect::<Vec<_>>();
+    assert_eq!(lines[0], "uid,last_access,time_spent,visits");
+    assert_eq!(lines[1], "100,1767312000,5400,42");
+    assert_eq!(lines.len(), 3);
+}
+
+#[tokio::test]
+async fn exports_group_access_codes() {
+    let dir = export(&[]).await;
+    // the groups fixture wraps its array in "groups" like some deployments do
+    let group_dir = dir.path().join("export").join("groups").join("8");
+
+    let access = read_json(&group_dir.join("access.json"));
+    assert_eq!(access["access_code"]["access_code"], "K7X2-P9QM");
+    assert_eq!(access["invite_type"], "invite");
+}
+
+#[tokio::test]
+async fn exports_every_account() {
+    let export = Export::start().await.configure(|x| {
+        format!(
+            r#"parallel_accounts = true
+{x}
+[[accounts]]
+name = "student"
+user_token = "student-token"
+user_secret = "student-secret"
+
+[[accounts]]
+name = "parent"
+user_token = "parent-token"
+user_secret = "parent-secret"
+"#
+        )
+    });
+    export.run(&[]).await;
+
+    for account in ["student", "parent"] {
+        let account_dir = export.export_dir().join(account);
+        assert!(account_dir.join("manifest.json").exists());
+        assert!(account_dir.join("summary.json").exists());
+    }
+}
+
+#[tokio::test]
+async fn exports_children() {
+    let dir = export(&[]).await;
+    let export_dir = dir.path().join("export");
+
+    let sections = read_json(
+        &export_dir
+            .join("children")
+            .join("300")
+            .join("sections.json"),
+    );
+    assert_eq!(sections["section"][0]["course_title"], "Biology");
+    let child = read_json(&export_dir.join("users").join("300").join("user_info.json"));
+    assert_eq!(child["name_display"], "Test Child");
+}
+
+#[tokio::test]
+async fn sends_user_agent() {
+    let export = Export::start().await;
+    export.run(&["--user-agent", "district-backup/1.0"]).await;
+
+    let requests = export.schoology.server.received_requests().await.unwrap();
+    assert!(!requests.is_empty());
+    for request in requests {
+        assert_eq!(request.headers["user-agent"], "district-backup/1.0");
+    }
+}
+
+#[tokio::test]
+async fn waits_out_rate_limit() {
+    let export = Export::start()
+        .await
+        .mock(
+            Mock::given(method("GET"))
+                .and(path_regex("^/users/100/blogs"))
+                .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "3"))
+                .up_to_n_times(1)
+                .with_priority(1),
+        )
+        .await;
+    let started = Instant::now();
+    let output = export.run(&["--max-retries", "1"]).await;
+    // the retry waits for the window named by Retry-After, not just its own backoff
+    assert!(started.elapsed() >= Duration::from_secs(3));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("pausing all requests for 3s"));
+}
+
+#[tokio::test]
+async fn reauthorizes_while_listening_for_pause() {
+    let export =
+        Export::start()
+            .await
+            .mock(
+                Mock::given(method("GET"))
+                    .and(path_regex("^/users/100/blogs"))
+                    .respond_with(ResponseTemplate::new(401))
+                    .up_to_n_times(1)
+                    .with_priority(1),
+            )
+            .await
+            .mock(
+                Mock::given(path_regex("^/oauth/request_token"))
+                    .respond_with(ResponseTemplate::new(200).set_body_string(
+                        "oauth_token=request-token&oauth_token_secret=request-secret",
+                    ))
+                    .with_priority(1),
+            )
+            .await
+            .mock(
+                Mock::given(path_regex("^/oauth/access_token"))
+                    .respond_with(ResponseTemplate::new(200).set_body_string(
+                        "oauth_token=renewed-token&oauth_token_secret=renewed-secret",
+                    ))
+                    .with_priority(1),
+            )
+            .await;
+    let mut child = Command::new(env!("CARGO_BIN_EXE_export-schoology"))
+        .arg(&export.config)
+        .arg("--output")
+        .arg(export.export_dir())
+        .env("SCHOOLOGY_INTERACTIVE", "1")
+        .stdin(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    let mut stderr = BufReader::new(child.stderr.take().unwrap()).lines();
+    let mut log = String::new();
+    let status = tokio::time::timeout(Duration::from_secs(60), async {
+        let mut listening = false;
+        while let Some(line) = stderr.next_line().await.unwrap() {
+            listening |= line.contains("type p and press ENTER to pause");
+            // the pause listener is already reading stdin when the login asks for ENTER
+            if line.contains("press ENTER once authorized") {
+                assert!(listening);
+                stdin.write_all(b"\n").await.unwrap();
+            }
+            log += &line;
+            log.push('\n');
+        }
+        child.wait().await.unwrap()
+    })
+    .await
+    .expect("the login never got the ENTER");
+    assert!(status.success(), "export failed: {log}");
+
+    let retried = export
+        .schoology
+        .server
+        .received_requests()
+        .await
+        .unwrap()
+        .into_iter()
+        .filter(|x| x.url.path() == "/users/100/blogs")
+        .filter(|x| {
+            x.headers["authorization"]
+                .to_str()
+                .unwrap()
+                .contains("oauth_token=\"renewed-token\"")
+        })
+        .count();
+    assert_eq!(retried, 1);
+}
+
+#[cfg(feature = "search")]
+#[tokio::test]
+async fn searches_export() {
+    let dir = export(&["--layout", "id", "--search-index"]).await;
+    let export_dir = dir.path().join("export");
+    assert!(export_dir.join("search_index").exists());
+
+    let output = Command::new(env!("CARGO_BIN_EXE_export-schoology"))
+        .arg("search")
+        .arg(&export_dir)
+        .arg("titration")
+        .output()
+        .await
+        .unwrap();
+    assert!(output.status.success());
+    let results = String::from_utf8_lossy(&output.stdout);
+    assert!(results.contains("assignment \"Lab report\" courses/5/files/2/info.json"));
+    assert!(results.contains("Write up the titration lab"));
+}
+
+#[tokio::test]
+async fn exports_report_cards() {
+    let dir = export(&[]).await;
+    let period_dir = dir.path().join("export").join("reports").join("p1");
+
+    let period = read_json(&period_dir.join("period.json"));
+    assert_eq!(period["title"], "Semester 1");
+    let grades = read_json(&period_dir.join("grades.json"));
+    assert_eq!(grades["section"][0]["final_grade"][0]["grade"], 70);
+}
+
+#[tokio::test]
+async fn skips_empty_report_cards() {
+    let export = Export::start()
+        .await
+        .mock(
+            Mock::given(method("GET"))
+                .and(path_regex("^/users/100/grades"))
+                .and(query_param("grading_period_ids", "p1"))
+                .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({})))
+                .with_priority(1),
+        )
+        .await;
+    export.run(&[]).await;
+    assert!(!export.export_dir().join("reports").join("p1").exists());
+}
+
+#[tokio::test]
+async fn splits_compressed_export() {
+    let dir = export(&["--compress-json", "gzip", "--split-by", "course"]).await;
+    let course_dir = dir.path().join("export").join("split").join("5 Chemistry");
+
+    // the compressed files are copied as they are, the index is rewritten for the course only
+    assert!(course_dir
+        .join("courses")
+        .join("5")
+        .join("info.json.gz")
+        .exists());
+    let index = read_json(&course_dir.join("users").join("index.json"));
+    assert!(index.get("100").is_some());
+    assert!(!course_dir.join("users").join("index.json.gz").exists());
+}
+
+#[tokio::test]
+async fn summarizes_phases() {
+    let dir = export(&[]).await;
+    let summary = read_json(&dir.path().join("export").join("summary.json"));
+    let phases = summary["phases"].as_array().unwrap();
+    let phase = |name: &str| phases.iter().find(|x| x["name"] == name).unwrap();
+
+    // the other users are exported alongside the courses and counted on their own
+    let users = phase("users");
+    assert_eq!(users["entities"]["user"], 2);
+    assert!(users["bytes_written"].as_u64().unwrap() > 0);
+    assert!(phase("courses")["entities"].get("user").is_none());
+    assert!(phase("courses")["bytes_written"].as_u64().unwrap() > 0);
+}
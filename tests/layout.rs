@@ -0,0 +1,98 @@
+mod common;
+
+use std::path::Path;
+
+use common::{export, walkdir};
+
+const SNAPSHOTS_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/snapshots");
+
+// files whose contents are part of the snapshot, the rest only by name
+const KEY_FILES: &[&str] = &[
+    "courses/5/grade_setup.json",
+    "courses/5/info.json",
+    "courses/index.md",
+    "users/index.json",
+];
+
+// the mock server listens on a different port every run
+fn normalize(contents: &str) -> String {
+    let mut normalized = String::new();
+    let mut rest = contents;
+    while let Some(start) = rest.find("http://127.0.0.1:") {
+        normalized += &rest[..start];
+        normalized += "{{base}}";
+        rest = rest[start + "http://127.0.0.1:".len()..]
+            .trim_start_matches(|x: char| x.is_ascii_digit());
+    }
+    normalized += rest;
+    normalized
+}
+
+fn snapshot(export_dir: &Path, key_files: &[&str]) -> String {
+    let mut files = walkdir(export_dir)
+        .into_iter()
+        .map(|x| {
+            x.strip_prefix(export_dir)
+                .unwrap()
+                .to_string_lossy()
+                .replace('\\', "/")
+        })
+        .collect::<Vec<_>>();
+    files.sort();
+    let mut snapshot = files.join("\n");
+    snapshot.push('\n');
+    for file in key_files {
+        let contents = std::fs::read_to_string(export_dir.join(file))
+            .unwrap_or_else(|_| panic!("{file} is missing"));
+        snapshot += &format!("\n--- {file}\n{}\n", normalize(contents.trim_end()));
+    }
+    snapshot
+}
+
+// UPDATE_SNAPSHOTS=1 rewrites the snapshot after an intended change of the layout
+fn assert_snapshot(name: &str, actual: &str) {
+    let path = Path::new(SNAPSHOTS_DIR).join(format!("{name}.txt"));
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        std::fs::create_dir_all(SNAPSHOTS_DIR).unwrap();
+        std::fs::write(&path, actual).unwrap();
+        return;
+    }
+    let expected = std::fs::read_to_string(&path)
+        .unwrap_or_else(|_| panic!("{path:?} is missing, run with UPDATE_SNAPSHOTS=1"));
+    if expected != actual {
+        let diff = expected
+            .lines()
+            .zip(actual.lines())
+            .position(|(expected, actual)| expected != actual)
+            .unwrap_or(expected.lines().count().min(actual.lines().count()));
+        panic!(
+            "{name} differs from {path:?} at line {}:\n  expected: {:?}\n  actual:   {:?}\nrun with UPDATE_SNAPSHOTS=1 if the change is intended",
+            diff + 1,
+            expected.lines().nth(diff).unwrap_or_default(),
+            actual.lines().nth(diff).unwrap_or_default()
+        );
+    }
+}
+
+#[tokio::test]
+async fn title_layout() {
+    let dir = export(&[]).await;
+    let export_dir = dir.path().join("export");
+    assert_snapshot("title_layout", &snapshot(&export_dir, KEY_FILES));
+}
+
+#[tokio::test]
+async fn id_layout() {
+    let dir = export(&["--layout", "id"]).await;
+    let export_dir = dir.path().join("export");
+    let key_files = [
+        KEY_FILES,
+        &[
+            "courses/5/files/folder_index.json",
+            "courses/5/files/index.md",
+            "courses/5/files/2/submission_status.json",
+        ],
+    ]
+    .concat();
+    assert_snapshot("id_layout", &snapshot(&export_dir, &key_files));
+}